@@ -0,0 +1,317 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_client::rpc_response::RpcKeyedAccount;
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountFilter {
+    Memcmp { offset: usize, bytes: Vec<u8> },
+    DataSize(u64),
+}
+
+impl AccountFilter {
+    pub fn matches(&self, data: &[u8]) -> bool {
+        match self {
+            AccountFilter::Memcmp { offset, bytes } => {
+                data.get(*offset..*offset + bytes.len()) == Some(bytes.as_slice())
+            }
+            AccountFilter::DataSize(size) => data.len() as u64 == *size,
+        }
+    }
+}
+
+// Where discovery reads accounts from: the RPC node in production, fixture dumps or
+// in-memory accounts offline.
+#[async_trait]
+pub trait AccountSource: Send + Sync {
+    async fn get_program_accounts(&self, program_id: &Pubkey, filters: &[AccountFilter]) -> Result<Vec<(Pubkey, Account)>>;
+
+    async fn get_multiple_accounts(&self, addresses: &[Pubkey]) -> Result<Vec<Option<Account>>>;
+
+    // Slot the accounts are at least as recent as.
+    async fn get_slot(&self) -> Result<u64>;
+}
+
+pub fn program_accounts_config(filters: &[AccountFilter]) -> RpcProgramAccountsConfig {
+    let filters = filters
+        .iter()
+        .map(|filter| match filter {
+            AccountFilter::Memcmp { offset, bytes } => {
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(*offset, bytes))
+            }
+            AccountFilter::DataSize(size) => RpcFilterType::DataSize(*size),
+        })
+        .collect::<Vec<_>>();
+
+    RpcProgramAccountsConfig {
+        filters: (!filters.is_empty()).then_some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            data_slice: None,
+            min_context_slot: None,
+        },
+        with_context: Some(true),
+    }
+}
+
+#[async_trait]
+impl AccountSource for RpcClient {
+    async fn get_program_accounts(&self, program_id: &Pubkey, filters: &[AccountFilter]) -> Result<Vec<(Pubkey, Account)>> {
+        Ok(self.get_program_accounts_with_config(program_id, program_accounts_config(filters)).await?)
+    }
+
+    async fn get_multiple_accounts(&self, addresses: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(RpcClient::get_multiple_accounts(self, addresses).await?)
+    }
+
+    async fn get_slot(&self) -> Result<u64> {
+        Ok(RpcClient::get_slot(self).await?)
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryAccountSource {
+    accounts: RwLock<HashMap<Pubkey, Account>>,
+    slot: AtomicU64,
+}
+
+impl InMemoryAccountSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(test)]
+    pub fn from_accounts(accounts: impl IntoIterator<Item = (Pubkey, Account)>) -> Self {
+        InMemoryAccountSource {
+            accounts: RwLock::new(accounts.into_iter().collect()),
+            slot: AtomicU64::new(0),
+        }
+    }
+
+    pub fn insert(&self, address: Pubkey, account: Account) {
+        self.accounts.write().unwrap().insert(address, account);
+    }
+
+    #[cfg(test)]
+    pub fn set_slot(&self, slot: u64) {
+        self.slot.store(slot, Ordering::Relaxed);
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.read().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl AccountSource for InMemoryAccountSource {
+    async fn get_program_accounts(&self, program_id: &Pubkey, filters: &[AccountFilter]) -> Result<Vec<(Pubkey, Account)>> {
+        let mut accounts: Vec<(Pubkey, Account)> = self
+            .accounts
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, account)| {
+                account.owner == *program_id && filters.iter().all(|filter| filter.matches(&account.data))
+            })
+            .map(|(address, account)| (*address, account.clone()))
+            .collect();
+        accounts.sort_by_key(|(address, _)| *address);
+        Ok(accounts)
+    }
+
+    async fn get_multiple_accounts(&self, addresses: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        let accounts = self.accounts.read().unwrap();
+        Ok(addresses.iter().map(|address| accounts.get(address).cloned()).collect())
+    }
+
+    async fn get_slot(&self) -> Result<u64> {
+        Ok(self.slot.load(Ordering::Relaxed))
+    }
+}
+
+// Account dumps in the JSON shape of getProgramAccounts results and `solana account
+// --output json`: either one {"pubkey", "account"} object or an array of them. A directory
+// loads every .json file in it.
+pub struct FixtureAccountSource {
+    accounts: InMemoryAccountSource,
+}
+
+impl FixtureAccountSource {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let accounts = InMemoryAccountSource::new();
+
+        let files = if path.is_dir() {
+            let mut files: Vec<_> = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.extension().is_some_and(|extension| extension == "json"))
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        for file in files {
+            let contents = std::fs::read_to_string(&file)
+                .with_context(|| format!("failed to read account dump {}", file.display()))?;
+            for (address, account) in parse_account_dump(&contents)
+                .with_context(|| format!("invalid account dump {}", file.display()))?
+            {
+                accounts.insert(address, account);
+            }
+        }
+
+        Ok(FixtureAccountSource { accounts })
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+}
+
+#[async_trait]
+impl AccountSource for FixtureAccountSource {
+    async fn get_program_accounts(&self, program_id: &Pubkey, filters: &[AccountFilter]) -> Result<Vec<(Pubkey, Account)>> {
+        self.accounts.get_program_accounts(program_id, filters).await
+    }
+
+    async fn get_multiple_accounts(&self, addresses: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        self.accounts.get_multiple_accounts(addresses).await
+    }
+
+    async fn get_slot(&self) -> Result<u64> {
+        self.accounts.get_slot().await
+    }
+}
+
+pub fn parse_account_dump(contents: &str) -> Result<Vec<(Pubkey, Account)>> {
+    let value: serde_json::Value = serde_json::from_str(contents)?;
+    let keyed: Vec<RpcKeyedAccount> = if value.is_array() {
+        serde_json::from_value(value)?
+    } else {
+        vec![serde_json::from_value(value)?]
+    };
+
+    keyed
+        .into_iter()
+        .map(|keyed| {
+            let address = Pubkey::from_str(&keyed.pubkey)
+                .map_err(|e| anyhow!("invalid pubkey {}: {}", keyed.pubkey, e))?;
+            let account = keyed
+                .account
+                .decode::<Account>()
+                .ok_or_else(|| anyhow!("could not decode account data of {}", keyed.pubkey))?;
+            Ok((address, account))
+        })
+        .collect()
+}
+
+// Writes accounts in the dump format read by FixtureAccountSource, e.g. to capture a market.
+pub fn write_account_dump(path: impl AsRef<Path>, accounts: &[(Pubkey, Account)]) -> Result<()> {
+    let keyed: Vec<RpcKeyedAccount> = accounts
+        .iter()
+        .map(|(address, account)| RpcKeyedAccount {
+            pubkey: address.to_string(),
+            account: UiAccount::encode(address, account, UiAccountEncoding::Base64, None, None),
+        })
+        .collect();
+    std::fs::write(path, serde_json::to_string_pretty(&keyed)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(owner: Pubkey, data: Vec<u8>) -> Account {
+        Account {
+            lamports: 1_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn in_memory_source_applies_owner_and_filters() {
+        let program_id = Pubkey::new_unique();
+        let first = Pubkey::new_unique();
+        let second = Pubkey::new_unique();
+        let source = InMemoryAccountSource::from_accounts([
+            (first, account(program_id, vec![1, 2, 3, 4])),
+            (second, account(program_id, vec![1, 9, 3])),
+            (Pubkey::new_unique(), account(Pubkey::new_unique(), vec![1, 2, 3, 4])),
+        ]);
+
+        assert_eq!(source.get_program_accounts(&program_id, &[]).await.unwrap().len(), 2);
+
+        let memcmp = [AccountFilter::Memcmp { offset: 1, bytes: vec![2, 3] }];
+        let matched = source.get_program_accounts(&program_id, &memcmp).await.unwrap();
+        assert_eq!(matched.iter().map(|(address, _)| *address).collect::<Vec<_>>(), vec![first]);
+
+        let size = [AccountFilter::DataSize(3)];
+        let matched = source.get_program_accounts(&program_id, &size).await.unwrap();
+        assert_eq!(matched.iter().map(|(address, _)| *address).collect::<Vec<_>>(), vec![second]);
+
+        let missing = Pubkey::new_unique();
+        let fetched = source.get_multiple_accounts(&[second, missing]).await.unwrap();
+        assert_eq!(fetched[0].as_ref().unwrap().data, vec![1, 9, 3]);
+        assert!(fetched[1].is_none());
+    }
+
+    #[test]
+    fn parses_solana_cli_account_output() {
+        let dump = r#"{
+            "pubkey": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+            "account": {
+                "lamports": 388127047454,
+                "data": ["AQIDBA==", "base64"],
+                "owner": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+                "executable": false,
+                "rentEpoch": 18446744073709551615,
+                "space": 4
+            }
+        }"#;
+
+        let accounts = parse_account_dump(dump).unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].0.to_string(), "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+        assert_eq!(accounts[0].1.data, vec![1, 2, 3, 4]);
+        assert_eq!(accounts[0].1.owner.to_string(), "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+    }
+
+    #[tokio::test]
+    async fn fixture_source_round_trips_written_dumps() {
+        let program_id = Pubkey::new_unique();
+        let accounts = [
+            (Pubkey::new_unique(), account(program_id, vec![7; 64])),
+            (Pubkey::new_unique(), account(program_id, vec![8; 32])),
+        ];
+        let dir = std::env::temp_dir().join(format!("account-dump-{}", Pubkey::new_unique()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_account_dump(dir.join("first.json"), &accounts[..1]).unwrap();
+        write_account_dump(dir.join("second.json"), &accounts[1..]).unwrap();
+
+        let source = FixtureAccountSource::load(&dir).unwrap();
+        assert_eq!(source.len(), 2);
+        let fetched = source
+            .get_multiple_accounts(&[accounts[0].0, accounts[1].0])
+            .await
+            .unwrap();
+        assert_eq!(fetched[0].as_ref(), Some(&accounts[0].1));
+        assert_eq!(fetched[1].as_ref(), Some(&accounts[1].1));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,534 @@
+use crate::deleverage::evaluate_deleverage;
+use crate::health::{compute_health, ObligationHealth};
+use crate::kamino::{Obligation, Reserve};
+use crate::klend_listener::account_discriminator;
+use crate::obligation_store::ObligationStore;
+use crate::orders::evaluate_orders;
+use crate::planner::{plan_obligation, Opportunity, OpportunityKind, PlannerConfig};
+use crate::price_guard::{quote_price_at, PriceGuardConfig};
+use crate::price_listener::{parse_real_pyth_price_account, time_weighted_average, PriceSample, TokenPrice};
+use crate::recorder::read_recordings;
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use borsh::BorshDeserialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::str::FromStr;
+use tracing::{info, warn};
+
+pub const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+const REPLAY_PRICE_HISTORY: usize = 2_048;
+
+// One recorded account write, as delivered by Geyser.
+#[derive(Debug, Clone)]
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub slot: u64,
+    pub write_version: u64,
+    pub timestamp_ms: i64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureUpdate {
+    pub pubkey: String,
+    pub owner: String,
+    pub slot: u64,
+    #[serde(default)]
+    pub write_version: u64,
+    pub timestamp_ms: i64,
+    // base64 encoded account data
+    pub data: String,
+}
+
+// Offline fixture: account updates plus the Pyth price account -> token mint mapping
+// needed to decode oracle accounts.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BacktestFixture {
+    pub program_id: String,
+    pub oracles: HashMap<String, String>,
+    #[serde(default)]
+    pub updates: Vec<FixtureUpdate>,
+    // Optional recording file or directory replayed alongside the inline updates.
+    #[serde(default)]
+    pub recording: Option<String>,
+}
+
+impl BacktestFixture {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read fixture {}", path.display()))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn account_updates(&self) -> Result<Vec<AccountUpdate>> {
+        let mut updates = self.inline_updates()?;
+        if let Some(recording) = &self.recording {
+            updates.extend(read_recordings(recording)?.into_iter().map(AccountUpdate::from));
+        }
+        Ok(updates)
+    }
+
+    fn inline_updates(&self) -> Result<Vec<AccountUpdate>> {
+        self.updates
+            .iter()
+            .map(|update| {
+                Ok(AccountUpdate {
+                    pubkey: parse_pubkey(&update.pubkey)?,
+                    owner: parse_pubkey(&update.owner)?,
+                    slot: update.slot,
+                    write_version: update.write_version,
+                    timestamp_ms: update.timestamp_ms,
+                    data: base64::engine::general_purpose::STANDARD.decode(&update.data)?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn oracle_mints(&self) -> Result<HashMap<Pubkey, String>> {
+        self.oracles
+            .iter()
+            .map(|(account, mint)| Ok((parse_pubkey(account)?, mint.clone())))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktestConfig {
+    pub latency_slots: u64,
+    pub fee_lamports: u64,
+    pub slippage_bps: u64,
+    pub sol_price_usd: f64,
+    pub planner_config: PlannerConfig,
+    pub price_guard_config: PriceGuardConfig,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        BacktestConfig {
+            latency_slots: 2,
+            fee_lamports: 1_000_000,
+            slippage_bps: 30,
+            sol_price_usd: 150.0,
+            planner_config: PlannerConfig::default(),
+            price_guard_config: PriceGuardConfig::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TradeOutcome {
+    Captured,
+    Recovered,
+    TakenByOther,
+    Unprofitable,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TradeRecord {
+    pub obligation: String,
+    pub kind: OpportunityKind,
+    pub detected_slot: u64,
+    pub execution_slot: u64,
+    pub expected_profit: f64,
+    pub realized_profit: f64,
+    pub fee_usd: f64,
+    pub slippage_usd: f64,
+    pub outcome: TradeOutcome,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BacktestReport {
+    pub updates_processed: usize,
+    pub decode_failures: usize,
+    pub first_slot: u64,
+    pub last_slot: u64,
+    pub opportunities_detected: usize,
+    pub captured: usize,
+    pub missed: usize,
+    pub missed_by_outcome: BTreeMap<String, usize>,
+    pub gross_profit: f64,
+    pub fees: f64,
+    pub pnl: f64,
+    pub trades: Vec<TradeRecord>,
+}
+
+struct PendingExecution {
+    opportunity: Opportunity,
+    detected_slot: u64,
+    execution_slot: u64,
+}
+
+// Replays account updates in slot order through the live decoders, health engine and planner,
+// and simulates acting on each opportunity `latency_slots` later against the state at that point.
+pub struct Backtester {
+    config: BacktestConfig,
+    program_id: Pubkey,
+    oracle_mints: HashMap<Pubkey, String>,
+    reserve_discriminator: [u8; 8],
+    obligation_discriminator: [u8; 8],
+    store: ObligationStore,
+    reserves: HashMap<Pubkey, Reserve>,
+    prices: HashMap<String, TokenPrice>,
+    price_history: HashMap<String, VecDeque<PriceSample>>,
+    obligation_slots: HashMap<Pubkey, u64>,
+    pending: Vec<PendingExecution>,
+    in_flight: HashSet<(Pubkey, String)>,
+    current_slot: u64,
+    now: DateTime<Utc>,
+    report: BacktestReport,
+}
+
+impl Backtester {
+    pub fn new(config: BacktestConfig, program_id: Pubkey, oracle_mints: HashMap<Pubkey, String>) -> Self {
+        Backtester {
+            config,
+            program_id,
+            oracle_mints,
+            reserve_discriminator: account_discriminator("Reserve"),
+            obligation_discriminator: account_discriminator("Obligation"),
+            store: ObligationStore::new(),
+            reserves: HashMap::new(),
+            prices: HashMap::new(),
+            price_history: HashMap::new(),
+            obligation_slots: HashMap::new(),
+            pending: Vec::new(),
+            in_flight: HashSet::new(),
+            current_slot: 0,
+            now: DateTime::<Utc>::UNIX_EPOCH,
+            report: BacktestReport::default(),
+        }
+    }
+
+    pub fn from_fixture(config: BacktestConfig, fixture: &BacktestFixture) -> Result<Self> {
+        Ok(Backtester::new(config, parse_pubkey(&fixture.program_id)?, fixture.oracle_mints()?))
+    }
+
+    pub fn run(mut self, mut updates: Vec<AccountUpdate>) -> BacktestReport {
+        updates.sort_by_key(|update| (update.slot, update.write_version));
+        self.report.first_slot = updates.first().map(|update| update.slot).unwrap_or_default();
+
+        for update in updates {
+            if update.slot > self.current_slot {
+                self.settle(update.slot);
+            }
+            self.current_slot = update.slot;
+            self.now = DateTime::from_timestamp_millis(update.timestamp_ms).unwrap_or(self.now);
+            self.report.updates_processed += 1;
+
+            let affected = self.apply(update);
+            self.detect(affected);
+        }
+
+        self.settle(u64::MAX);
+        self.report.last_slot = self.current_slot;
+        self.report.pnl = self.report.gross_profit - self.report.fees;
+        self.report
+    }
+
+    fn apply(&mut self, update: AccountUpdate) -> Vec<Pubkey> {
+        if let Some(mint) = self.oracle_mints.get(&update.pubkey).cloned() {
+            return self.apply_price(&mint, &update);
+        }
+        if update.owner != self.program_id || update.data.len() <= 8 {
+            return Vec::new();
+        }
+
+        let discriminator = &update.data[..8];
+        if discriminator == self.reserve_discriminator {
+            match Reserve::try_from_account_data(&update.data) {
+                Some(reserve) => {
+                    self.store.set_reserve_mint(update.pubkey, reserve.mint().to_string());
+                    self.reserves.insert(update.pubkey, reserve);
+                    self.store.obligations_for_reserve(&update.pubkey)
+                }
+                None => self.decode_failed("reserve", &update),
+            }
+        } else if discriminator == self.obligation_discriminator {
+            match Obligation::try_from_slice(&update.data[8..]) {
+                Ok(obligation) => {
+                    self.obligation_slots.insert(update.pubkey, update.slot);
+                    self.in_flight.retain(|(address, _)| *address != update.pubkey);
+                    if obligation.has_active_borrows() {
+                        self.store.insert(update.pubkey, obligation);
+                        vec![update.pubkey]
+                    } else {
+                        self.store.remove(&update.pubkey);
+                        Vec::new()
+                    }
+                }
+                Err(_) => self.decode_failed("obligation", &update),
+            }
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn apply_price(&mut self, mint: &str, update: &AccountUpdate) -> Vec<Pubkey> {
+        let mut price = match parse_real_pyth_price_account(&update.data, mint) {
+            Some(price) => price,
+            None => return self.decode_failed("price", update),
+        };
+        price.last_updated = self.now;
+
+        if price.price > 0.0 {
+            let history = self.price_history.entry(mint.to_string()).or_default();
+            if history.len() == REPLAY_PRICE_HISTORY {
+                history.pop_front();
+            }
+            history.push_back(PriceSample {
+                slot: price.publish_slot,
+                publish_time: price.publish_time.unwrap_or(self.now),
+                price: price.price,
+                confidence: price.confidence,
+            });
+        }
+        self.prices.insert(mint.to_string(), price);
+        self.store.obligations_for_mint(mint)
+    }
+
+    fn decode_failed(&mut self, kind: &str, update: &AccountUpdate) -> Vec<Pubkey> {
+        warn!("Failed to decode {} {} at slot {}", kind, update.pubkey, update.slot);
+        self.report.decode_failures += 1;
+        Vec::new()
+    }
+
+    fn health(&self, obligation: &Obligation) -> ObligationHealth {
+        compute_health(obligation, &self.reserves, |reserve| {
+            let mint = reserve.mint().to_string();
+            let twap = self
+                .price_history
+                .get(&mint)
+                .and_then(|samples| {
+                    time_weighted_average(samples, self.now, self.config.price_guard_config.twap_window)
+                });
+            quote_price_at(
+                reserve,
+                self.prices.get(&mint),
+                twap,
+                &self.config.price_guard_config,
+                self.now,
+                Some(self.current_slot),
+            )
+        })
+    }
+
+    fn plan(&self, address: &Pubkey) -> Vec<Opportunity> {
+        let obligation = match self.store.get(address) {
+            Some(obligation) => obligation,
+            None => return Vec::new(),
+        };
+        let health = self.health(&obligation);
+        let orders = evaluate_orders(&obligation, &health);
+        let deleverage = evaluate_deleverage(&obligation, &health, &self.reserves, self.now.timestamp().max(0) as u64);
+        plan_obligation(
+            address,
+            &health,
+            &orders,
+            deleverage.as_ref(),
+            &self.reserves,
+            &self.config.planner_config,
+        )
+    }
+
+    fn detect(&mut self, affected: Vec<Pubkey>) {
+        for address in affected {
+            for opportunity in self.plan(&address) {
+                let key = (address, format!("{:?}", opportunity.kind));
+                if !self.in_flight.insert(key) {
+                    continue;
+                }
+                self.report.opportunities_detected += 1;
+                self.pending.push(PendingExecution {
+                    opportunity,
+                    detected_slot: self.current_slot,
+                    execution_slot: self.current_slot + self.config.latency_slots,
+                });
+            }
+        }
+    }
+
+    // Lands every pending execution due before `slot`, against the state replayed so far.
+    fn settle(&mut self, slot: u64) {
+        let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|execution| execution.execution_slot < slot);
+        self.pending = pending;
+
+        for execution in due {
+            let record = self.execute(&execution);
+            if record.outcome == TradeOutcome::Captured {
+                self.report.captured += 1;
+                self.report.gross_profit += record.realized_profit + record.fee_usd;
+                self.report.fees += record.fee_usd;
+            } else {
+                self.report.missed += 1;
+                self.in_flight.remove(&(execution.opportunity.obligation, format!("{:?}", execution.opportunity.kind)));
+                *self
+                    .report
+                    .missed_by_outcome
+                    .entry(format!("{:?}", record.outcome))
+                    .or_default() += 1;
+            }
+            self.report.trades.push(record);
+        }
+    }
+
+    fn execute(&self, execution: &PendingExecution) -> TradeRecord {
+        let planned = &execution.opportunity;
+        let fee_usd = self.config.fee_lamports as f64 / 1e9 * self.sol_price();
+        let changed = self
+            .obligation_slots
+            .get(&planned.obligation)
+            .is_some_and(|slot| *slot > execution.detected_slot);
+
+        let current = self
+            .plan(&planned.obligation)
+            .into_iter()
+            .find(|opportunity| opportunity.kind == planned.kind);
+
+        let (outcome, realized_profit, slippage_usd) = match current {
+            None if changed => (TradeOutcome::TakenByOther, 0.0, 0.0),
+            None => (TradeOutcome::Recovered, 0.0, 0.0),
+            Some(opportunity) => {
+                let slippage_usd = opportunity.repay_value * (1.0 + opportunity.bonus_rate)
+                    * self.config.slippage_bps as f64
+                    / 10_000.0;
+                let net = opportunity.expected_profit - slippage_usd - fee_usd;
+                if net > 0.0 {
+                    (TradeOutcome::Captured, net, slippage_usd)
+                } else {
+                    (TradeOutcome::Unprofitable, 0.0, slippage_usd)
+                }
+            }
+        };
+
+        TradeRecord {
+            obligation: planned.obligation.to_string(),
+            kind: planned.kind.clone(),
+            detected_slot: execution.detected_slot,
+            execution_slot: execution.execution_slot,
+            expected_profit: planned.expected_profit,
+            realized_profit,
+            fee_usd: if outcome == TradeOutcome::Captured { fee_usd } else { 0.0 },
+            slippage_usd,
+            outcome,
+        }
+    }
+
+    fn sol_price(&self) -> f64 {
+        self.prices
+            .get(SOL_MINT)
+            .map(|price| price.price)
+            .filter(|price| *price > 0.0)
+            .unwrap_or(self.config.sol_price_usd)
+    }
+}
+
+pub fn run_fixture(path: impl AsRef<Path>, config: BacktestConfig) -> Result<BacktestReport> {
+    let fixture = BacktestFixture::load(path)?;
+    let updates = fixture.account_updates()?;
+    info!("Replaying {} account updates", updates.len());
+
+    let report = Backtester::from_fixture(config, &fixture)?.run(updates);
+    info!(
+        "Backtest slots {}..{}: {} opportunities, {} captured, {} missed, PnL ${:.2} (gross ${:.2}, fees ${:.2})",
+        report.first_slot, report.last_slot, report.opportunities_detected, report.captured,
+        report.missed, report.pnl, report.gross_profit, report.fees
+    );
+    Ok(report)
+}
+
+fn parse_pubkey(value: &str) -> Result<Pubkey> {
+    Pubkey::from_str(value).map_err(|e| anyhow!("invalid pubkey {} in fixture: {}", value, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/backtest/liquidations.json");
+
+    fn run(latency_slots: u64, fee_lamports: u64) -> BacktestReport {
+        let config = BacktestConfig {
+            latency_slots,
+            fee_lamports,
+            ..BacktestConfig::default()
+        };
+        run_fixture(FIXTURE, config).unwrap()
+    }
+
+    fn outcomes(report: &BacktestReport) -> Vec<(String, u64, TradeOutcome)> {
+        let mut outcomes: Vec<_> = report
+            .trades
+            .iter()
+            .map(|trade| (trade.obligation.clone(), trade.execution_slot, trade.outcome))
+            .collect();
+        outcomes.sort_by(|a, b| a.0.cmp(&b.0));
+        outcomes
+    }
+
+    // Both borrowers hold 10 SOL against 700 and 720 USDC; SOL falls from $100 to $80 at slot
+    // 110, leaving them at health 640/700 and 640/720. At slot 111 someone else repays 144
+    // USDC of borrower b's debt.
+    #[test]
+    fn finds_the_liquidations_in_the_fixture() {
+        let report = run(0, 0);
+        assert_eq!(report.updates_processed, 6);
+        assert_eq!(report.decode_failures, 0);
+        assert_eq!((report.first_slot, report.last_slot), (100, 111));
+        assert_eq!(report.opportunities_detected, 2);
+        assert_eq!((report.captured, report.missed), (2, 0));
+
+        // Close factor 0.2: a repays 140 USDC at a bonus of its LTV 0.875 less the liquidation
+        // LTV 0.8, b 144 USDC at 0.9 - 0.8, the 10% maximum. Slippage is 30bps of the seized
+        // collateral.
+        let a = report.trades.iter().find(|trade| (trade.expected_profit - 10.5).abs() < 1e-6).unwrap();
+        let b = report.trades.iter().find(|trade| (trade.expected_profit - 14.4).abs() < 1e-6).unwrap();
+        assert!(a.kind == OpportunityKind::Liquidation && b.kind == OpportunityKind::Liquidation);
+        assert!((a.slippage_usd - 140.0 * 1.075 * 0.003).abs() < 1e-6);
+        assert!((a.realized_profit - (10.5 - 0.4515)).abs() < 1e-6);
+        assert!((b.realized_profit - (14.4 - 0.4752)).abs() < 1e-6);
+        assert!((report.pnl - (10.0485 + 13.9248)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn latency_lets_another_liquidator_take_the_opportunity() {
+        let immediate = run(0, 0);
+        let delayed = run(2, 0);
+
+        let slots = |report: &BacktestReport| -> Vec<u64> {
+            outcomes(report).into_iter().map(|(_, slot, _)| slot).collect()
+        };
+        assert_eq!(slots(&immediate), vec![110, 110]);
+        assert_eq!(slots(&delayed), vec![112, 112]);
+
+        // Borrower b's debt is repaid at slot 111, before the delayed execution lands.
+        assert_eq!((delayed.captured, delayed.missed), (1, 1));
+        assert_eq!(delayed.missed_by_outcome.get("TakenByOther"), Some(&1));
+        assert!((delayed.pnl - 10.0485).abs() < 1e-6);
+    }
+
+    #[test]
+    fn subtracts_fees_from_each_captured_trade() {
+        let free = run(0, 0);
+        // 0.001 SOL at the configured $150 SOL price.
+        let paid = run(0, 1_000_000);
+        assert_eq!(paid.captured, 2);
+        assert!((paid.fees - 0.3).abs() < 1e-9);
+        assert!((free.pnl - paid.pnl - 0.3).abs() < 1e-9);
+        assert!((paid.gross_profit - free.gross_profit).abs() < 1e-9);
+        assert!(paid.trades.iter().all(|trade| (trade.fee_usd - 0.15).abs() < 1e-9));
+
+        // A fee above the profit makes both trades unprofitable.
+        let expensive = run(0, 100 * 1_000_000_000);
+        assert_eq!((expensive.captured, expensive.missed), (0, 2));
+        assert_eq!(expensive.missed_by_outcome.get("Unprofitable"), Some(&2));
+        assert_eq!(expensive.pnl, 0.0);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::price_listener::{run_listener, Listener};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use solana_sdk::hash::Hash;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+use yellowstone_grpc_proto::geyser::{
+    subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
+    SubscribeRequestFilterBlocksMeta, SubscribeRequestFilterSlots,
+};
+
+pub const DEFAULT_MAX_BLOCKHASH_SLOT_AGE: u64 = 100;
+const SLOT_DURATION_MS: u64 = 400;
+// The wall-clock twin of DEFAULT_MAX_BLOCKHASH_SLOT_AGE (100 slots, ~40s): a stalled stream
+// stops advancing current_slot, so the slot age alone cannot tell that the cached hash expired.
+pub const MAX_BLOCKHASH_AGE: Duration =
+    Duration::from_millis(DEFAULT_MAX_BLOCKHASH_SLOT_AGE * SLOT_DURATION_MS);
+
+#[derive(Debug, Clone)]
+pub struct AppState {
+    pub latest_block_hash: String,
+    pub slot: u64,
+    pub current_slot: u64,
+    pub received_at: Instant,
+}
+
+pub static SHARED_STATE: Lazy<Arc<DashMap<(), AppState>>> =
+    Lazy::new(|| Arc::new(DashMap::with_capacity(1)));
+
+pub fn current_slot() -> Option<u64> {
+    SHARED_STATE.get(&()).map(|state| state.current_slot)
+}
+
+pub fn get_cached_blockhash(max_slot_age: u64) -> Option<(Hash, u64)> {
+    let state = SHARED_STATE.get(&())?;
+    fresh_blockhash(&state, max_slot_age, MAX_BLOCKHASH_AGE)
+}
+
+// Block metas can arrive out of order; an older one never replaces a newer hash or moves the
+// current slot back.
+fn apply_block_meta(state: &mut AppState, blockhash: String, slot: u64) {
+    if slot > state.slot || state.latest_block_hash.is_empty() {
+        state.latest_block_hash = blockhash;
+        state.slot = slot;
+        state.received_at = Instant::now();
+    }
+    state.current_slot = state.current_slot.max(slot);
+}
+
+fn empty_state() -> AppState {
+    AppState {
+        latest_block_hash: String::new(),
+        slot: 0,
+        current_slot: 0,
+        received_at: Instant::now(),
+    }
+}
+
+fn fresh_blockhash(state: &AppState, max_slot_age: u64, max_age: Duration) -> Option<(Hash, u64)> {
+    if state.latest_block_hash.is_empty() {
+        return None;
+    }
+    let slot_age = state.current_slot.saturating_sub(state.slot);
+    if slot_age > max_slot_age {
+        debug!(
+            "Cached blockhash from slot {} is stale ({} slots old)",
+            state.slot, slot_age
+        );
+        return None;
+    }
+
+    let age = state.received_at.elapsed();
+    if age > max_age {
+        debug!(
+            "Cached blockhash from slot {} is stale (received {:?} ago)",
+            state.slot, age
+        );
+        return None;
+    }
+
+    match Hash::from_str(&state.latest_block_hash) {
+        Ok(hash) => Some((hash, state.slot)),
+        Err(e) => {
+            warn!("Invalid cached blockhash {}: {:?}", state.latest_block_hash, e);
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BlockHashListener;
+
+impl BlockHashListener {
+    pub fn new() -> Self {
+        BlockHashListener
+    }
+
+    pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            run_listener(self).await;
+        })
+    }
+}
+
+impl Listener for BlockHashListener {
+    fn get_subscription_request(&self) -> SubscribeRequest {
+        let mut blocks_meta_map = HashMap::new();
+        blocks_meta_map.insert(
+            "blockmetadata".to_string(),
+            SubscribeRequestFilterBlocksMeta::default(),
+        );
+
+        let mut slots_map = HashMap::new();
+        slots_map.insert(
+            "slots".to_string(),
+            SubscribeRequestFilterSlots {
+                filter_by_commitment: Some(false),
+            },
+        );
+
+        SubscribeRequest {
+            slots: slots_map,
+            blocks_meta: blocks_meta_map,
+            commitment: Some(CommitmentLevel::Confirmed as i32),
+            ..Default::default()
+        }
+    }
+
+    fn handle_update(&self, update: UpdateOneof) {
+        match update {
+            UpdateOneof::BlockMeta(block_update) => {
+                debug!("Block hash: {} (slot {})", block_update.blockhash, block_update.slot);
+                let mut state = SHARED_STATE.entry(()).or_insert_with(empty_state);
+                apply_block_meta(&mut state, block_update.blockhash, block_update.slot);
+            }
+            // Over the websocket transport only slots arrive: the current slot is still tracked,
+            // but the blockhash stays empty and transactions fetch theirs over RPC.
+            UpdateOneof::Slot(slot_update) => {
+                let mut state = SHARED_STATE.entry(()).or_insert_with(empty_state);
+                if slot_update.slot > state.current_slot {
+                    state.current_slot = slot_update.slot;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn name(&self) -> String {
+        "BlockHashListener".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(slot: u64, current_slot: u64, age: Duration) -> AppState {
+        AppState {
+            latest_block_hash: Hash::new_unique().to_string(),
+            slot,
+            current_slot,
+            received_at: Instant::now() - age,
+        }
+    }
+
+    #[test]
+    fn rejects_blockhashes_past_the_slot_or_wall_clock_bound() {
+        let fresh = state(500, 550, Duration::from_secs(1));
+        assert_eq!(fresh_blockhash(&fresh, 100, MAX_BLOCKHASH_AGE).map(|(_, slot)| slot), Some(500));
+        assert!(fresh_blockhash(&state(500, 601, Duration::ZERO), 100, MAX_BLOCKHASH_AGE).is_none());
+
+        // The stream stalled: no slot updates arrived, but the hash is long expired.
+        let stalled = state(500, 500, MAX_BLOCKHASH_AGE + Duration::from_secs(1));
+        assert!(fresh_blockhash(&stalled, 100, MAX_BLOCKHASH_AGE).is_none());
+
+        // Only slots arrived, as over the websocket transport.
+        let slots_only = AppState { latest_block_hash: String::new(), ..state(0, 550, Duration::ZERO) };
+        assert!(fresh_blockhash(&slots_only, 1_000, MAX_BLOCKHASH_AGE).is_none());
+    }
+
+    #[test]
+    fn block_metas_only_move_forward() {
+        let mut state = empty_state();
+        apply_block_meta(&mut state, "newer".to_string(), 500);
+        state.current_slot = 505;
+
+        // A late meta for an older block keeps the newer hash and current slot.
+        apply_block_meta(&mut state, "older".to_string(), 480);
+        assert_eq!((state.latest_block_hash.as_str(), state.slot, state.current_slot), ("newer", 500, 505));
+
+        apply_block_meta(&mut state, "next".to_string(), 510);
+        assert_eq!((state.latest_block_hash.as_str(), state.slot, state.current_slot), ("next", 510, 510));
+    }
+}
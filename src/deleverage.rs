@@ -0,0 +1,210 @@
+use crate::health::ObligationHealth;
+use crate::kamino::{Obligation, Reserve};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeleverageStatus {
+    pub margin_call_started_at: u64,
+    pub grace_period_secs: u64,
+    pub grace_period_expires_at: u64,
+    pub seconds_until_expiry: i64,
+    pub grace_period_expired: bool,
+    pub target_ltv: f64,
+    pub current_ltv: f64,
+    pub effective_liquidation_ltv: f64,
+    pub bonus_rate: f64,
+    pub repay_reserve: String,
+    pub withdraw_reserve: String,
+    pub deleverageable_value: f64,
+    pub deleverageable_amount: f64,
+}
+
+impl DeleverageStatus {
+    pub fn is_actionable(&self) -> bool {
+        self.grace_period_expired && self.deleverageable_value > 0.0
+    }
+}
+
+pub fn evaluate_deleverage(
+    obligation: &Obligation,
+    health: &ObligationHealth,
+    reserves: &HashMap<Pubkey, Reserve>,
+    now: u64,
+) -> Option<DeleverageStatus> {
+    if !obligation.is_marked_for_deleveraging() {
+        return None;
+    }
+
+    let repay = health.largest_borrow()?;
+    let withdraw = health.largest_deposit()?;
+    let reserve = reserves.get(&Pubkey::from_str(&withdraw.reserve_address).ok()?)?;
+    let config = &reserve.config;
+
+    let started_at = obligation.autodeleverage_margin_call_started_timestamp;
+    let grace_period_secs = config.deleveraging_margin_call_period_secs;
+    let expires_at = started_at.saturating_add(grace_period_secs);
+    let grace_period_expired = now >= expires_at;
+    let days_past_expiry = now.saturating_sub(expires_at) as f64 / SECONDS_PER_DAY;
+
+    let min_bonus = config.min_liquidation_bonus_bps as f64 / 10_000.0;
+    let max_bonus = config.max_liquidation_bonus_bps.max(config.min_liquidation_bonus_bps) as f64 / 10_000.0;
+    let bonus_rate = if grace_period_expired {
+        (min_bonus + days_past_expiry * config.deleveraging_bonus_increase_bps_per_day as f64 / 10_000.0)
+            .min(max_bonus)
+    } else {
+        0.0
+    };
+
+    let effective_liquidation_ltv = if grace_period_expired {
+        (health.liquidation_ltv
+            - days_past_expiry * config.deleveraging_threshold_decrease_bps_per_day as f64 / 10_000.0)
+            .max(0.0)
+    } else {
+        health.liquidation_ltv
+    };
+
+    let target_ltv = obligation.autodeleverage_target_ltv_pct as f64 / 100.0;
+    let deleverageable_value = debt_to_reach_target(
+        health.bf_adjusted_debt_value,
+        health.deposited_value,
+        target_ltv,
+        bonus_rate,
+    )
+    .min(repay.value)
+    .min(withdraw.value / (1.0 + bonus_rate));
+
+    Some(DeleverageStatus {
+        margin_call_started_at: started_at,
+        grace_period_secs,
+        grace_period_expires_at: expires_at,
+        seconds_until_expiry: expires_at as i64 - now as i64,
+        grace_period_expired,
+        target_ltv,
+        current_ltv: health.ltv,
+        effective_liquidation_ltv,
+        bonus_rate,
+        repay_reserve: repay.reserve_address.clone(),
+        withdraw_reserve: withdraw.reserve_address.clone(),
+        deleverageable_value,
+        deleverageable_amount: if repay.price > 0.0 { deleverageable_value / repay.price } else { 0.0 },
+    })
+}
+
+// Repaying x of debt releases x * (1 + bonus) of collateral, so solve
+// (debt - x) / (collateral - x * (1 + bonus)) = target for x.
+pub fn debt_to_reach_target(debt: f64, collateral: f64, target_ltv: f64, bonus_rate: f64) -> f64 {
+    if debt <= 0.0 || collateral <= 0.0 || debt / collateral <= target_ltv {
+        return 0.0;
+    }
+
+    let denominator = 1.0 - target_ltv * (1.0 + bonus_rate);
+    if denominator <= 0.0 {
+        return debt;
+    }
+
+    ((debt - target_ltv * collateral) / denominator).clamp(0.0, debt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{position, zeroed};
+
+    const STARTED_AT: u64 = 1_700_000_000;
+    const GRACE_PERIOD_SECS: u64 = 86_400;
+
+    // $1000 of collateral against $700 of debt, marked for deleveraging to 50% LTV with a day
+    // of grace. After it, the bonus grows 1% a day from 2% up to 10% and the liquidation LTV
+    // falls 0.5% a day.
+    fn marked() -> (Obligation, ObligationHealth, HashMap<Pubkey, Reserve>) {
+        let (collateral, debt) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut reserve = zeroed::<Reserve>();
+        reserve.config.min_liquidation_bonus_bps = 200;
+        reserve.config.max_liquidation_bonus_bps = 1_000;
+        reserve.config.deleveraging_margin_call_period_secs = GRACE_PERIOD_SECS;
+        reserve.config.deleveraging_bonus_increase_bps_per_day = 100;
+        reserve.config.deleveraging_threshold_decrease_bps_per_day = 50;
+
+        let mut obligation = zeroed::<Obligation>();
+        obligation.autodeleverage_margin_call_started_timestamp = STARTED_AT;
+        obligation.autodeleverage_target_ltv_pct = 50;
+
+        let health = ObligationHealth {
+            deposited_value: 1_000.0,
+            borrowed_value: 700.0,
+            bf_adjusted_debt_value: 700.0,
+            ltv: 0.7,
+            liquidation_ltv: 0.8,
+            deposits: vec![position(collateral, 1_000.0, 6, 1.0)],
+            borrows: vec![position(debt, 700.0, 6, 1.0)],
+            ..Default::default()
+        };
+        (obligation, health, HashMap::from([(collateral, reserve)]))
+    }
+
+    fn days_after_grace(days: f64) -> u64 {
+        STARTED_AT + GRACE_PERIOD_SECS + (days * SECONDS_PER_DAY) as u64
+    }
+
+    #[test]
+    fn ignores_obligations_without_a_margin_call() {
+        let (mut obligation, health, reserves) = marked();
+        obligation.autodeleverage_margin_call_started_timestamp = 0;
+        assert!(evaluate_deleverage(&obligation, &health, &reserves, days_after_grace(2.0)).is_none());
+    }
+
+    #[test]
+    fn waits_out_the_grace_period() {
+        let (obligation, health, reserves) = marked();
+        let status = evaluate_deleverage(&obligation, &health, &reserves, STARTED_AT + 3_600).unwrap();
+        assert!(!status.grace_period_expired && !status.is_actionable());
+        assert_eq!(status.seconds_until_expiry, 82_800);
+        assert_eq!(status.bonus_rate, 0.0);
+        assert_eq!(status.effective_liquidation_ltv, 0.8);
+    }
+
+    #[test]
+    fn sizes_the_repayment_that_reaches_the_target_ltv() {
+        let (obligation, health, reserves) = marked();
+        let status = evaluate_deleverage(&obligation, &health, &reserves, days_after_grace(2.0)).unwrap();
+        assert!(status.grace_period_expired && status.is_actionable());
+        assert_eq!(status.seconds_until_expiry, -172_800);
+        assert!((status.bonus_rate - 0.04).abs() < 1e-9);
+        assert!((status.effective_liquidation_ltv - 0.79).abs() < 1e-9);
+
+        // (700 - x) / (1000 - 1.04x) = 0.5 gives x = 200 / 0.48.
+        assert!((status.deleverageable_value - 200.0 / 0.48).abs() < 1e-9);
+        assert!((status.deleverageable_amount - 200.0 / 0.48).abs() < 1e-9);
+        let remaining_ltv = (700.0 - status.deleverageable_value) / (1_000.0 - status.deleverageable_value * 1.04);
+        assert!((remaining_ltv - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn caps_the_bonus_and_the_repayment() {
+        let (mut obligation, health, reserves) = marked();
+        // 20 days past the grace period the bonus would be 22%, capped at 10%.
+        let status = evaluate_deleverage(&obligation, &health, &reserves, days_after_grace(20.0)).unwrap();
+        assert!((status.bonus_rate - 0.1).abs() < 1e-9);
+        assert!((status.effective_liquidation_ltv - 0.7).abs() < 1e-9);
+
+        // A 0% target repays everything the collateral covers: all $700 of debt.
+        obligation.autodeleverage_target_ltv_pct = 0;
+        let status = evaluate_deleverage(&obligation, &health, &reserves, days_after_grace(2.0)).unwrap();
+        assert!((status.deleverageable_value - 700.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn solves_for_the_debt_to_repay() {
+        assert_eq!(debt_to_reach_target(400.0, 1_000.0, 0.5, 0.05), 0.0);
+        assert_eq!(debt_to_reach_target(0.0, 1_000.0, 0.5, 0.05), 0.0);
+        // (800 - 500) / (1 - 0.5 * 1.2) = 750.
+        assert!((debt_to_reach_target(800.0, 1_000.0, 0.5, 0.2) - 750.0).abs() < 1e-9);
+        // A target the bonus makes unreachable repays all debt.
+        assert_eq!(debt_to_reach_target(950.0, 1_000.0, 0.9, 0.2), 950.0);
+    }
+}
@@ -0,0 +1,557 @@
+use crate::blockhash_listener::current_slot;
+use crate::deleverage::evaluate_deleverage;
+use crate::events::{Event, EvaluationEvent, EventReceiver, ExecutionRequest, ExecutionSender};
+use crate::health::compute_health;
+use crate::kamino::{Obligation, Reserve};
+use crate::liquidation_price::liquidation_prices;
+use crate::obligation_store::ObligationStore;
+use crate::orders::evaluate_orders;
+use crate::persistence::Database;
+use crate::planner::{plan_obligation, Opportunity, OpportunityInfo, PlannerConfig};
+use crate::price_guard::{quote_price, PriceGuardConfig};
+use crate::price_listener::{get_current_price_info, get_token_symbol, get_twap, PriceListener};
+use crate::snapshot::{BorrowInfo, DepositInfo, ObligationInfo, PriceInfo};
+use crate::volatility::{mint_volatility, obligation_risk, VolatilityConfig};
+use crate::watchlist::{WatchTier, Watchlist, WatchlistConfig};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+pub const DUE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+pub const MAX_EVENT_LAG: Duration = Duration::from_secs(1);
+
+// Consumes price, reserve and obligation events, recomputes the affected obligations and
+// hands opportunities to the executor and evaluations to the sinks as soon as they appear.
+pub struct HealthEvaluator {
+    store: Arc<ObligationStore>,
+    reserves: Arc<HashMap<Pubkey, Reserve>>,
+    reserve_to_mint_map: HashMap<Pubkey, String>,
+    watchlist: Watchlist,
+    executions: Option<ExecutionSender>,
+    evaluations: broadcast::Sender<EvaluationEvent>,
+    database: Option<Database>,
+    price_listener: Option<Arc<PriceListener>>,
+    pub planner_config: PlannerConfig,
+    pub price_guard_config: PriceGuardConfig,
+    pub volatility_config: VolatilityConfig,
+}
+
+impl HealthEvaluator {
+    pub fn new(
+        store: Arc<ObligationStore>,
+        reserves: HashMap<Pubkey, Reserve>,
+        reserve_to_mint_map: HashMap<Pubkey, String>,
+        executions: Option<ExecutionSender>,
+        evaluations: broadcast::Sender<EvaluationEvent>,
+    ) -> Self {
+        HealthEvaluator {
+            store,
+            reserves: Arc::new(reserves),
+            reserve_to_mint_map,
+            watchlist: Watchlist::new(WatchlistConfig::default()),
+            executions,
+            evaluations,
+            database: None,
+            price_listener: None,
+            planner_config: PlannerConfig::default(),
+            price_guard_config: PriceGuardConfig::default(),
+            volatility_config: VolatilityConfig::default(),
+        }
+    }
+
+    pub fn with_database(mut self, database: Option<Database>) -> Self {
+        self.database = database;
+        self
+    }
+
+    // Reserves listed after startup get their mint's price feed added to the live subscription.
+    pub fn with_price_listener(mut self, price_listener: Arc<PriceListener>) -> Self {
+        self.price_listener = Some(price_listener);
+        self
+    }
+
+    pub fn start(mut self, mut events: EventReceiver) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            info!("Evaluating all {} obligations to seed the watchlist...", self.store.len());
+            let all_addresses = self.store.addresses();
+            self.evaluate(&all_addresses);
+            let (hot, warm, cold) = self.watchlist.tier_counts();
+            info!("Watchlist seeded with {} obligations: {} hot, {} warm, {} cold", self.watchlist.len(), hot, warm, cold);
+            for entry in self.watchlist.ranked(Instant::now()).iter().take(5) {
+                info!("   #{} {} health factor {:.4}", entry.rank, entry.obligation_address, entry.health_factor);
+            }
+
+            let mut due_interval = tokio::time::interval(DUE_CHECK_INTERVAL);
+            loop {
+                tokio::select! {
+                    event = events.recv() => {
+                        let Some(event) = event else { break };
+                        let mut batch = vec![event];
+                        while let Ok(more) = events.try_recv() {
+                            batch.push(more);
+                        }
+                        self.handle_events(batch);
+                    }
+                    _ = due_interval.tick() => {
+                        let due = self.watchlist.due(Instant::now());
+                        if !due.is_empty() {
+                            self.evaluate(&due);
+                        }
+                    }
+                }
+            }
+            warn!("Event bus closed, health evaluator stopping");
+        })
+    }
+
+    fn handle_events(&mut self, batch: Vec<Event>) {
+        if let Some(oldest) = batch.iter().map(Event::received_at).min() {
+            let lag = oldest.elapsed();
+            if lag > MAX_EVENT_LAG {
+                warn!("Health evaluator is lagging by {:?} ({} events batched)", lag, batch.len());
+            }
+        }
+
+        let mut forced = HashSet::new();
+        let mut candidates = HashSet::new();
+
+        for event in batch {
+            match event {
+                Event::Price { obligations, .. } => candidates.extend(obligations),
+                Event::Reserve { address, reserve, slot, .. } => {
+                    if let Some(database) = &self.database {
+                        database.record_reserve(address, slot, &reserve);
+                    }
+                    let mint = reserve.mint().to_string();
+                    if let Some(price_listener) = &self.price_listener {
+                        if !self.reserve_to_mint_map.contains_key(&address) {
+                            price_listener.track_mint(&mint);
+                        }
+                    }
+                    self.reserve_to_mint_map.insert(address, mint.clone());
+                    self.store.set_reserve_mint(address, mint);
+                    Arc::make_mut(&mut self.reserves).insert(address, *reserve);
+                    candidates.extend(self.store.obligations_for_reserve(&address));
+                }
+                Event::Obligation { address, obligation, slot, .. } => {
+                    if let Some(database) = &self.database {
+                        database.record_obligation(address, slot, &obligation);
+                    }
+                    if obligation.has_active_borrows() {
+                        self.store.insert(address, *obligation);
+                        forced.insert(address);
+                    } else if self.store.remove(&address).is_some() {
+                        self.watchlist.remove(&address);
+                        forced.remove(&address);
+                        let _ = self.evaluations.send(EvaluationEvent::Removed(address));
+                    }
+                }
+            }
+        }
+
+        candidates.retain(|address| !forced.contains(address));
+        let mut addresses: Vec<Pubkey> = forced.into_iter().collect();
+        addresses.extend(self.select_affected(candidates));
+        if !addresses.is_empty() {
+            self.evaluate(&addresses);
+        }
+    }
+
+    // Recomputes health for every obligation touched by the batched updates and keeps the
+    // ones that are, or have just become, near-threshold for a full evaluation.
+    fn select_affected(&self, candidates: HashSet<Pubkey>) -> Vec<Pubkey> {
+        candidates
+            .into_iter()
+            .filter(|address| {
+                if self.watchlist.is_hot(address) {
+                    return true;
+                }
+                self.store.get(address).is_some_and(|obligation| {
+                    let health = compute_health(&obligation, &self.reserves, |reserve| {
+                        quote_price(reserve, &self.price_guard_config)
+                    });
+                    health.is_liquidatable
+                        || self.watchlist.tier_of(health.health_factor) == WatchTier::Hot
+                })
+            })
+            .collect()
+    }
+
+    fn evaluate(&mut self, addresses: &[Pubkey]) {
+        let now = Instant::now();
+
+        for address in addresses {
+            let obligation = match self.store.get(address) {
+                Some(obligation) => obligation,
+                None => continue,
+            };
+
+            let (mut obligation_info, opportunities) = self.evaluate_obligation(&obligation, address);
+            self.watchlist.update(*address, &obligation_info.health, now);
+            obligation_info.watch = self.watchlist.entry_info(address, now);
+            let _ = self
+                .evaluations
+                .send(EvaluationEvent::Evaluated(Arc::new(obligation_info)));
+
+            if let Some(executions) = &self.executions {
+                for opportunity in opportunities {
+                    let _ = executions.send(ExecutionRequest {
+                        opportunity,
+                        obligation: Arc::clone(&obligation),
+                        reserves: Arc::clone(&self.reserves),
+                        detected_at: now,
+                    });
+                }
+            }
+        }
+    }
+
+    fn evaluate_obligation(&self, obligation: &Obligation, address: &Pubkey) -> (ObligationInfo, Vec<Opportunity>) {
+        let now = chrono::Utc::now();
+        let reserve_addresses = obligation.get_reserve_addresses();
+        let all_token_mints: Vec<String> = reserve_addresses
+            .iter()
+            .map(|addr| self.reserve_to_mint_map.get(addr).cloned().unwrap_or_else(|| "UNKNOWN".to_string()))
+            .collect();
+
+        let mut live_prices: HashMap<String, PriceInfo> = HashMap::new();
+        for mint in &all_token_mints {
+            if let Some(price_info) = get_current_price_info(mint) {
+                live_prices.insert(mint.clone(), PriceInfo {
+                    symbol: price_info.symbol,
+                    price: price_info.price,
+                    confidence: price_info.confidence,
+                    status: price_info.status,
+                    last_updated: price_info.last_updated.to_rfc3339(),
+                    publish_time: price_info.publish_time.map(|time| time.to_rfc3339()),
+                    publish_slot: price_info.publish_slot,
+                    ema_price: price_info.ema_price,
+                    oracle_ema_price: price_info.oracle_ema_price,
+                    twap: get_twap(mint, self.price_guard_config.twap_window),
+                    volatility: mint_volatility(mint, &self.volatility_config),
+                });
+            }
+        }
+
+        let deposits: Vec<DepositInfo> = obligation.deposits
+            .iter()
+            .filter(|deposit| deposit.deposit_reserve != Pubkey::default())
+            .map(|deposit| {
+                let token_mint = self.reserve_to_mint_map.get(&deposit.deposit_reserve)
+                    .cloned()
+                    .unwrap_or_else(|| "UNKNOWN".to_string());
+            
+                let token_symbol = get_token_symbol(&token_mint).to_string();
+                let (live_price, price_status) = 
+                    if let Some(price_info) = get_current_price_info(&token_mint) {
+                        (Some(price_info.price), Some(price_info.status))
+                    } else {
+                        (None, Some("No Pyth Data".to_string()))
+                    };
+            
+                DepositInfo {
+                    reserve_address: deposit.deposit_reserve.to_string(),
+                    token_mint,
+                    token_symbol,
+                    deposited_amount: deposit.deposited_amount,
+                    market_value: deposit.market_value_sf.to_string(),
+                    live_price,
+                    price_status,
+                }
+            })
+            .collect();
+
+        let borrows: Vec<BorrowInfo> = obligation.borrows
+            .iter()
+            .filter(|borrow| borrow.borrow_reserve != Pubkey::default() && borrow.borrowed_amount_sf > 0)
+            .map(|borrow| {
+                let token_mint = self.reserve_to_mint_map.get(&borrow.borrow_reserve)
+                    .cloned()
+                    .unwrap_or_else(|| "UNKNOWN".to_string());
+            
+                let token_symbol = get_token_symbol(&token_mint).to_string();
+                let (live_price, price_status) = 
+                    if let Some(price_info) = get_current_price_info(&token_mint) {
+                        (Some(price_info.price), Some(price_info.status))
+                    } else {
+                        (None, Some("No Pyth Data".to_string()))
+                    };
+            
+                BorrowInfo {
+                    reserve_address: borrow.borrow_reserve.to_string(),
+                    token_mint,
+                    token_symbol,
+                    borrowed_amount: borrow.borrowed_amount_sf.to_string(),
+                    market_value: borrow.market_value_sf.to_string(),
+                    live_price,
+                    price_status,
+                }
+            })
+            .collect();
+
+        let health = compute_health(obligation, &self.reserves, |reserve| quote_price(reserve, &self.price_guard_config));
+        let risk = obligation_risk(&health, &self.volatility_config);
+        let obligation_liquidation_prices = liquidation_prices(obligation, &health, &self.reserves);
+        let orders = evaluate_orders(obligation, &health);
+        let deleverage = evaluate_deleverage(obligation, &health, &self.reserves, now.timestamp() as u64);
+        let obligation_opportunities = plan_obligation(
+            address,
+            &health,
+            &orders,
+            deleverage.as_ref(),
+            &self.reserves,
+            &self.planner_config,
+        );
+
+        let obligation_info = ObligationInfo {
+            obligation_address: address.to_string(),
+            slot: current_slot().unwrap_or_default(),
+            owner: obligation.owner.to_string(),
+            deposited_value: obligation.deposited_value_sf.to_string(),
+            borrowed_value: obligation.borrowed_assets_market_value_sf.to_string(),
+            allowed_borrow_value: obligation.allowed_borrow_value_sf.to_string(),
+            unhealthy_borrow_value: obligation.unhealthy_borrow_value_sf.to_string(),
+            elevation_group: obligation.elevation_group,
+            has_debt: obligation.has_debt != 0,
+            active_deposits_count: deposits.len(),
+            active_borrows_count: borrows.len(),
+            deposits,
+            borrows,
+            all_token_mints,
+            live_prices,
+            health,
+            risk,
+            liquidation_prices: obligation_liquidation_prices,
+            orders,
+            deleverage,
+            opportunities: obligation_opportunities.iter().map(OpportunityInfo::from).collect(),
+            watch: None,
+            last_updated: now.to_rfc3339(),
+        };
+
+        (obligation_info, obligation_opportunities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{evaluation_channel, event_channel, execution_channel, EventSender, ExecutionReceiver};
+    use crate::kamino::f64_to_sf;
+    use crate::planner::OpportunityKind;
+    use crate::price_listener::{TokenPrice, PRICE_STATE};
+    use crate::snapshot::SnapshotSink;
+    use crate::test_fixtures::zeroed;
+    use chrono::Utc;
+    use serde_json::Value;
+    use tokio::sync::broadcast::Receiver;
+
+    struct Market {
+        sol: Pubkey,
+        usdc: Pubkey,
+        address: Pubkey,
+        reserves: HashMap<Pubkey, Reserve>,
+        obligation: Obligation,
+    }
+
+    // Reserves with fresh on-chain prices and unique mints, so no live price applies until a
+    // test publishes one.
+    fn reserve(decimals: u64, price: f64, updated_at: u64) -> Reserve {
+        let mut reserve = zeroed::<Reserve>();
+        reserve.liquidity.mint_pubkey = Pubkey::new_unique();
+        reserve.liquidity.mint_decimals = decimals;
+        reserve.liquidity.market_price_sf = f64_to_sf(price);
+        reserve.liquidity.market_price_last_updated_ts = updated_at;
+        reserve.config.loan_to_value_pct = 75;
+        reserve.config.liquidation_threshold_pct = 80;
+        reserve.config.borrow_factor_pct = 100;
+        reserve.config.min_liquidation_bonus_bps = 200;
+        reserve.config.max_liquidation_bonus_bps = 1_000;
+        reserve
+    }
+
+    // 10 SOL at $100 against 700 USDC: health factor 800 / 700, warm but not liquidatable.
+    fn market(sol_updated_at: u64) -> Market {
+        let now = Utc::now().timestamp() as u64;
+        let (sol, usdc, address) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+
+        let mut obligation = zeroed::<Obligation>();
+        obligation.deposits[0].deposit_reserve = sol;
+        obligation.deposits[0].deposited_amount = 10_000_000_000;
+        obligation.borrows[0].borrow_reserve = usdc;
+        obligation.borrows[0].borrowed_amount_sf = f64_to_sf(700e6);
+
+        let reserves = HashMap::from([(sol, reserve(9, 100.0, sol_updated_at)), (usdc, reserve(6, 1.0, now))]);
+        Market { sol, usdc, address, reserves, obligation }
+    }
+
+    struct Pipeline {
+        events: EventSender,
+        executions: ExecutionReceiver,
+        evaluations: Receiver<EvaluationEvent>,
+        snapshot_path: std::path::PathBuf,
+    }
+
+    // Starts the evaluator and a snapshot sink on the market and waits for the seeding pass.
+    async fn start(market: &Market) -> Pipeline {
+        let store = Arc::new(ObligationStore::new());
+        let reserve_to_mint_map: HashMap<Pubkey, String> = market
+            .reserves
+            .iter()
+            .map(|(address, reserve)| (*address, reserve.mint().to_string()))
+            .collect();
+        for (reserve, mint) in &reserve_to_mint_map {
+            store.set_reserve_mint(*reserve, mint.clone());
+        }
+        store.insert(market.address, market.obligation.clone());
+
+        let (event_sender, event_receiver) = event_channel();
+        let (execution_sender, executions) = execution_channel();
+        let evaluation_sender = evaluation_channel();
+        let mut evaluations = evaluation_sender.subscribe();
+
+        let snapshot_path = std::env::temp_dir().join(format!("obligations-{}.json", market.address));
+        SnapshotSink::new(snapshot_path.clone(), Duration::from_millis(10), Duration::from_secs(600))
+            .start(evaluation_sender.subscribe());
+        HealthEvaluator::new(
+            store,
+            market.reserves.clone(),
+            reserve_to_mint_map,
+            Some(execution_sender),
+            evaluation_sender,
+        )
+        .start(event_receiver);
+
+        let seeded = next_evaluation(&mut evaluations).await;
+        assert!(!seeded.health.is_liquidatable);
+
+        Pipeline { events: event_sender, executions, evaluations, snapshot_path }
+    }
+
+    async fn next_evaluation(evaluations: &mut Receiver<EvaluationEvent>) -> Arc<ObligationInfo> {
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(5), evaluations.recv()).await.unwrap().unwrap();
+            if let EvaluationEvent::Evaluated(info) = event {
+                return info;
+            }
+        }
+    }
+
+    async fn next_execution(executions: &mut ExecutionReceiver) -> ExecutionRequest {
+        tokio::time::timeout(Duration::from_secs(5), executions.recv()).await.unwrap().unwrap()
+    }
+
+    // Polls the snapshot file until the obligation shows up as liquidatable.
+    async fn liquidatable_snapshot_entry(path: &std::path::Path, address: &Pubkey) -> Value {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let entry = std::fs::read(path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<Vec<Value>>(&bytes).ok())
+                .and_then(|entries| {
+                    entries.into_iter().find(|entry| {
+                        entry["obligation_address"] == address.to_string()
+                            && entry["health"]["is_liquidatable"] == true
+                    })
+                });
+            if let Some(entry) = entry {
+                return entry;
+            }
+            assert!(Instant::now() < deadline, "no liquidatable snapshot entry for {}", address);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    // SOL at $75: 750 deposited, 600 unhealthy against 700 of debt. The 20% close factor
+    // repays 140 USDC for SOL.
+    fn assert_liquidation(request: &ExecutionRequest, market: &Market) {
+        let opportunity = &request.opportunity;
+        assert_eq!(opportunity.kind, OpportunityKind::Liquidation);
+        assert_eq!(opportunity.obligation, market.address);
+        assert_eq!(opportunity.repay_reserve, market.usdc);
+        assert_eq!(opportunity.withdraw_reserve, market.sol);
+        assert!((opportunity.repay_value - 140.0).abs() < 1e-6);
+        assert!(opportunity.expected_profit > 0.0);
+    }
+
+    #[tokio::test]
+    async fn reserve_price_drop_produces_an_execution_and_a_snapshot_entry() {
+        let now = Utc::now().timestamp() as u64;
+        let market = market(now);
+        let mut pipeline = start(&market).await;
+        assert!(pipeline.executions.try_recv().is_err());
+
+        let mut sol = market.reserves[&market.sol].clone();
+        sol.liquidity.market_price_sf = f64_to_sf(75.0);
+        pipeline
+            .events
+            .send(Event::Reserve {
+                address: market.sol,
+                reserve: Box::new(sol),
+                slot: 1,
+                received_at: Instant::now(),
+            })
+            .unwrap();
+
+        let request = next_execution(&mut pipeline.executions).await;
+        assert_liquidation(&request, &market);
+        assert!((request.reserves[&market.sol].market_price() - 75.0).abs() < 1e-9);
+
+        let info = next_evaluation(&mut pipeline.evaluations).await;
+        assert!(info.health.is_liquidatable);
+        assert_eq!(info.opportunities.len(), 1);
+
+        let entry = liquidatable_snapshot_entry(&pipeline.snapshot_path, &market.address).await;
+        assert_eq!(entry["opportunities"].as_array().unwrap().len(), 1);
+        assert!((entry["health"]["deposited_value"].as_f64().unwrap() - 750.0).abs() < 1e-6);
+        let _ = std::fs::remove_file(&pipeline.snapshot_path);
+    }
+
+    #[tokio::test]
+    async fn live_price_drop_produces_an_execution_and_a_snapshot_entry() {
+        // The SOL reserve price is too old to trust on its own, so only a live price counts.
+        let market = market(0);
+        let mut pipeline = start(&market).await;
+        assert!(pipeline.executions.try_recv().is_err());
+
+        let mint = market.reserves[&market.sol].mint().to_string();
+        let now = Utc::now();
+        PRICE_STATE.insert(
+            mint.clone(),
+            TokenPrice {
+                mint,
+                symbol: "SOL".to_string(),
+                price: 75.0,
+                confidence: 0.0,
+                last_updated: now,
+                publish_time: Some(now),
+                publish_slot: 0,
+                status: "Trading".to_string(),
+                ema_price: 75.0,
+                oracle_ema_price: None,
+                oracle_ema_confidence: None,
+            },
+        );
+        pipeline
+            .events
+            .send(Event::Price {
+                obligations: vec![market.address],
+                received_at: Instant::now(),
+            })
+            .unwrap();
+
+        let request = next_execution(&mut pipeline.executions).await;
+        assert_liquidation(&request, &market);
+
+        let info = next_evaluation(&mut pipeline.evaluations).await;
+        assert!(info.health.is_liquidatable);
+        assert!(info.health.deposits[0].live_price);
+
+        let entry = liquidatable_snapshot_entry(&pipeline.snapshot_path, &market.address).await;
+        assert_eq!(entry["opportunities"].as_array().unwrap().len(), 1);
+        let _ = std::fs::remove_file(&pipeline.snapshot_path);
+    }
+}
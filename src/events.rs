@@ -0,0 +1,76 @@
+use crate::kamino::{Obligation, Reserve};
+use crate::planner::Opportunity;
+use crate::snapshot::ObligationInfo;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{broadcast, mpsc};
+
+// Listener -> evaluator. Price events already carry the obligations holding the mint,
+// looked up in the obligation store by the listener.
+#[derive(Debug, Clone)]
+pub enum Event {
+    Price {
+        obligations: Vec<Pubkey>,
+        received_at: Instant,
+    },
+    Reserve {
+        address: Pubkey,
+        reserve: Box<Reserve>,
+        slot: u64,
+        received_at: Instant,
+    },
+    Obligation {
+        address: Pubkey,
+        obligation: Box<Obligation>,
+        slot: u64,
+        received_at: Instant,
+    },
+}
+
+impl Event {
+    pub fn received_at(&self) -> Instant {
+        match self {
+            Event::Price { received_at, .. }
+            | Event::Reserve { received_at, .. }
+            | Event::Obligation { received_at, .. } => *received_at,
+        }
+    }
+}
+
+pub type EventSender = mpsc::UnboundedSender<Event>;
+pub type EventReceiver = mpsc::UnboundedReceiver<Event>;
+
+pub fn event_channel() -> (EventSender, EventReceiver) {
+    mpsc::unbounded_channel()
+}
+
+// Evaluator -> executor.
+#[derive(Debug, Clone)]
+pub struct ExecutionRequest {
+    pub opportunity: Opportunity,
+    pub obligation: Arc<Obligation>,
+    pub reserves: Arc<HashMap<Pubkey, Reserve>>,
+    pub detected_at: Instant,
+}
+
+pub type ExecutionSender = mpsc::UnboundedSender<ExecutionRequest>;
+pub type ExecutionReceiver = mpsc::UnboundedReceiver<ExecutionRequest>;
+
+pub fn execution_channel() -> (ExecutionSender, ExecutionReceiver) {
+    mpsc::unbounded_channel()
+}
+
+// Evaluator -> sinks (snapshot file, alerts).
+#[derive(Debug, Clone)]
+pub enum EvaluationEvent {
+    Evaluated(Arc<ObligationInfo>),
+    Removed(Pubkey),
+}
+
+pub const EVALUATION_CHANNEL_CAPACITY: usize = 16_384;
+
+pub fn evaluation_channel() -> broadcast::Sender<EvaluationEvent> {
+    broadcast::channel(EVALUATION_CHANNEL_CAPACITY).0
+}
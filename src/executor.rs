@@ -0,0 +1,398 @@
+use crate::events::ExecutionReceiver;
+use crate::fees::{PriorityFeeConfig, PriorityFeeEstimator};
+use crate::health::live_price;
+use crate::jito::{JitoSubmitter, SubmissionOutcome};
+use crate::kamino::{Obligation, Reserve};
+use crate::klend_ix::{self, LiquidationAccounts};
+use crate::lookup_table::{LookupTableManager, DEFAULT_CACHE_PATH};
+use crate::planner::Opportunity;
+use crate::rpc_pool::RpcPool;
+use crate::tx_builder::TransactionBuilder;
+use anyhow::{anyhow, Result};
+use dashmap::DashSet;
+use solana_sdk::{
+    native_token::LAMPORTS_PER_SOL,
+    pubkey,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signer},
+};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const WSOL_MINT: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+
+#[derive(Debug, Clone)]
+pub struct ExecutorConfig {
+    pub live: bool,
+    pub tip_share: f64,
+    pub max_tip_lamports: u64,
+    pub min_received_share: f64,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        ExecutorConfig {
+            live: false,
+            tip_share: 0.3,
+            max_tip_lamports: LAMPORTS_PER_SOL / 10,
+            min_received_share: 0.98,
+        }
+    }
+}
+
+pub struct Executor {
+    program_id: Pubkey,
+    lending_market: Pubkey,
+    payer: Arc<Keypair>,
+    builder: TransactionBuilder,
+    submitter: JitoSubmitter,
+    config: ExecutorConfig,
+    in_flight: DashSet<Pubkey>,
+}
+
+impl Executor {
+    pub async fn from_env(
+        program_id: Pubkey,
+        lending_market: Pubkey,
+        rpc_pool: Arc<RpcPool>,
+        reserves: &HashMap<Pubkey, Reserve>,
+    ) -> Result<Option<Self>> {
+        let keypair_path = match std::env::var("KEYPAIR_PATH") {
+            Ok(path) => path,
+            Err(_) => return Ok(None),
+        };
+
+        let payer = Arc::new(
+            read_keypair_file(&keypair_path)
+                .map_err(|e| anyhow!("failed to read keypair {}: {}", keypair_path, e))?,
+        );
+
+        let lookup_tables = Arc::new(LookupTableManager::new(
+            Arc::clone(&rpc_pool),
+            Arc::clone(&payer),
+            PathBuf::from(DEFAULT_CACHE_PATH),
+        ));
+        lookup_tables.load_cached().await?;
+        let seeded = lookup_tables.seed(&program_id, &lending_market, reserves);
+        info!("Queued {} market and reserve accounts for the lookup tables", seeded);
+        Arc::clone(&lookup_tables).start_maintenance(Duration::from_secs(30));
+
+        let builder = TransactionBuilder::new(
+            Arc::clone(&payer),
+            Arc::clone(&rpc_pool),
+            PriorityFeeEstimator::new(Arc::clone(&rpc_pool), PriorityFeeConfig::default()),
+            lookup_tables,
+        );
+
+        let config = ExecutorConfig {
+            live: std::env::var("LIVE_EXECUTION").map(|v| v == "true").unwrap_or(false),
+            ..Default::default()
+        };
+
+        info!(
+            "Executor ready: liquidator {} ({})",
+            payer.pubkey(),
+            if config.live { "LIVE" } else { "dry-run" }
+        );
+
+        let mut submitter = JitoSubmitter::from_env(rpc_pool);
+        if config.live {
+            if let Err(e) = submitter.refresh_tip_accounts().await {
+                warn!("Failed to refresh Jito tip accounts, keeping the defaults: {}", e);
+            }
+        }
+
+        Ok(Some(Executor {
+            program_id,
+            lending_market,
+            payer,
+            builder,
+            submitter,
+            config,
+            in_flight: DashSet::new(),
+        }))
+    }
+
+    // Runs each requested execution on its own task; `execute` already skips obligations
+    // with an execution in flight.
+    pub fn start(self: Arc<Self>, mut requests: ExecutionReceiver) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                let executor = Arc::clone(&self);
+                tokio::spawn(async move {
+                    info!(
+                        "Executing {:?} on {} ({:?} after detection)",
+                        request.opportunity.kind,
+                        request.opportunity.obligation,
+                        request.detected_at.elapsed()
+                    );
+                    if let Err(e) = executor
+                        .execute(&request.opportunity, &request.obligation, &request.reserves)
+                        .await
+                    {
+                        warn!(
+                            "Failed to execute {:?} on {}: {:?}",
+                            request.opportunity.kind, request.opportunity.obligation, e
+                        );
+                    }
+                });
+            }
+        })
+    }
+
+    pub async fn execute(
+        &self,
+        opportunity: &Opportunity,
+        obligation: &Obligation,
+        reserves: &HashMap<Pubkey, Reserve>,
+    ) -> Result<()> {
+        if !self.in_flight.insert(opportunity.obligation) {
+            return Ok(());
+        }
+
+        let result = self.try_execute(opportunity, obligation, reserves).await;
+        self.in_flight.remove(&opportunity.obligation);
+        result
+    }
+
+    async fn try_execute(
+        &self,
+        opportunity: &Opportunity,
+        obligation: &Obligation,
+        reserves: &HashMap<Pubkey, Reserve>,
+    ) -> Result<()> {
+        let accounts = LiquidationAccounts {
+            program_id: self.program_id,
+            lending_market: self.lending_market,
+            liquidator: self.payer.pubkey(),
+            obligation_address: opportunity.obligation,
+            obligation,
+            reserves,
+            repay_reserve: opportunity.repay_reserve,
+            withdraw_reserve: opportunity.withdraw_reserve,
+        };
+
+        // Every kind goes through the same instruction: klend has no separate order or
+        // deleveraging instruction, liquidate_obligation_and_redeem_reserve_collateral works out
+        // on-chain whether the obligation is over its liquidation LTV, marked for auto-deleveraging
+        // or has a triggered order, and applies that reason's bonus and close factor. The planner
+        // already sized repay_amount and bonus_rate for the kind.
+        let instructions = klend_ix::liquidation_instructions(
+            &accounts,
+            opportunity.repay_amount,
+            self.min_received_amount(opportunity, reserves),
+        )?;
+
+        let profit_lamports = usd_to_lamports(opportunity.expected_profit);
+        let blockhash = self.builder.latest_blockhash().await?;
+        let built = self.builder.build(&instructions, profit_lamports, blockhash).await?;
+
+        if !self.config.live {
+            info!(
+                "[dry-run] {:?} on {}: repay {} of reserve {}, expected profit ${:.2}, fee {} lamports, {} bytes, {} lookup tables",
+                opportunity.kind,
+                opportunity.obligation,
+                opportunity.repay_amount,
+                opportunity.repay_reserve,
+                opportunity.expected_profit,
+                built.fee.total_priority_fee_lamports,
+                built.size,
+                built.lookup_tables_used
+            );
+            return Ok(());
+        }
+
+        // Without a SOL price neither the tip nor the profit-based fee can be sized, so the
+        // bundle is skipped and the transaction goes out via RPC at the market fee.
+        let outcome = match profit_lamports {
+            Some(profit_lamports) => {
+                let tip_lamports = ((profit_lamports as f64 * self.config.tip_share) as u64)
+                    .min(self.config.max_tip_lamports);
+                self.submitter
+                    .submit(vec![built.transaction], &self.payer, tip_lamports, blockhash)
+                    .await?
+            }
+            None => self.submitter.send_via_rpc(&[built.transaction]).await?,
+        };
+
+        match outcome {
+            SubmissionOutcome::Bundle { bundle_id, slot } => info!(
+                "Executed {:?} on {}: bundle {} landed in slot {}",
+                opportunity.kind, opportunity.obligation, bundle_id, slot
+            ),
+            SubmissionOutcome::Rpc { signatures } => info!(
+                "Executed {:?} on {} via RPC: {:?}",
+                opportunity.kind, opportunity.obligation, signatures
+            ),
+        }
+        Ok(())
+    }
+
+    fn min_received_amount(&self, opportunity: &Opportunity, reserves: &HashMap<Pubkey, Reserve>) -> u64 {
+        let reserve = match reserves.get(&opportunity.withdraw_reserve) {
+            Some(reserve) => reserve,
+            None => return 0,
+        };
+
+        let price = live_price(&reserve.mint()).unwrap_or_else(|| reserve.market_price());
+        if price <= 0.0 {
+            return 0;
+        }
+
+        let expected = opportunity.repay_value * (1.0 + opportunity.bonus_rate) / price;
+        (expected * self.config.min_received_share * 10f64.powi(reserve.decimals() as i32)) as u64
+    }
+}
+
+fn usd_to_lamports(value_usd: f64) -> Option<u64> {
+    let lamports = live_price(&WSOL_MINT).map(|sol_price| (value_usd / sol_price * LAMPORTS_PER_SOL as f64) as u64);
+    if lamports.is_none() {
+        warn!("No SOL price available, cannot convert profit to lamports");
+    }
+    lamports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kamino::{f64_to_sf, ConditionType};
+    use crate::mock_rpc::{with_context, MockRpc};
+    use crate::planner::OpportunityKind;
+    use crate::test_fixtures::{rpc_pool, zeroed};
+    use base64::Engine;
+    use serde_json::json;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::transaction::VersionedTransaction;
+
+    fn reserve(decimals: u64, price: f64) -> Reserve {
+        let mut reserve = zeroed::<Reserve>();
+        reserve.liquidity.mint_pubkey = Pubkey::new_unique();
+        reserve.liquidity.mint_decimals = decimals;
+        reserve.liquidity.market_price_sf = f64_to_sf(price);
+        reserve.collateral.mint_pubkey = Pubkey::new_unique();
+        reserve
+    }
+
+    fn executor(url: &str) -> Executor {
+        let payer = Arc::new(Keypair::new());
+        let pool = rpc_pool(url);
+        let cache_path = std::env::temp_dir().join(format!("lookup-tables-{}.json", Pubkey::new_unique()));
+        let lookup_tables = Arc::new(LookupTableManager::new(Arc::clone(&pool), Arc::clone(&payer), cache_path));
+        Executor {
+            program_id: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+            payer: Arc::clone(&payer),
+            builder: TransactionBuilder::new(
+                payer,
+                Arc::clone(&pool),
+                PriorityFeeEstimator::new(Arc::clone(&pool), PriorityFeeConfig::default()),
+                lookup_tables,
+            ),
+            submitter: JitoSubmitter::new(url.to_string(), pool),
+            config: ExecutorConfig::default(),
+            in_flight: DashSet::new(),
+        }
+    }
+
+    // Program, accounts and data of each instruction. Signer and writable flags are merged
+    // across a compiled message, so they are left out.
+    type Call = (Pubkey, Vec<Pubkey>, Vec<u8>);
+
+    fn call(instruction: &Instruction) -> Call {
+        let accounts = instruction.accounts.iter().map(|meta| meta.pubkey).collect();
+        (instruction.program_id, accounts, instruction.data.clone())
+    }
+
+    // A v0 transaction without lookups only references its static keys.
+    fn decompile(transaction: &VersionedTransaction) -> Vec<Call> {
+        let keys = transaction.message.static_account_keys();
+        transaction
+            .message
+            .instructions()
+            .iter()
+            .map(|instruction| {
+                let accounts = instruction.accounts.iter().map(|&index| keys[index as usize]).collect();
+                (keys[instruction.program_id_index as usize], accounts, instruction.data.clone())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn dry_runs_an_order_execution_through_the_liquidate_instruction() {
+        let rpc = MockRpc::new(|method, _| match method {
+            "getLatestBlockhash" => Ok(with_context(
+                1,
+                json!({"blockhash": Hash::new_unique().to_string(), "lastValidBlockHeight": 1_000}),
+            )),
+            "simulateTransaction" => Ok(with_context(
+                1,
+                json!({"err": null, "logs": [], "accounts": null, "unitsConsumed": 150_000, "returnData": null}),
+            )),
+            "getRecentPrioritizationFees" => Ok(json!([])),
+            _ => Err(json!({"code": -32601, "message": "method not found"})),
+        })
+        .serve()
+        .await
+        .unwrap();
+        let executor = executor(&rpc.url);
+
+        let (sol, usdc) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let reserves = HashMap::from([(sol, reserve(9, 125.0)), (usdc, reserve(6, 1.0))]);
+        let mut obligation = zeroed::<Obligation>();
+        obligation.deposits[0].deposit_reserve = sol;
+        obligation.borrows[0].borrow_reserve = usdc;
+
+        let opportunity = Opportunity {
+            obligation: Pubkey::new_unique(),
+            kind: OpportunityKind::OrderExecution {
+                order_index: 0,
+                condition: ConditionType::UserLtvAbove,
+            },
+            repay_reserve: usdc,
+            withdraw_reserve: sol,
+            repay_amount: 100_000_000,
+            repay_value: 100.0,
+            bonus_rate: 0.25,
+            expected_profit: 25.0,
+        };
+
+        // $100 repaid plus the 25% order bonus buys $125 = 1 SOL, of which 98% must arrive.
+        assert_eq!(executor.min_received_amount(&opportunity, &reserves), 980_000_000);
+
+        executor.execute(&opportunity, &obligation, &reserves).await.unwrap();
+
+        let accounts = LiquidationAccounts {
+            program_id: executor.program_id,
+            lending_market: executor.lending_market,
+            liquidator: executor.payer.pubkey(),
+            obligation_address: opportunity.obligation,
+            obligation: &obligation,
+            reserves: &reserves,
+            repay_reserve: usdc,
+            withdraw_reserve: sol,
+        };
+        let expected = klend_ix::liquidation_instructions(&accounts, 100_000_000, 980_000_000).unwrap();
+
+        // Dry runs only simulate: the simulated transaction carries the compute unit limit and
+        // then the liquidation instructions, nothing is sent.
+        let requests = rpc.requests();
+        assert!(!requests.iter().any(|(method, _)| method == "sendTransaction"));
+        let (_, params) = requests.iter().find(|(method, _)| method == "simulateTransaction").unwrap();
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(params[0].as_str().unwrap())
+            .unwrap();
+        let simulated: VersionedTransaction = bincode::deserialize(&data).unwrap();
+        let calls = decompile(&simulated);
+        assert_eq!(calls[1..], expected.iter().map(call).collect::<Vec<_>>()[..]);
+
+        // The liquidate arguments: liquidity_amount, min_acceptable_received_liquidity_amount.
+        let (program_id, _, data) = calls.last().unwrap();
+        assert_eq!(*program_id, executor.program_id);
+        assert_eq!(data[8..16], 100_000_000u64.to_le_bytes());
+        assert_eq!(data[16..24], 980_000_000u64.to_le_bytes());
+    }
+}
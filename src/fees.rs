@@ -0,0 +1,301 @@
+use anyhow::{anyhow, Result};
+use crate::rpc_pool::RpcPool;
+use solana_client::rpc_client::SerializableTransaction;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
+    pubkey::Pubkey,
+};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+const MAX_FEE_ACCOUNTS: usize = 128;
+const MICRO_LAMPORTS_PER_LAMPORT: u128 = 1_000_000;
+
+#[derive(Debug, Clone)]
+pub struct PriorityFeeConfig {
+    pub percentile: u8,
+    pub min_micro_lamports: u64,
+    pub max_micro_lamports: u64,
+    pub profit_share: f64,
+    pub max_profit_share: f64,
+    pub compute_unit_margin: f64,
+    pub fallback_compute_unit_limit: u32,
+}
+
+impl Default for PriorityFeeConfig {
+    fn default() -> Self {
+        PriorityFeeConfig {
+            percentile: 75,
+            min_micro_lamports: 1_000,
+            max_micro_lamports: 5_000_000,
+            profit_share: 0.10,
+            max_profit_share: 0.50,
+            compute_unit_margin: 1.15,
+            fallback_compute_unit_limit: 600_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FeeEstimate {
+    pub compute_unit_limit: u32,
+    pub compute_unit_price: u64,
+    pub total_priority_fee_lamports: u64,
+}
+
+impl FeeEstimate {
+    pub fn instructions(&self) -> Vec<Instruction> {
+        compute_budget_instructions(self.compute_unit_limit, self.compute_unit_price)
+    }
+}
+
+pub struct PriorityFeeEstimator {
+    rpc_pool: Arc<RpcPool>,
+    pub config: PriorityFeeConfig,
+}
+
+impl PriorityFeeEstimator {
+    pub fn new(rpc_pool: Arc<RpcPool>, config: PriorityFeeConfig) -> Self {
+        PriorityFeeEstimator { rpc_pool, config }
+    }
+
+    pub async fn sample_recent_fees(&self, writable_accounts: &[Pubkey]) -> Result<Vec<u64>> {
+        let accounts = &writable_accounts[..writable_accounts.len().min(MAX_FEE_ACCOUNTS)];
+        let fees = self
+            .rpc_pool
+            .call_async(|client| async move { client.get_recent_prioritization_fees(accounts).await })
+            .await?;
+        Ok(fees.into_iter().map(|fee| fee.prioritization_fee).collect())
+    }
+
+    // Bids the market percentile, raised to a share of the expected profit and capped at a
+    // larger share of it. Without a profit estimate the market percentile is bid as is.
+    pub async fn estimate_compute_unit_price(
+        &self,
+        writable_accounts: &[Pubkey],
+        expected_profit_lamports: Option<u64>,
+        compute_unit_limit: u32,
+    ) -> u64 {
+        let market_fee = match self.sample_recent_fees(writable_accounts).await {
+            Ok(fees) => percentile_fee(fees, self.config.percentile),
+            Err(e) => {
+                warn!("Failed to sample recent prioritization fees: {:?}", e);
+                self.config.min_micro_lamports
+            }
+        };
+
+        let bid = match expected_profit_lamports {
+            Some(profit) => {
+                let profit_bid = price_for_budget(profit as f64 * self.config.profit_share, compute_unit_limit);
+                let profit_cap = price_for_budget(profit as f64 * self.config.max_profit_share, compute_unit_limit);
+                market_fee.max(profit_bid).min(profit_cap)
+            }
+            None => market_fee,
+        };
+
+        bid.clamp(self.config.min_micro_lamports, self.config.max_micro_lamports)
+    }
+
+    pub async fn simulate_compute_unit_limit(&self, transaction: &(impl SerializableTransaction + Sync)) -> Result<u32> {
+        let config = RpcSimulateTransactionConfig {
+            sig_verify: false,
+            replace_recent_blockhash: true,
+            commitment: Some(CommitmentConfig::processed()),
+            ..Default::default()
+        };
+
+        let response = self
+            .rpc_pool
+            .call_async(|client| {
+                let config = config.clone();
+                async move { client.simulate_transaction_with_config(transaction, config).await }
+            })
+            .await?;
+
+        if let Some(err) = response.value.err {
+            return Err(anyhow!(
+                "simulation failed: {:?} (logs: {:?})",
+                err,
+                response.value.logs.unwrap_or_default()
+            ));
+        }
+
+        let units_consumed = response
+            .value
+            .units_consumed
+            .ok_or_else(|| anyhow!("simulation did not report units consumed"))?;
+
+        let limit = (units_consumed as f64 * self.config.compute_unit_margin).ceil() as u64;
+        Ok(limit.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32)
+    }
+
+    pub async fn estimate(
+        &self,
+        simulation_tx: &(impl SerializableTransaction + Sync),
+        writable_accounts: &[Pubkey],
+        expected_profit_lamports: Option<u64>,
+    ) -> FeeEstimate {
+        let compute_unit_limit = match self.simulate_compute_unit_limit(simulation_tx).await {
+            Ok(limit) => limit,
+            Err(e) => {
+                warn!(
+                    "Compute unit simulation failed, using fallback limit {}: {:?}",
+                    self.config.fallback_compute_unit_limit, e
+                );
+                self.config.fallback_compute_unit_limit
+            }
+        };
+
+        let compute_unit_price =
+            self.estimate_compute_unit_price(writable_accounts, expected_profit_lamports, compute_unit_limit).await;
+
+        let estimate = FeeEstimate {
+            compute_unit_limit,
+            compute_unit_price,
+            total_priority_fee_lamports: priority_fee_lamports(compute_unit_limit, compute_unit_price),
+        };
+
+        info!(
+            "Fee estimate: {} CU @ {} micro-lamports = {} lamports (expected profit {:?} lamports)",
+            estimate.compute_unit_limit,
+            estimate.compute_unit_price,
+            estimate.total_priority_fee_lamports,
+            expected_profit_lamports
+        );
+
+        estimate
+    }
+}
+
+pub fn compute_budget_instructions(compute_unit_limit: u32, compute_unit_price: u64) -> Vec<Instruction> {
+    vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit),
+        ComputeBudgetInstruction::set_compute_unit_price(compute_unit_price),
+    ]
+}
+
+pub fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut accounts: Vec<Pubkey> = instructions
+        .iter()
+        .flat_map(|ix| ix.accounts.iter())
+        .filter(|meta| meta.is_writable && !meta.is_signer)
+        .map(|meta| meta.pubkey)
+        .collect();
+    accounts.sort();
+    accounts.dedup();
+    accounts
+}
+
+pub fn percentile_fee(mut fees: Vec<u64>, percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+    let index = (fees.len() - 1) * percentile.min(100) as usize / 100;
+    fees[index]
+}
+
+pub fn priority_fee_lamports(compute_unit_limit: u32, compute_unit_price: u64) -> u64 {
+    (compute_unit_limit as u128 * compute_unit_price as u128).div_ceil(MICRO_LAMPORTS_PER_LAMPORT) as u64
+}
+
+fn price_for_budget(budget_lamports: f64, compute_unit_limit: u32) -> u64 {
+    if compute_unit_limit == 0 || budget_lamports <= 0.0 {
+        return 0;
+    }
+    (budget_lamports * MICRO_LAMPORTS_PER_LAMPORT as f64 / compute_unit_limit as f64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rpc::{MockRpc, MockRpcHandle};
+    use crate::test_fixtures::rpc_pool;
+    use serde_json::json;
+
+    // Answers getRecentPrioritizationFees with `fees`, or fails when there are none.
+    async fn stub(fees: Vec<u64>) -> MockRpcHandle {
+        MockRpc::new(move |method, _| match method {
+            "getRecentPrioritizationFees" if !fees.is_empty() => Ok(json!(fees
+                .iter()
+                .enumerate()
+                .map(|(slot, fee)| json!({"slot": slot, "prioritizationFee": fee}))
+                .collect::<Vec<_>>())),
+            _ => Err(json!({"code": -32601, "message": "method not found"})),
+        })
+        .serve()
+        .await
+        .unwrap()
+    }
+
+    fn estimator(url: &str) -> PriorityFeeEstimator {
+        PriorityFeeEstimator::new(rpc_pool(url), PriorityFeeConfig::default())
+    }
+
+    #[test]
+    fn picks_the_percentile_of_the_sampled_fees() {
+        assert_eq!(percentile_fee(vec![], 75), 0);
+        assert_eq!(percentile_fee(vec![7], 0), 7);
+        assert_eq!(percentile_fee(vec![7], 100), 7);
+
+        let fees = vec![9, 3, 10, 1, 6, 2, 8, 4, 7, 5];
+        assert_eq!(percentile_fee(fees.clone(), 0), 1);
+        // Index (10 - 1) * 50 / 100 = 4 and 9 * 75 / 100 = 6 of the sorted fees.
+        assert_eq!(percentile_fee(fees.clone(), 50), 5);
+        assert_eq!(percentile_fee(fees.clone(), 75), 7);
+        assert_eq!(percentile_fee(fees.clone(), 100), 10);
+        assert_eq!(percentile_fee(fees, 255), 10);
+    }
+
+    #[test]
+    fn converts_lamport_budgets_to_compute_unit_prices() {
+        // 1000 lamports over 200k CU is 5000 micro-lamports per CU.
+        assert_eq!(price_for_budget(1_000.0, 200_000), 5_000);
+        assert_eq!(price_for_budget(1_000.0, 0), 0);
+        assert_eq!(price_for_budget(0.0, 200_000), 0);
+        assert_eq!(price_for_budget(-5.0, 200_000), 0);
+        // 200k CU at 5001 micro-lamports is 1000.2 lamports, rounded up.
+        assert_eq!(priority_fee_lamports(200_000, 5_001), 1_001);
+        assert_eq!(priority_fee_lamports(200_000, 5_000), 1_000);
+    }
+
+    #[tokio::test]
+    async fn bids_between_the_market_fee_and_the_profit_share_cap() {
+        // With 1M lamports of profit over 200k CU, 10% of the profit is a 500k micro-lamport
+        // bid and 50% a 2.5M cap.
+        let quiet = stub(vec![100_000; 4]).await;
+        assert_eq!(estimator(&quiet.url).estimate_compute_unit_price(&[], Some(1_000_000), 200_000).await, 500_000);
+
+        let busy = stub(vec![1_000, 3_000_000, 3_000_000, 3_000_000]).await;
+        let estimator = estimator(&busy.url);
+        assert_eq!(estimator.estimate_compute_unit_price(&[], Some(1_000_000), 200_000).await, 2_500_000);
+        // Without profit the cap is zero, but the bid never drops below the 1000 minimum...
+        assert_eq!(estimator.estimate_compute_unit_price(&[], Some(0), 200_000).await, 1_000);
+        // ...or rises above the 5M maximum.
+        assert_eq!(estimator.estimate_compute_unit_price(&[], Some(1_000_000_000), 200_000).await, 5_000_000);
+    }
+
+    #[tokio::test]
+    async fn bids_the_market_fee_without_a_profit_estimate() {
+        // The 75th percentile of the busy market, uncapped by a zero profit.
+        let busy = stub(vec![1_000, 3_000_000, 3_000_000, 3_000_000]).await;
+        assert_eq!(estimator(&busy.url).estimate_compute_unit_price(&[], None, 200_000).await, 3_000_000);
+
+        let quiet = stub(vec![0; 4]).await;
+        assert_eq!(estimator(&quiet.url).estimate_compute_unit_price(&[], None, 200_000).await, 1_000);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_minimum_fee_when_sampling_fails() {
+        let failing = stub(vec![]).await;
+        let estimator = estimator(&failing.url);
+        assert!(estimator.sample_recent_fees(&[Pubkey::new_unique()]).await.is_err());
+        assert_eq!(estimator.estimate_compute_unit_price(&[], Some(0), 200_000).await, 1_000);
+        // The profit bid still applies: 10% of 10k lamports over 200k CU.
+        assert_eq!(estimator.estimate_compute_unit_price(&[], Some(10_000), 200_000).await, 5_000);
+    }
+}
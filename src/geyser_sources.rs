@@ -0,0 +1,352 @@
+use crate::geyser_stream::{run_recorded_stream, run_stream, GeyserEndpoint, StreamConfig, SubscriptionHandle};
+use crate::price_listener::Listener;
+use crate::recorder::Recorder;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+use yellowstone_grpc_proto::geyser::{
+    subscribe_update::UpdateOneof, SubscribeRequest, SubscribeUpdateAccount, SubscribeUpdateAccountInfo,
+};
+
+// Sources delivering an account's newest slot are tracked in a u64 bitmask.
+pub const MAX_SOURCES: usize = 64;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+// A version of an account applied in the newest slot, and which sources have delivered it.
+struct Version {
+    hash: u64,
+    first_seen: Instant,
+    delivered: u64,
+}
+
+// Versions of an account applied in its newest slot, the last of which is current, and per
+// source the write version and hash it last delivered for that slot.
+struct Newest {
+    slot: u64,
+    applied: Vec<Version>,
+    latest: Vec<Option<(u64, u64)>>,
+}
+
+#[derive(Debug, Default)]
+struct SourceStats {
+    updates: u64,
+    first: u64,
+    late: u64,
+    stale: u64,
+    avg_lag_ms: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GeyserSourceInfo {
+    pub label: String,
+    // Account updates received from this source.
+    pub updates: u64,
+    // Updates this source delivered before any other.
+    pub first: u64,
+    // Updates for a slot another source delivered first, and how far behind they arrived.
+    pub late: u64,
+    pub avg_lag_ms: f64,
+    // Updates older than a slot already applied.
+    pub stale: u64,
+}
+
+// Deduplicates account updates arriving from several Geyser endpoints subscribed to the same
+// filters. Slots order updates across sources; within a slot the same account contents from
+// another source are a duplicate. Write versions are validator-local, so they only order the
+// updates of one source. Non-account updates pass through unchanged.
+pub struct GeyserSources {
+    labels: Vec<String>,
+    newest: DashMap<Pubkey, Newest>,
+    stats: Vec<Mutex<SourceStats>>,
+}
+
+impl GeyserSources {
+    pub fn new(labels: Vec<String>) -> Self {
+        assert!(labels.len() <= MAX_SOURCES, "at most {} Geyser sources are supported", MAX_SOURCES);
+        GeyserSources {
+            stats: labels.iter().map(|_| Mutex::new(SourceStats::default())).collect(),
+            labels,
+            newest: DashMap::new(),
+        }
+    }
+
+    // Returns whether the update from `source` should be applied: it is for a newer slot, or
+    // carries contents not yet applied in the current slot and is newer than what this source
+    // delivered before. `hash` identifies the account contents, see account_hash.
+    pub fn observe(&self, source: usize, pubkey: Pubkey, slot: u64, write_version: u64, hash: u64) -> bool {
+        let now = Instant::now();
+        let bit = 1u64 << source;
+        let mut stats = self.stats[source].lock().unwrap();
+        stats.updates += 1;
+
+        let mut newest = match self.newest.entry(pubkey) {
+            Entry::Vacant(entry) => entry.insert(Newest {
+                slot: 0,
+                applied: Vec::new(),
+                latest: vec![None; self.labels.len()],
+            }),
+            Entry::Occupied(entry) => entry.into_ref(),
+        };
+
+        if slot < newest.slot {
+            stats.stale += 1;
+            return false;
+        }
+        if slot > newest.slot || newest.applied.is_empty() {
+            newest.slot = slot;
+            newest.applied = vec![Version {
+                hash,
+                first_seen: now,
+                delivered: bit,
+            }];
+            newest.latest.iter_mut().for_each(|latest| *latest = None);
+            newest.latest[source] = Some((write_version, hash));
+            stats.first += 1;
+            return true;
+        }
+
+        let previous = newest.latest[source];
+        if matches!(previous, Some((previous_version, _)) if write_version <= previous_version) {
+            stats.stale += 1;
+            return false;
+        }
+        newest.latest[source] = Some((write_version, hash));
+
+        let current = newest.applied.last().map(|version| version.hash);
+        let index = match newest.applied.iter().position(|version| version.hash == hash) {
+            Some(index) => index,
+            None => {
+                newest.applied.push(Version {
+                    hash,
+                    first_seen: now,
+                    delivered: bit,
+                });
+                stats.first += 1;
+                return true;
+            }
+        };
+
+        let version = &mut newest.applied[index];
+        if version.delivered & bit == 0 {
+            version.delivered |= bit;
+            let lag_ms = (now - version.first_seen).as_secs_f64() * 1000.0;
+            stats.avg_lag_ms = if stats.late == 0 {
+                lag_ms
+            } else {
+                stats.avg_lag_ms * 0.9 + lag_ms * 0.1
+            };
+            stats.late += 1;
+        }
+
+        // Contents applied earlier in the slot are only current again when this source had
+        // already delivered the current contents, i.e. its own ordering puts them after.
+        if Some(hash) != current && previous.map(|(_, previous_hash)| previous_hash) == current {
+            let version = newest.applied.remove(index);
+            newest.applied.push(version);
+            return true;
+        }
+        false
+    }
+
+    pub fn sources(&self) -> Vec<GeyserSourceInfo> {
+        self.labels
+            .iter()
+            .zip(&self.stats)
+            .map(|(label, stats)| {
+                let stats = stats.lock().unwrap();
+                GeyserSourceInfo {
+                    label: label.clone(),
+                    updates: stats.updates,
+                    first: stats.first,
+                    late: stats.late,
+                    avg_lag_ms: stats.avg_lag_ms,
+                    stale: stats.stale,
+                }
+            })
+            .collect()
+    }
+
+    fn report(&self, name: &str) {
+        for source in self.sources() {
+            info!(
+                "{} source {}: {} updates, {} first, {} late (avg {:.1}ms behind), {} stale",
+                name, source.label, source.updates, source.first, source.late, source.avg_lag_ms, source.stale
+            );
+        }
+    }
+}
+
+// The listener as seen by one source's stream: account updates go through the shared
+// deduplication before reaching the wrapped listener, and only applied ones are recorded.
+struct SourceListener<L: Listener> {
+    inner: Arc<L>,
+    sources: Arc<GeyserSources>,
+    source: usize,
+    recorder: Option<Recorder>,
+}
+
+impl<L: Listener> Listener for SourceListener<L> {
+    fn get_subscription_request(&self) -> SubscribeRequest {
+        self.inner.get_subscription_request()
+    }
+
+    fn handle_update(&self, update: UpdateOneof) {
+        if let UpdateOneof::Account(SubscribeUpdateAccount {
+            account: Some(account),
+            slot,
+            ..
+        }) = &update
+        {
+            if let Ok(pubkey) = Pubkey::try_from(account.pubkey.as_slice()) {
+                let hash = account_hash(account);
+                if !self.sources.observe(self.source, pubkey, *slot, account.write_version, hash) {
+                    return;
+                }
+            }
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&update);
+        }
+        self.inner.handle_update(update);
+    }
+
+    fn name(&self) -> String {
+        format!("{}@{}", self.inner.name(), self.sources.labels[self.source])
+    }
+
+    // Listeners with a runtime handle return the same one to every stream, so their
+    // subscription changes reach all endpoints.
+    fn subscription(&self) -> SubscriptionHandle {
+        self.inner.subscription()
+    }
+}
+
+// Identifies an account's contents independently of the validator that produced the update.
+pub fn account_hash(account: &SubscribeUpdateAccountInfo) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    account.lamports.hash(&mut hasher);
+    account.owner.hash(&mut hasher);
+    account.data.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Runs the listener on every endpoint; a single endpoint streams directly without
+// deduplication.
+pub async fn run_streams<L: Listener>(listener: Arc<L>, mut endpoints: Vec<GeyserEndpoint>, config: StreamConfig) {
+    if endpoints.len() == 1 {
+        run_stream(listener, endpoints.remove(0), config).await;
+        return;
+    }
+    let sources = Arc::new(GeyserSources::new(endpoints.iter().map(GeyserEndpoint::label).collect()));
+    run_redundant_streams(listener, endpoints, config, sources).await;
+}
+
+// Subscribes the same filters on every endpoint at once, applying each account version once
+// and logging per-source latency every minute.
+pub async fn run_redundant_streams<L: Listener>(
+    listener: Arc<L>,
+    endpoints: Vec<GeyserEndpoint>,
+    config: StreamConfig,
+    sources: Arc<GeyserSources>,
+) {
+    info!("{} streaming from {} redundant Geyser endpoints", listener.name(), endpoints.len());
+    let recorder = Recorder::from_env(&listener.name());
+    let streams = endpoints.into_iter().enumerate().map(|(source, endpoint)| {
+        let listener = Arc::new(SourceListener {
+            inner: Arc::clone(&listener),
+            sources: Arc::clone(&sources),
+            source,
+            recorder: recorder.clone(),
+        });
+        run_recorded_stream(listener, endpoint, config.clone(), None)
+    });
+
+    let report = async {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + REPORT_INTERVAL, REPORT_INTERVAL);
+        loop {
+            ticker.tick().await;
+            sources.report(&listener.name());
+        }
+    };
+    tokio::join!(futures::future::join_all(streams), report);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_each_account_version_once_and_tracks_which_source_was_first() {
+        let sources = GeyserSources::new(vec!["a".to_string(), "b".to_string()]);
+        let account = Pubkey::new_unique();
+
+        assert!(sources.observe(0, account, 10, 1, 100));
+        assert!(!sources.observe(1, account, 10, 7, 100));
+        assert!(sources.observe(1, account, 11, 8, 110));
+        assert!(!sources.observe(0, account, 10, 2, 101));
+        // The same contents from another validator, with an unrelated lower write version.
+        assert!(!sources.observe(0, account, 11, 3, 110));
+        // A later write in the same slot, seen first by a.
+        assert!(sources.observe(0, account, 11, 4, 111));
+        assert!(!sources.observe(1, account, 11, 9, 111));
+        assert!(sources.observe(1, Pubkey::new_unique(), 5, 1, 50));
+
+        let [a, b] = <[GeyserSourceInfo; 2]>::try_from(sources.sources()).unwrap();
+        assert_eq!((a.updates, a.first, a.late, a.stale), (4, 2, 1, 1));
+        assert_eq!((b.updates, b.first, b.late, b.stale), (4, 2, 2, 0));
+    }
+
+    #[test]
+    fn compares_write_versions_only_within_a_source() {
+        let sources = GeyserSources::new(vec!["a".to_string(), "b".to_string()]);
+        let account = Pubkey::new_unique();
+
+        // a's write versions are far ahead of b's; b's later write in the slot still applies.
+        assert!(sources.observe(0, account, 10, 1_000, 1));
+        assert!(sources.observe(1, account, 10, 5, 2));
+        assert!(!sources.observe(0, account, 10, 1_001, 2));
+
+        // An older write from b replayed after a newer one it delivered is stale.
+        assert!(!sources.observe(1, account, 10, 4, 1));
+
+        // b delivers the slot's second write before a delivers the first; a's first write
+        // applies, then its second restores the newest contents.
+        let account = Pubkey::new_unique();
+        assert!(sources.observe(1, account, 20, 6, 21));
+        assert!(sources.observe(0, account, 20, 1_002, 20));
+        assert!(sources.observe(0, account, 20, 1_003, 21));
+        assert!(!sources.observe(1, account, 20, 6, 21));
+
+        let [a, b] = <[GeyserSourceInfo; 2]>::try_from(sources.sources()).unwrap();
+        assert_eq!((a.updates, a.first, a.late, a.stale), (4, 2, 2, 0));
+        assert_eq!((b.updates, b.first, b.late, b.stale), (4, 2, 0, 2));
+    }
+
+    #[test]
+    fn hashes_account_contents_but_not_write_version() {
+        let account = SubscribeUpdateAccountInfo {
+            lamports: 1,
+            data: vec![1, 2, 3],
+            write_version: 5,
+            ..Default::default()
+        };
+        let replayed = SubscribeUpdateAccountInfo {
+            write_version: 900,
+            ..account.clone()
+        };
+        let changed = SubscribeUpdateAccountInfo {
+            data: vec![1, 2, 4],
+            ..account.clone()
+        };
+
+        assert_eq!(account_hash(&account), account_hash(&replayed));
+        assert_ne!(account_hash(&account), account_hash(&changed));
+    }
+}
@@ -0,0 +1,540 @@
+use crate::account_source::{AccountFilter, AccountSource};
+use crate::geyser_sources::MAX_SOURCES;
+use crate::price_listener::Listener;
+use crate::recorder::Recorder;
+use crate::rpc_pool::endpoint_label;
+use crate::utils::MULTIPLE_ACCOUNTS_BATCH_SIZE;
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+use futures::sink::SinkExt;
+use futures::stream::StreamExt;
+use once_cell::sync::OnceCell;
+use rand::Rng;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tonic::transport::{Certificate, ClientTlsConfig};
+use tracing::{error, info, warn};
+use yellowstone_grpc_client::{GeyserGrpcClient, Interceptor};
+use yellowstone_grpc_proto::geyser::{
+    subscribe_request_filter_accounts_filter::Filter,
+    subscribe_request_filter_accounts_filter_memcmp::Data, subscribe_update::UpdateOneof,
+    CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeRequestPing,
+    SubscribeUpdateAccount, SubscribeUpdateAccountInfo,
+};
+
+pub const DEFAULT_GEYSER_URL: &str = "https://solana-yellowstone-grpc.publicnode.com:443";
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+
+static GAP_FILL_SOURCE: OnceCell<Arc<dyn AccountSource>> = OnceCell::new();
+
+// Source every listener re-reads its subscribed accounts from after a reconnect.
+pub fn set_gap_fill_source(source: Arc<dyn AccountSource>) {
+    let _ = GAP_FILL_SOURCE.set(source);
+}
+
+// A Yellowstone endpoint and how to authenticate and talk to it.
+#[derive(Debug, Clone)]
+pub struct GeyserEndpoint {
+    pub url: String,
+    pub x_token: Option<String>,
+    // PEM file of the CA to trust instead of the system roots.
+    pub tls_ca_cert: Option<PathBuf>,
+    // Server name to verify when it differs from the URL host.
+    pub tls_domain: Option<String>,
+    pub max_message_bytes: usize,
+    pub connect_timeout: Duration,
+    // Bounds each request until the server responds; an open subscription stream is not cut.
+    pub request_timeout: Duration,
+    // Replaces the commitment of every listener's subscription when set.
+    pub commitment: Option<CommitmentLevel>,
+}
+
+impl GeyserEndpoint {
+    pub fn new(url: impl Into<String>) -> Self {
+        GeyserEndpoint {
+            url: url.into(),
+            x_token: None,
+            tls_ca_cert: None,
+            tls_domain: None,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(10),
+            commitment: None,
+        }
+    }
+
+    // GEYSER_URL, GEYSER_X_TOKEN, GEYSER_TLS_CA_CERT, GEYSER_TLS_DOMAIN, GEYSER_MAX_MESSAGE_MB,
+    // GEYSER_CONNECT_TIMEOUT_SECS, GEYSER_REQUEST_TIMEOUT_SECS and GEYSER_COMMITMENT.
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let mut endpoint = GeyserEndpoint::new(var("GEYSER_URL").unwrap_or_else(|| DEFAULT_GEYSER_URL.to_string()));
+        endpoint.x_token = var("GEYSER_X_TOKEN");
+        endpoint.tls_ca_cert = var("GEYSER_TLS_CA_CERT").map(PathBuf::from);
+        endpoint.tls_domain = var("GEYSER_TLS_DOMAIN");
+        if let Some(mb) = var("GEYSER_MAX_MESSAGE_MB") {
+            endpoint.max_message_bytes = mb.parse::<usize>()? * 1024 * 1024;
+        }
+        if let Some(secs) = var("GEYSER_CONNECT_TIMEOUT_SECS") {
+            endpoint.connect_timeout = Duration::from_secs(secs.parse()?);
+        }
+        if let Some(secs) = var("GEYSER_REQUEST_TIMEOUT_SECS") {
+            endpoint.request_timeout = Duration::from_secs(secs.parse()?);
+        }
+        if let Some(commitment) = var("GEYSER_COMMITMENT") {
+            endpoint.commitment = Some(parse_commitment(&commitment)?);
+        }
+        Ok(endpoint)
+    }
+
+    // GEYSER_URLS lists redundant endpoints sharing the settings above; GEYSER_X_TOKENS gives
+    // per-endpoint tokens in the same order, falling back to GEYSER_X_TOKEN.
+    pub fn all_from_env() -> Result<Vec<Self>> {
+        let endpoint = GeyserEndpoint::from_env()?;
+        let Some(urls) = std::env::var("GEYSER_URLS").ok().filter(|urls| !urls.trim().is_empty()) else {
+            return Ok(vec![endpoint]);
+        };
+        let x_tokens: Vec<Option<String>> = std::env::var("GEYSER_X_TOKENS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|token| Some(token.trim().to_string()).filter(|token| !token.is_empty()))
+            .collect();
+
+        let endpoints: Vec<GeyserEndpoint> = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .enumerate()
+            .map(|(i, url)| GeyserEndpoint {
+                url: url.to_string(),
+                x_token: x_tokens.get(i).cloned().flatten().or_else(|| endpoint.x_token.clone()),
+                ..endpoint.clone()
+            })
+            .collect();
+        if endpoints.len() > MAX_SOURCES {
+            bail!("at most {} Geyser endpoints are supported, got {}", MAX_SOURCES, endpoints.len());
+        }
+        Ok(endpoints)
+    }
+
+    pub fn label(&self) -> String {
+        endpoint_label(&self.url)
+    }
+
+    pub async fn connect(&self, config: &StreamConfig) -> Result<GeyserGrpcClient<impl Interceptor>> {
+        let mut builder = GeyserGrpcClient::build_from_shared(self.url.clone())?
+            .x_token(self.x_token.clone())?
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout)
+            .max_decoding_message_size(self.max_message_bytes)
+            .http2_keep_alive_interval(config.ping_interval)
+            .keep_alive_timeout(config.stale_timeout)
+            .keep_alive_while_idle(true)
+            .tcp_nodelay(true);
+
+        if self.url.starts_with("https://") {
+            let mut tls = ClientTlsConfig::new();
+            if let Some(path) = &self.tls_ca_cert {
+                tls = tls.ca_certificate(Certificate::from_pem(std::fs::read(path)?));
+            }
+            if let Some(domain) = &self.tls_domain {
+                tls = tls.domain_name(domain.clone());
+            }
+            builder = builder.tls_config(tls)?;
+        } else if self.tls_ca_cert.is_some() || self.tls_domain.is_some() {
+            bail!("TLS settings require an https:// Geyser URL, got {}", self.label());
+        }
+
+        Ok(builder.connect().await?)
+    }
+
+    fn subscription(&self, mut request: SubscribeRequest) -> SubscribeRequest {
+        if let Some(commitment) = self.commitment {
+            request.commitment = Some(commitment as i32);
+        }
+        request
+    }
+}
+
+pub fn parse_commitment(value: &str) -> Result<CommitmentLevel> {
+    match value.to_ascii_lowercase().as_str() {
+        "processed" => Ok(CommitmentLevel::Processed),
+        "confirmed" => Ok(CommitmentLevel::Confirmed),
+        "finalized" => Ok(CommitmentLevel::Finalized),
+        other => bail!("unknown commitment level {}", other),
+    }
+}
+
+// Subscription filters shared between the service and its stream. Yellowstone replaces the
+// whole filter set with every SubscribeRequest, so each change is pushed to the open stream as
+// the full updated request, and reconnects subscribe with the latest one.
+#[derive(Clone)]
+pub struct SubscriptionHandle {
+    request: Arc<watch::Sender<SubscribeRequest>>,
+}
+
+impl SubscriptionHandle {
+    pub fn new(request: SubscribeRequest) -> Self {
+        let (sender, _) = watch::channel(request);
+        SubscriptionHandle {
+            request: Arc::new(sender),
+        }
+    }
+
+    pub fn current(&self) -> SubscribeRequest {
+        self.request.borrow().clone()
+    }
+
+    // Applies a change and pushes it to open streams if `modify` reports one.
+    pub fn update(&self, modify: impl FnOnce(&mut SubscribeRequest) -> bool) -> bool {
+        self.request.send_if_modified(modify)
+    }
+
+    pub fn add_accounts(&self, filter: &str, accounts: &[Pubkey]) -> bool {
+        self.update(|request| {
+            let filter = request.accounts.entry(filter.to_string()).or_default();
+            let before = filter.account.len();
+            for account in accounts {
+                let account = account.to_string();
+                if !filter.account.contains(&account) {
+                    filter.account.push(account);
+                }
+            }
+            filter.account.len() != before
+        })
+    }
+
+    // A filter left without accounts or owners would match every account, so it is dropped.
+    pub fn remove_accounts(&self, filter: &str, accounts: &[Pubkey]) -> bool {
+        self.update(|request| {
+            let Some(entry) = request.accounts.get_mut(filter) else {
+                return false;
+            };
+            let before = entry.account.len();
+            let removed: Vec<String> = accounts.iter().map(Pubkey::to_string).collect();
+            entry.account.retain(|account| !removed.contains(account));
+            let changed = entry.account.len() != before;
+            if entry.account.is_empty() && entry.owner.is_empty() {
+                request.accounts.remove(filter);
+            }
+            changed
+        })
+    }
+
+    pub fn watch(&self) -> watch::Receiver<SubscribeRequest> {
+        self.request.subscribe()
+    }
+}
+
+#[derive(Clone)]
+pub struct StreamConfig {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    // Interval of both HTTP/2 keepalive pings and subscription pings on the stream.
+    pub ping_interval: Duration,
+    // A stream without any message (updates, pings or pongs) for this long is reconnected.
+    pub stale_timeout: Duration,
+    pub gap_fill: Option<Arc<dyn AccountSource>>,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            ping_interval: Duration::from_secs(10),
+            stale_timeout: Duration::from_secs(30),
+            gap_fill: None,
+        }
+    }
+}
+
+impl StreamConfig {
+    pub fn from_env() -> Self {
+        let mut config = StreamConfig {
+            gap_fill: GAP_FILL_SOURCE.get().cloned(),
+            ..Default::default()
+        };
+        if let Some(secs) = std::env::var("GEYSER_STALE_TIMEOUT_SECS").ok().and_then(|value| value.parse().ok()) {
+            config.stale_timeout = Duration::from_secs(secs);
+        }
+        config
+    }
+
+    // Exponential backoff with jitter: between half and all of initial_backoff * 2^(failures - 1).
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let ceiling = self
+            .initial_backoff
+            .saturating_mul(1 << failures.saturating_sub(1).min(16))
+            .min(self.max_backoff);
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+#[derive(Default)]
+struct Session {
+    subscribed: bool,
+    messages: usize,
+}
+
+// Keeps a listener subscribed forever: every dropped, failed or silent stream is reconnected
+// with jittered exponential backoff, and after a reconnect the subscribed accounts are re-read
+// from the gap-fill source so updates missed while disconnected are not lost.
+pub async fn run_stream<L: Listener>(listener: Arc<L>, endpoint: GeyserEndpoint, config: StreamConfig) {
+    let recorder = Recorder::from_env(&listener.name());
+    run_recorded_stream(listener, endpoint, config, recorder).await;
+}
+
+// run_stream with the recorder chosen by the caller, e.g. none when updates are recorded after
+// deduplication across several streams.
+pub async fn run_recorded_stream<L: Listener>(
+    listener: Arc<L>,
+    endpoint: GeyserEndpoint,
+    config: StreamConfig,
+    recorder: Option<Recorder>,
+) {
+    let subscription = listener.subscription();
+    let mut connected_before = false;
+    let mut failures: u32 = 0;
+
+    loop {
+        info!("{} connecting to: {}", listener.name(), endpoint.label());
+        let mut session = Session::default();
+        let result = run_session(
+            listener.as_ref(),
+            &endpoint,
+            &subscription,
+            &config,
+            recorder.as_ref(),
+            connected_before,
+            &mut session,
+        )
+        .await;
+        connected_before |= session.subscribed;
+
+        match result {
+            Ok(()) => warn!("{} stream closed by server after {} messages", listener.name(), session.messages),
+            Err(e) => error!("{} stream failed after {} messages: {:?}", listener.name(), session.messages, e),
+        }
+
+        failures = if session.subscribed && session.messages > 0 { 1 } else { failures + 1 };
+        let delay = config.backoff(failures);
+        warn!("{} reconnecting in {:?} (attempt {})", listener.name(), delay, failures);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+async fn run_session<L: Listener>(
+    listener: &L,
+    endpoint: &GeyserEndpoint,
+    subscription: &SubscriptionHandle,
+    config: &StreamConfig,
+    recorder: Option<&Recorder>,
+    reconnect: bool,
+    session: &mut Session,
+) -> Result<()> {
+    let mut client = endpoint.connect(config).await?;
+    let mut changes = subscription.watch();
+    let request = endpoint.subscription(changes.borrow_and_update().clone());
+    let (mut requests, mut stream) = client.subscribe_with_request(Some(request.clone())).await?;
+    session.subscribed = true;
+    info!("{} subscribed successfully, starting to listen for updates", listener.name());
+
+    // Updates arriving during the gap fill wait in the stream and are applied after it.
+    if reconnect {
+        if let Some(source) = &config.gap_fill {
+            match gap_fill(listener, &request, source.as_ref(), recorder).await {
+                Ok(filled) => info!("{} gap fill re-read {} accounts", listener.name(), filled),
+                Err(e) => error!("{} gap fill failed: {:?}", listener.name(), e),
+            }
+        }
+    }
+
+    let mut ping = tokio::time::interval_at(Instant::now() + config.ping_interval, config.ping_interval);
+    let mut ping_id = 0;
+    let mut last_message = Instant::now();
+
+    loop {
+        tokio::select! {
+            message = stream.next() => {
+                let update = match message {
+                    Some(Ok(update)) => update,
+                    Some(Err(status)) => return Err(anyhow!("stream error: {}", status)),
+                    None => return Ok(()),
+                };
+                last_message = Instant::now();
+                session.messages += 1;
+
+                match update.update_oneof {
+                    Some(UpdateOneof::Ping(_)) => {
+                        ping_id += 1;
+                        requests.send(ping_request(ping_id)).await?;
+                    }
+                    Some(UpdateOneof::Pong(_)) | None => {}
+                    Some(update) => {
+                        if let Some(recorder) = recorder {
+                            recorder.record(&update);
+                        }
+                        listener.handle_update(update);
+                    }
+                }
+            }
+            changed = changes.changed() => {
+                changed?;
+                let request = endpoint.subscription(changes.borrow_and_update().clone());
+                info!("{} updating subscription to {} account filters", listener.name(), request.accounts.len());
+                requests.send(request).await?;
+            }
+            _ = ping.tick() => {
+                ping_id += 1;
+                requests.send(ping_request(ping_id)).await?;
+            }
+            _ = tokio::time::sleep_until(last_message + config.stale_timeout) => {
+                return Err(anyhow!("no messages for {:?}", config.stale_timeout));
+            }
+        }
+    }
+}
+
+fn ping_request(id: i32) -> SubscribeRequest {
+    SubscribeRequest {
+        ping: Some(SubscribeRequestPing { id }),
+        ..Default::default()
+    }
+}
+
+// Re-reads every account matched by the request's account filters and feeds them through
+// handle_update as startup updates at the source's slot.
+pub async fn gap_fill<L: Listener + ?Sized>(
+    listener: &L,
+    request: &SubscribeRequest,
+    source: &dyn AccountSource,
+    recorder: Option<&Recorder>,
+) -> Result<usize> {
+    // Read the slot first: the accounts fetched afterwards are at least this recent.
+    let slot = source.get_slot().await?;
+    let mut filled = 0;
+
+    for filter in request.accounts.values() {
+        for (address, account) in filtered_accounts(filter, source).await? {
+            let update = account_update(address, &account, slot, true);
+            if let Some(recorder) = recorder {
+                recorder.record(&update);
+            }
+            listener.handle_update(update);
+            filled += 1;
+        }
+    }
+
+    Ok(filled)
+}
+
+async fn filtered_accounts(
+    filter: &SubscribeRequestFilterAccounts,
+    source: &dyn AccountSource,
+) -> Result<Vec<(Pubkey, Account)>> {
+    let owners = filter
+        .owner
+        .iter()
+        .map(|owner| Pubkey::from_str(owner).map_err(|e| anyhow!("invalid owner {}: {}", owner, e)))
+        .collect::<Result<Vec<_>>>()?;
+    let data_filters = account_filters(filter);
+
+    if filter.account.is_empty() {
+        let mut accounts = Vec::new();
+        for owner in &owners {
+            accounts.extend(source.get_program_accounts(owner, &data_filters).await?);
+        }
+        return Ok(accounts);
+    }
+
+    let addresses = filter
+        .account
+        .iter()
+        .map(|address| Pubkey::from_str(address).map_err(|e| anyhow!("invalid account {}: {}", address, e)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut accounts = Vec::new();
+    for chunk in addresses.chunks(MULTIPLE_ACCOUNTS_BATCH_SIZE) {
+        for (address, account) in chunk.iter().zip(source.get_multiple_accounts(chunk).await?) {
+            let Some(account) = account else {
+                continue;
+            };
+            if (owners.is_empty() || owners.contains(&account.owner))
+                && data_filters.iter().all(|filter| filter.matches(&account.data))
+            {
+                accounts.push((*address, account));
+            }
+        }
+    }
+    Ok(accounts)
+}
+
+// Memcmp and data size filters of a subscription; token account state filters have no RPC
+// equivalent and are left out.
+pub fn account_filters(filter: &SubscribeRequestFilterAccounts) -> Vec<AccountFilter> {
+    filter
+        .filters
+        .iter()
+        .filter_map(|filter| match &filter.filter {
+            Some(Filter::Memcmp(memcmp)) => {
+                let bytes = match &memcmp.data {
+                    Some(Data::Bytes(bytes)) => bytes.clone(),
+                    Some(Data::Base58(value)) => bs58::decode(value).into_vec().ok()?,
+                    Some(Data::Base64(value)) => base64::engine::general_purpose::STANDARD.decode(value).ok()?,
+                    None => return None,
+                };
+                Some(AccountFilter::Memcmp {
+                    offset: memcmp.offset as usize,
+                    bytes,
+                })
+            }
+            Some(Filter::Datasize(size)) => Some(AccountFilter::DataSize(*size)),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn account_update(address: Pubkey, account: &Account, slot: u64, is_startup: bool) -> UpdateOneof {
+    UpdateOneof::Account(SubscribeUpdateAccount {
+        account: Some(SubscribeUpdateAccountInfo {
+            pubkey: address.to_bytes().to_vec(),
+            lamports: account.lamports,
+            owner: account.owner.to_bytes().to_vec(),
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+            data: account.data.clone(),
+            write_version: 0,
+            txn_signature: None,
+        }),
+        slot,
+        is_startup,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_with_jitter_up_to_the_cap() {
+        let config = StreamConfig::default();
+        for failures in 1..=10 {
+            let ceiling = (config.initial_backoff * 2u32.pow(failures - 1)).min(config.max_backoff);
+            let delay = config.backoff(failures);
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "{:?} outside {:?}", delay, ceiling);
+        }
+        assert!(config.backoff(100) <= config.max_backoff);
+    }
+
+    #[test]
+    fn parses_commitment_levels() {
+        assert_eq!(parse_commitment("Confirmed").unwrap(), CommitmentLevel::Confirmed);
+        assert_eq!(parse_commitment("finalized").unwrap(), CommitmentLevel::Finalized);
+        assert!(parse_commitment("recent").is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use crate::kamino::{sf_to_f64, Obligation, Reserve};
+use crate::price_guard::{PriceQuote, PriceVerdict};
+use crate::price_listener::get_current_price;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PositionValue {
+    pub reserve_address: String,
+    pub token_mint: String,
+    pub amount: f64,
+    pub decimals: u32,
+    pub price: f64,
+    pub value: f64,
+    pub live_price: bool,
+    pub price_verdict: PriceVerdict,
+    pub liquidation_threshold: f64,
+    pub borrow_factor: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ObligationHealth {
+    pub deposited_value: f64,
+    pub borrowed_value: f64,
+    pub bf_adjusted_debt_value: f64,
+    pub allowed_borrow_value: f64,
+    pub unhealthy_borrow_value: f64,
+    pub ltv: f64,
+    pub liquidation_ltv: f64,
+    pub health_factor: f64,
+    pub underwater: bool,
+    pub prices_trusted: bool,
+    pub is_liquidatable: bool,
+    pub price_issues: Vec<String>,
+    pub missing_reserves: Vec<String>,
+    pub deposits: Vec<PositionValue>,
+    pub borrows: Vec<PositionValue>,
+}
+
+impl ObligationHealth {
+    pub fn largest_deposit(&self) -> Option<&PositionValue> {
+        self.deposits
+            .iter()
+            .max_by(|a, b| a.value.total_cmp(&b.value))
+    }
+
+    pub fn largest_borrow(&self) -> Option<&PositionValue> {
+        self.borrows
+            .iter()
+            .max_by(|a, b| a.value.total_cmp(&b.value))
+    }
+}
+
+pub fn live_price(mint: &Pubkey) -> Option<f64> {
+    get_current_price(&mint.to_string()).filter(|price| *price > 0.0)
+}
+
+pub fn compute_health<F>(
+    obligation: &Obligation,
+    reserves: &HashMap<Pubkey, Reserve>,
+    price_of: F,
+) -> ObligationHealth
+where
+    F: Fn(&Reserve) -> PriceQuote,
+{
+    let mut health = ObligationHealth::default();
+
+    for deposit in obligation.active_deposits() {
+        let reserve = match reserves.get(&deposit.deposit_reserve) {
+            Some(reserve) => reserve,
+            None => {
+                health.missing_reserves.push(deposit.deposit_reserve.to_string());
+                continue;
+            }
+        };
+
+        let quote = price_of(reserve);
+        let price = quote.price;
+        record_price_issue(&mut health, reserve, &quote);
+        let amount = deposit.deposited_amount as f64 * reserve.collateral_exchange_rate()
+            / 10f64.powi(reserve.decimals() as i32);
+        let value = amount * price;
+        let liquidation_threshold = reserve.config.liquidation_threshold_pct as f64 / 100.0;
+
+        health.deposited_value += value;
+        health.allowed_borrow_value += value * reserve.config.loan_to_value_pct as f64 / 100.0;
+        health.unhealthy_borrow_value += value * liquidation_threshold;
+
+        health.deposits.push(PositionValue {
+            reserve_address: deposit.deposit_reserve.to_string(),
+            token_mint: reserve.mint().to_string(),
+            amount,
+            decimals: reserve.decimals(),
+            price,
+            value,
+            live_price: quote.live,
+            price_verdict: quote.verdict,
+            liquidation_threshold,
+            borrow_factor: 1.0,
+        });
+    }
+
+    for borrow in obligation.active_borrows() {
+        let reserve = match reserves.get(&borrow.borrow_reserve) {
+            Some(reserve) => reserve,
+            None => {
+                health.missing_reserves.push(borrow.borrow_reserve.to_string());
+                continue;
+            }
+        };
+
+        let quote = price_of(reserve);
+        let price = quote.price;
+        record_price_issue(&mut health, reserve, &quote);
+        let obligation_rate = borrow.cumulative_borrow_rate_bsf.to_f64();
+        let accrued = if obligation_rate > 0.0 {
+            reserve.cumulative_borrow_rate() / obligation_rate
+        } else {
+            1.0
+        };
+        let amount = sf_to_f64(borrow.borrowed_amount_sf) * accrued.max(1.0)
+            / 10f64.powi(reserve.decimals() as i32);
+        let value = amount * price;
+        let borrow_factor = reserve.config.borrow_factor_pct.max(100) as f64 / 100.0;
+
+        health.borrowed_value += value;
+        health.bf_adjusted_debt_value += value * borrow_factor;
+
+        health.borrows.push(PositionValue {
+            reserve_address: borrow.borrow_reserve.to_string(),
+            token_mint: reserve.mint().to_string(),
+            amount,
+            decimals: reserve.decimals(),
+            price,
+            value,
+            live_price: quote.live,
+            price_verdict: quote.verdict,
+            liquidation_threshold: 0.0,
+            borrow_factor,
+        });
+    }
+
+    if health.deposited_value > 0.0 {
+        health.ltv = health.bf_adjusted_debt_value / health.deposited_value;
+        health.liquidation_ltv = health.unhealthy_borrow_value / health.deposited_value;
+    }
+
+    health.health_factor = if health.bf_adjusted_debt_value > 0.0 {
+        health.unhealthy_borrow_value / health.bf_adjusted_debt_value
+    } else {
+        f64::MAX
+    };
+
+    health.underwater = health.bf_adjusted_debt_value > 0.0
+        && health.bf_adjusted_debt_value >= health.unhealthy_borrow_value;
+    health.prices_trusted = health.price_issues.is_empty() && health.missing_reserves.is_empty();
+    health.is_liquidatable = health.underwater && health.prices_trusted;
+
+    health
+}
+
+fn record_price_issue(health: &mut ObligationHealth, reserve: &Reserve, quote: &PriceQuote) {
+    if !quote.verdict.is_ok() {
+        health
+            .price_issues
+            .push(format!("{}: {:?}", reserve.mint(), quote.verdict));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kamino::{f64_to_sf, BigFractionBytes};
+    use crate::price_guard::PriceVerdict;
+    use crate::test_fixtures::zeroed;
+
+    fn reserve(decimals: u64, liquidation_threshold_pct: u8, borrow_factor_pct: u64) -> Reserve {
+        let mut reserve = zeroed::<Reserve>();
+        reserve.liquidity.mint_pubkey = Pubkey::new_unique();
+        reserve.liquidity.mint_decimals = decimals;
+        reserve.config.loan_to_value_pct = 75;
+        reserve.config.liquidation_threshold_pct = liquidation_threshold_pct;
+        reserve.config.borrow_factor_pct = borrow_factor_pct;
+        reserve
+    }
+
+    fn rate(value: f64) -> BigFractionBytes {
+        BigFractionBytes {
+            value: [f64_to_sf(value) as u64, 0, 0, 0],
+            padding: [0; 2],
+        }
+    }
+
+    // 10 cSOL at an exchange rate of 1.2 SOL, against 800 USDC borrowed when the cumulative
+    // borrow rate was 1.0; the USDC reserve has since accrued to 1.05 and has a 125% borrow
+    // factor.
+    fn market() -> (Obligation, HashMap<Pubkey, Reserve>, Pubkey, Pubkey) {
+        let (sol_address, usdc_address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut sol = reserve(9, 80, 100);
+        sol.liquidity.available_amount = 1_200_000_000_000;
+        sol.collateral.mint_total_supply = 1_000_000_000_000;
+        let mut usdc = reserve(6, 85, 125);
+        usdc.liquidity.cumulative_borrow_rate_bsf = rate(1.05);
+
+        let mut obligation = zeroed::<Obligation>();
+        obligation.deposits[0].deposit_reserve = sol_address;
+        obligation.deposits[0].deposited_amount = 10_000_000_000;
+        obligation.borrows[0].borrow_reserve = usdc_address;
+        obligation.borrows[0].borrowed_amount_sf = f64_to_sf(800e6);
+        obligation.borrows[0].cumulative_borrow_rate_bsf = rate(1.0);
+
+        let reserves = HashMap::from([(sol_address, sol), (usdc_address, usdc)]);
+        (obligation, reserves, sol_address, usdc_address)
+    }
+
+    fn quote(price: f64, verdict: PriceVerdict) -> PriceQuote {
+        PriceQuote { price, live: true, verdict }
+    }
+
+    fn health_at(sol_price: f64, usdc_verdict: PriceVerdict) -> ObligationHealth {
+        let (obligation, reserves, _, _) = market();
+        compute_health(&obligation, &reserves, |reserve| match reserve.decimals() {
+            9 => quote(sol_price, PriceVerdict::Ok),
+            _ => quote(1.0, usdc_verdict),
+        })
+    }
+
+    #[test]
+    fn values_positions_with_exchange_rate_accrued_interest_and_borrow_factor() {
+        let health = health_at(100.0, PriceVerdict::Ok);
+
+        // 12 SOL at $100; 840 USDC owed, weighted to 1050 by the borrow factor.
+        assert!((health.deposits[0].amount - 12.0).abs() < 1e-9);
+        assert!((health.deposited_value - 1_200.0).abs() < 1e-9);
+        assert!((health.allowed_borrow_value - 900.0).abs() < 1e-9);
+        assert!((health.unhealthy_borrow_value - 960.0).abs() < 1e-9);
+        assert!((health.borrows[0].amount - 840.0).abs() < 1e-6);
+        assert!((health.borrowed_value - 840.0).abs() < 1e-6);
+        assert!((health.bf_adjusted_debt_value - 1_050.0).abs() < 1e-6);
+        assert!((health.ltv - 0.875).abs() < 1e-9);
+        assert!((health.liquidation_ltv - 0.8).abs() < 1e-9);
+        assert!((health.health_factor - 960.0 / 1_050.0).abs() < 1e-9);
+        assert!(health.underwater && health.prices_trusted && health.is_liquidatable);
+    }
+
+    #[test]
+    fn is_healthy_once_the_liquidation_threshold_covers_the_debt() {
+        // 12 SOL at $110 gives 1056 against 1050; at $109 it is 1046.4.
+        let healthy = health_at(110.0, PriceVerdict::Ok);
+        assert!((healthy.health_factor - 1_056.0 / 1_050.0).abs() < 1e-9);
+        assert!(!healthy.underwater && !healthy.is_liquidatable);
+        assert!(health_at(109.0, PriceVerdict::Ok).is_liquidatable);
+    }
+
+    #[test]
+    fn never_liquidates_on_untrusted_prices() {
+        let stale = health_at(100.0, PriceVerdict::Stale);
+        assert!(stale.underwater);
+        assert!(!stale.prices_trusted && !stale.is_liquidatable);
+        assert_eq!(stale.price_issues.len(), 1);
+        assert!(stale.price_issues[0].ends_with("Stale"));
+
+        let (obligation, mut reserves, _, usdc_address) = market();
+        reserves.remove(&usdc_address);
+        let missing = compute_health(&obligation, &reserves, |_| quote(50.0, PriceVerdict::Ok));
+        assert_eq!(missing.missing_reserves, vec![usdc_address.to_string()]);
+        assert_eq!(missing.health_factor, f64::MAX);
+        assert!(!missing.prices_trusted && !missing.is_liquidatable);
+    }
+}
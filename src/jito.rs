@@ -0,0 +1,404 @@
+use crate::rpc_pool::RpcPool;
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, VersionedMessage},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::VersionedTransaction,
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+pub const DEFAULT_BLOCK_ENGINE_URL: &str = "https://mainnet.block-engine.jito.wtf";
+pub const MAX_BUNDLE_TRANSACTIONS: usize = 5;
+
+const TIP_ACCOUNTS: [&str; 8] = [
+    "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+    "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+    "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+    "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+    "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+    "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+    "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+    "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleStatus {
+    Pending,
+    Landed { slot: u64 },
+    Failed,
+    Invalid,
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub enum SubmissionOutcome {
+    Bundle { bundle_id: String, slot: u64 },
+    Rpc { signatures: Vec<Signature> },
+}
+
+#[derive(Deserialize)]
+struct InflightBundleStatus {
+    status: String,
+    landed_slot: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct InflightBundleStatuses {
+    value: Vec<InflightBundleStatus>,
+}
+
+pub struct JitoSubmitter {
+    http: reqwest::Client,
+    block_engine_url: String,
+    tip_accounts: Vec<Pubkey>,
+    rpc_pool: Arc<RpcPool>,
+    pub status_poll_interval: Duration,
+    pub status_timeout: Duration,
+    pub fallback_to_rpc: bool,
+}
+
+impl JitoSubmitter {
+    pub fn new(block_engine_url: String, rpc_pool: Arc<RpcPool>) -> Self {
+        let tip_accounts = TIP_ACCOUNTS
+            .iter()
+            .filter_map(|account| Pubkey::from_str(account).ok())
+            .collect();
+
+        JitoSubmitter {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+            block_engine_url: block_engine_url.trim_end_matches('/').to_string(),
+            tip_accounts,
+            rpc_pool,
+            status_poll_interval: Duration::from_millis(500),
+            status_timeout: Duration::from_secs(15),
+            fallback_to_rpc: true,
+        }
+    }
+
+    pub fn from_env(rpc_pool: Arc<RpcPool>) -> Self {
+        let block_engine_url = std::env::var("JITO_BLOCK_ENGINE_URL")
+            .unwrap_or_else(|_| DEFAULT_BLOCK_ENGINE_URL.to_string());
+        Self::new(block_engine_url, rpc_pool)
+    }
+
+    pub async fn refresh_tip_accounts(&mut self) -> Result<()> {
+        let result = self.call("getTipAccounts", json!([])).await?;
+        let accounts: Vec<String> = serde_json::from_value(result)?;
+        let tip_accounts: Vec<Pubkey> = accounts
+            .iter()
+            .filter_map(|account| Pubkey::from_str(account).ok())
+            .collect();
+
+        if tip_accounts.is_empty() {
+            return Err(anyhow!("block engine returned no tip accounts"));
+        }
+
+        info!("Loaded {} Jito tip accounts from block engine", tip_accounts.len());
+        self.tip_accounts = tip_accounts;
+        Ok(())
+    }
+
+    pub fn tip_instruction(&self, payer: &Pubkey, tip_lamports: u64) -> Result<Instruction> {
+        let tip_account = self
+            .tip_accounts
+            .choose(&mut rand::thread_rng())
+            .ok_or_else(|| anyhow!("no Jito tip accounts configured"))?;
+        Ok(system_instruction::transfer(payer, tip_account, tip_lamports))
+    }
+
+    pub fn build_tip_transaction(
+        &self,
+        payer: &Keypair,
+        tip_lamports: u64,
+        blockhash: Hash,
+    ) -> Result<VersionedTransaction> {
+        let instruction = self.tip_instruction(&payer.pubkey(), tip_lamports)?;
+        let message = v0::Message::try_compile(&payer.pubkey(), &[instruction], &[], blockhash)?;
+        Ok(VersionedTransaction::try_new(
+            VersionedMessage::V0(message),
+            &[payer],
+        )?)
+    }
+
+    pub async fn send_bundle(&self, transactions: &[VersionedTransaction]) -> Result<String> {
+        if transactions.is_empty() || transactions.len() > MAX_BUNDLE_TRANSACTIONS {
+            return Err(anyhow!(
+                "bundle must contain between 1 and {} transactions, got {}",
+                MAX_BUNDLE_TRANSACTIONS,
+                transactions.len()
+            ));
+        }
+
+        let encoded = transactions
+            .iter()
+            .map(|tx| Ok(base64::engine::general_purpose::STANDARD.encode(bincode::serialize(tx)?)))
+            .collect::<Result<Vec<String>>>()?;
+
+        let result = self
+            .call("sendBundle", json!([encoded, { "encoding": "base64" }]))
+            .await?;
+
+        result
+            .as_str()
+            .map(|bundle_id| bundle_id.to_string())
+            .ok_or_else(|| anyhow!("unexpected sendBundle result: {}", result))
+    }
+
+    pub async fn get_bundle_status(&self, bundle_id: &str) -> Result<BundleStatus> {
+        let result = self
+            .call("getInflightBundleStatuses", json!([[bundle_id]]))
+            .await?;
+        let statuses: InflightBundleStatuses = serde_json::from_value(result)?;
+
+        let status = match statuses.value.first() {
+            Some(status) => status,
+            None => return Ok(BundleStatus::Unknown),
+        };
+
+        Ok(match status.status.as_str() {
+            "Pending" => BundleStatus::Pending,
+            "Landed" => BundleStatus::Landed {
+                slot: status.landed_slot.unwrap_or_default(),
+            },
+            "Failed" => BundleStatus::Failed,
+            "Invalid" => BundleStatus::Invalid,
+            _ => BundleStatus::Unknown,
+        })
+    }
+
+    pub async fn wait_for_bundle(&self, bundle_id: &str) -> Result<BundleStatus> {
+        let started = Instant::now();
+
+        loop {
+            match self.get_bundle_status(bundle_id).await {
+                Ok(BundleStatus::Pending) | Ok(BundleStatus::Unknown) => {}
+                Ok(status) => return Ok(status),
+                Err(e) => warn!("Failed to poll Jito bundle {}: {:?}", bundle_id, e),
+            }
+
+            if started.elapsed() >= self.status_timeout {
+                return Ok(BundleStatus::Pending);
+            }
+            tokio::time::sleep(self.status_poll_interval).await;
+        }
+    }
+
+    pub async fn submit(
+        &self,
+        transactions: Vec<VersionedTransaction>,
+        payer: &Keypair,
+        tip_lamports: u64,
+        blockhash: Hash,
+    ) -> Result<SubmissionOutcome> {
+        let mut bundle = transactions.clone();
+        bundle.push(self.build_tip_transaction(payer, tip_lamports, blockhash)?);
+
+        match self.send_bundle(&bundle).await {
+            Ok(bundle_id) => {
+                info!(
+                    "Submitted Jito bundle {} ({} transactions, tip {} lamports)",
+                    bundle_id,
+                    bundle.len(),
+                    tip_lamports
+                );
+
+                match self.wait_for_bundle(&bundle_id).await? {
+                    BundleStatus::Landed { slot } => {
+                        info!("Jito bundle {} landed in slot {}", bundle_id, slot);
+                        return Ok(SubmissionOutcome::Bundle { bundle_id, slot });
+                    }
+                    status => {
+                        warn!("Jito bundle {} did not land: {:?}", bundle_id, status);
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to send Jito bundle: {:?}", e);
+            }
+        }
+
+        if !self.fallback_to_rpc {
+            return Err(anyhow!("Jito bundle did not land and RPC fallback is disabled"));
+        }
+
+        warn!("Falling back to RPC submission for {} transactions", transactions.len());
+        self.send_via_rpc(&transactions).await
+    }
+
+    pub async fn send_via_rpc(&self, transactions: &[VersionedTransaction]) -> Result<SubmissionOutcome> {
+        let config = RpcSendTransactionConfig {
+            skip_preflight: true,
+            max_retries: Some(0),
+            ..Default::default()
+        };
+
+        let mut signatures = Vec::with_capacity(transactions.len());
+        for tx in transactions {
+            let signature = self
+                .rpc_pool
+                .call_async(|client| async move { client.send_transaction_with_config(tx, config).await })
+                .await?;
+            info!("Sent transaction via RPC: {}", signature);
+            signatures.push(signature);
+        }
+
+        Ok(SubmissionOutcome::Rpc { signatures })
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let url = format!("{}/api/v1/bundles", self.block_engine_url);
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        let response: Value = self
+            .http
+            .post(&url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if let Some(err) = response.get("error") {
+            return Err(anyhow!("block engine {} error: {}", method, err));
+        }
+
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("block engine {} returned no result", method))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rpc::{MockRpc, MockRpcHandle};
+    use crate::test_fixtures::rpc_pool;
+    use std::sync::Mutex;
+
+    // Serves the block engine and the RPC fallback from one stub. Bundle statuses are handed
+    // out in order, the last one repeating.
+    async fn stub(bundle: std::result::Result<&'static str, &'static str>, statuses: Vec<Value>) -> MockRpcHandle {
+        let statuses = Mutex::new(statuses);
+        MockRpc::new(move |method, params| match method {
+            "sendBundle" => bundle
+                .map(|bundle_id| json!(bundle_id))
+                .map_err(|message| json!({"code": -32000, "message": message})),
+            "getInflightBundleStatuses" => {
+                let mut statuses = statuses.lock().unwrap();
+                let status = if statuses.len() > 1 { statuses.remove(0) } else { statuses[0].clone() };
+                Ok(json!({"context": {"slot": 1}, "value": [status]}))
+            }
+            "sendTransaction" => {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(params[0].as_str().unwrap())
+                    .unwrap();
+                let transaction: VersionedTransaction = bincode::deserialize(&data).unwrap();
+                Ok(json!(transaction.signatures[0].to_string()))
+            }
+            _ => Err(json!({"code": -32601, "message": "method not found"})),
+        })
+        .serve()
+        .await
+        .unwrap()
+    }
+
+    fn submitter(url: &str) -> JitoSubmitter {
+        let mut submitter = JitoSubmitter::new(format!("{}/", url), rpc_pool(url));
+        submitter.status_poll_interval = Duration::from_millis(10);
+        submitter.status_timeout = Duration::from_secs(2);
+        submitter
+    }
+
+    fn transaction(payer: &Keypair) -> VersionedTransaction {
+        let instruction = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+        let message = v0::Message::try_compile(&payer.pubkey(), &[instruction], &[], Hash::default()).unwrap();
+        VersionedTransaction::try_new(VersionedMessage::V0(message), &[payer]).unwrap()
+    }
+
+    fn status(status: &str, landed_slot: Option<u64>) -> Value {
+        json!({"bundle_id": "bundle-1", "status": status, "landed_slot": landed_slot})
+    }
+
+    #[tokio::test]
+    async fn reports_the_slot_a_bundle_landed_in() {
+        let stub = stub(Ok("bundle-1"), vec![status("Pending", None), status("Landed", Some(321))]).await;
+        let payer = Keypair::new();
+        let outcome = submitter(&stub.url)
+            .submit(vec![transaction(&payer)], &payer, 5_000, Hash::default())
+            .await
+            .unwrap();
+
+        match outcome {
+            SubmissionOutcome::Bundle { bundle_id, slot } => assert_eq!((bundle_id.as_str(), slot), ("bundle-1", 321)),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+        let requests = stub.requests();
+        assert_eq!(requests[0].0, "sendBundle");
+        assert_eq!(requests[0].1[0].as_array().unwrap().len(), 2);
+        assert_eq!(requests[0].1[1]["encoding"], "base64");
+        assert_eq!(requests.iter().filter(|(method, _)| method == "getInflightBundleStatuses").count(), 2);
+        assert!(!stub.methods().contains(&"sendTransaction".to_string()));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_rpc_when_a_bundle_fails_or_is_rejected() {
+        let payer = Keypair::new();
+        let transactions = vec![transaction(&payer)];
+
+        let failed = stub(Ok("bundle-1"), vec![status("Failed", None)]).await;
+        match submitter(&failed.url)
+            .submit(transactions.clone(), &payer, 5_000, Hash::default())
+            .await
+            .unwrap()
+        {
+            SubmissionOutcome::Rpc { signatures } => assert_eq!(signatures, vec![transactions[0].signatures[0]]),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+        assert_eq!(failed.methods(), ["sendBundle", "getInflightBundleStatuses", "sendTransaction"]);
+
+        let rejected = stub(Err("bundle rejected"), vec![status("Pending", None)]).await;
+        let outcome = submitter(&rejected.url)
+            .submit(transactions.clone(), &payer, 5_000, Hash::default())
+            .await
+            .unwrap();
+        assert!(matches!(outcome, SubmissionOutcome::Rpc { .. }));
+        assert_eq!(rejected.methods(), ["sendBundle", "sendTransaction"]);
+
+        let mut no_fallback = submitter(&failed.url);
+        no_fallback.fallback_to_rpc = false;
+        assert!(no_fallback.submit(transactions, &payer, 5_000, Hash::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_to_tip_without_tip_accounts() {
+        let mut submitter = submitter("http://127.0.0.1:1");
+        let payer = Keypair::new();
+        assert!(submitter.build_tip_transaction(&payer, 5_000, Hash::default()).is_ok());
+
+        submitter.tip_accounts.clear();
+        assert!(submitter.tip_instruction(&payer.pubkey(), 5_000).is_err());
+        assert!(submitter.build_tip_transaction(&payer, 5_000, Hash::default()).is_err());
+    }
+}
@@ -1,7 +1,20 @@
+use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
+use std::ops::RangeInclusive;
 use borsh::{BorshDeserialize, BorshSerialize};
 
-#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub const FRACTION_ONE: f64 = (1u128 << 60) as f64;
+
+pub fn sf_to_f64(value: u128) -> f64 {
+    value as f64 / FRACTION_ONE
+}
+
+#[cfg(test)]
+pub fn f64_to_sf(value: f64) -> u128 {
+    (value * FRACTION_ONE) as u128
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, Default)]
 pub struct LastUpdate {
     pub slot: u64,
     pub stale: u8,
@@ -9,18 +22,7 @@ pub struct LastUpdate {
     pub placeholder: [u8; 6],
 }
 
-impl Default for LastUpdate {
-    fn default() -> Self {
-        Self {
-            slot: 0,
-            stale: 0,
-            price_status: 0,
-            placeholder: [0; 6],
-        }
-    }
-}
-
-#[derive(BorshDeserialize, BorshSerialize, Debug)]
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
 pub struct ObligationCollateral {
     pub deposit_reserve: Pubkey,
     pub deposited_amount: u64,
@@ -29,13 +31,24 @@ pub struct ObligationCollateral {
     pub padding: [u64; 9],
 }
 
-#[derive(BorshDeserialize, BorshSerialize, Debug)]
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
 pub struct BigFractionBytes {
     pub value: [u64; 4],
     pub padding: [u64; 2],
 }
 
-#[derive(BorshDeserialize, BorshSerialize, Debug)]
+impl BigFractionBytes {
+    pub fn to_f64(&self) -> f64 {
+        self.value
+            .iter()
+            .enumerate()
+            .map(|(i, limb)| *limb as f64 * 2f64.powi(64 * i as i32))
+            .sum::<f64>()
+            / FRACTION_ONE
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
 pub struct ObligationLiquidity {
     pub borrow_reserve: Pubkey,
     pub cumulative_borrow_rate_bsf: BigFractionBytes,
@@ -47,7 +60,7 @@ pub struct ObligationLiquidity {
     pub padding2: [u64; 7],
 }
 
-#[derive(BorshDeserialize, BorshSerialize, Debug, Default)]
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, Default)]
 pub struct ObligationOrder {
     pub condition_threshold_sf: u128,
     pub opportunity_parameter_sf: u128,
@@ -59,7 +72,96 @@ pub struct ObligationOrder {
     pub padding2: [u128; 5],
 }
 
-#[derive(BorshDeserialize, BorshSerialize, Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConditionType {
+    Never,
+    UserLtvAbove,
+    UserLtvBelow,
+    DebtCollPriceRatioAbove,
+    DebtCollPriceRatioBelow,
+}
+
+impl ConditionType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ConditionType::Never),
+            1 => Some(ConditionType::UserLtvAbove),
+            2 => Some(ConditionType::UserLtvBelow),
+            3 => Some(ConditionType::DebtCollPriceRatioAbove),
+            4 => Some(ConditionType::DebtCollPriceRatioBelow),
+            _ => None,
+        }
+    }
+
+    pub fn is_supported_by(&self, obligation: &Obligation) -> bool {
+        match self {
+            ConditionType::Never | ConditionType::UserLtvAbove | ConditionType::UserLtvBelow => true,
+            ConditionType::DebtCollPriceRatioAbove | ConditionType::DebtCollPriceRatioBelow => {
+                obligation.is_single_debt_single_coll()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OpportunityType {
+    DeleverageSingleDebtAmount,
+    DeleverageAllDebt,
+}
+
+impl OpportunityType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(OpportunityType::DeleverageSingleDebtAmount),
+            1 => Some(OpportunityType::DeleverageAllDebt),
+            _ => None,
+        }
+    }
+
+    pub fn is_supported_by(&self, obligation: &Obligation) -> bool {
+        match self {
+            OpportunityType::DeleverageSingleDebtAmount => obligation.single_debt().is_some(),
+            OpportunityType::DeleverageAllDebt => true,
+        }
+    }
+}
+
+impl ObligationOrder {
+    pub fn is_active(&self) -> bool {
+        self.condition_type != 0
+    }
+
+    pub fn condition_type(&self) -> Option<ConditionType> {
+        ConditionType::from_u8(self.condition_type)
+    }
+
+    pub fn opportunity_type(&self) -> Option<OpportunityType> {
+        OpportunityType::from_u8(self.opportunity_type)
+    }
+
+    pub fn condition_threshold(&self) -> f64 {
+        sf_to_f64(self.condition_threshold_sf)
+    }
+
+    pub fn opportunity_parameter(&self) -> f64 {
+        sf_to_f64(self.opportunity_parameter_sf)
+    }
+
+    pub fn execution_bonus_rate_range(&self) -> RangeInclusive<f64> {
+        self.min_execution_bonus_bps as f64 / 10_000.0..=self.max_execution_bonus_bps as f64 / 10_000.0
+    }
+
+    pub fn is_supported_by(&self, obligation: &Obligation) -> bool {
+        match (self.condition_type(), self.opportunity_type()) {
+            (Some(condition), Some(opportunity)) => {
+                condition.is_supported_by(obligation) && opportunity.is_supported_by(obligation)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
 pub struct Obligation {
     pub tag: u64,
     pub last_update: LastUpdate,
@@ -110,99 +212,266 @@ impl Obligation {
         result.dedup();
         result
     }
+
+    pub fn active_deposits(&self) -> impl Iterator<Item = &ObligationCollateral> {
+        self.deposits
+            .iter()
+            .filter(|deposit| deposit.deposit_reserve != Pubkey::default())
+    }
+
+    pub fn active_borrows(&self) -> impl Iterator<Item = &ObligationLiquidity> {
+        self.borrows
+            .iter()
+            .filter(|borrow| borrow.borrow_reserve != Pubkey::default())
+    }
+
+    pub fn has_active_borrows(&self) -> bool {
+        self.active_borrows().any(|borrow| borrow.borrowed_amount_sf > 0)
+    }
+
+    pub fn single_collateral(&self) -> Option<&ObligationCollateral> {
+        let mut deposits = self.active_deposits();
+        let first = deposits.next()?;
+        deposits.next().is_none().then_some(first)
+    }
+
+    pub fn single_debt(&self) -> Option<&ObligationLiquidity> {
+        let mut borrows = self.active_borrows();
+        let first = borrows.next()?;
+        borrows.next().is_none().then_some(first)
+    }
+
+    pub fn is_single_debt_single_coll(&self) -> bool {
+        self.single_collateral().is_some() && self.single_debt().is_some()
+    }
+
+    pub fn is_marked_for_deleveraging(&self) -> bool {
+        self.autodeleverage_margin_call_started_timestamp != 0
+    }
+
+    pub fn active_orders(&self) -> impl Iterator<Item = (usize, &ObligationOrder)> {
+        self.orders.iter().enumerate().filter(|(_, order)| order.is_active())
+    }
 }
 
-#[derive(Debug)]
-pub struct ReserveData {
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct ReserveLiquidity {
     pub mint_pubkey: Pubkey,
-    pub decimals: u8,
-    pub market_price: u128,
-    pub oracle_pubkey: Pubkey,
-    pub token_name: String,
+    pub supply_vault: Pubkey,
+    pub fee_vault: Pubkey,
+    pub available_amount: u64,
+    pub borrowed_amount_sf: u128,
+    pub market_price_sf: u128,
+    pub market_price_last_updated_ts: u64,
+    pub mint_decimals: u64,
+    pub deposit_limit_crossed_timestamp: u64,
+    pub borrow_limit_crossed_timestamp: u64,
+    pub cumulative_borrow_rate_bsf: BigFractionBytes,
+    pub accumulated_protocol_fees_sf: u128,
+    pub accumulated_referrer_fees_sf: u128,
+    pub pending_referrer_fees_sf: u128,
+    pub absolute_referral_rate_sf: u128,
+    pub token_program: Pubkey,
+    pub padding2: [u64; 51],
+    pub padding3: [u128; 32],
 }
 
-impl ReserveData {
-    pub fn try_parse_from_account_data(data: &[u8]) -> Option<Self> {
-        if data.len() < 200 {
-            return None;
-        }
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct ReserveCollateral {
+    pub mint_pubkey: Pubkey,
+    pub mint_total_supply: u64,
+    pub supply_vault: Pubkey,
+    pub padding1: [u128; 32],
+    pub padding2: [u128; 32],
+}
 
-        let data = &data[8..];
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct ReserveFees {
+    pub borrow_fee_sf: u64,
+    pub flash_loan_fee_sf: u64,
+    pub padding: [u8; 8],
+}
 
-        let liquidity_offset = 48;
-        
-        if data.len() < liquidity_offset + 200 {
-            return None;
-        }
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct CurvePoint {
+    pub utilization_rate_bps: u32,
+    pub borrow_rate_bps: u32,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct BorrowRateCurve {
+    pub points: [CurvePoint; 11],
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct PriceHeuristic {
+    pub lower: u64,
+    pub upper: u64,
+    pub exp: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct ScopeConfiguration {
+    pub price_feed: Pubkey,
+    pub price_chain: [u16; 4],
+    pub twap_chain: [u16; 4],
+}
 
-        let mint_bytes = &data[liquidity_offset..liquidity_offset + 32];
-        let mint_pubkey = Pubkey::new_from_array(
-            mint_bytes.try_into().ok()?
-        );
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct SwitchboardConfiguration {
+    pub price_aggregator: Pubkey,
+    pub twap_aggregator: Pubkey,
+}
 
-        let decimals = data.get(liquidity_offset + 32)?;
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct PythConfiguration {
+    pub price: Pubkey,
+}
 
-        let oracle_offset = liquidity_offset + 32 + 1 + 32 + 32;
-        if data.len() < oracle_offset + 32 {
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct TokenInfo {
+    pub name: [u8; 32],
+    pub heuristic: PriceHeuristic,
+    pub max_twap_divergence_bps: u64,
+    pub max_age_price_seconds: u64,
+    pub max_age_twap_seconds: u64,
+    pub scope_configuration: ScopeConfiguration,
+    pub switchboard_configuration: SwitchboardConfiguration,
+    pub pyth_configuration: PythConfiguration,
+    pub block_price_usage: u8,
+    pub reserved: [u8; 7],
+    pub padding: [u64; 19],
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct WithdrawalCaps {
+    pub config_capacity: i64,
+    pub current_total: u64,
+    pub last_interval_start_timestamp: u64,
+    pub config_interval_length_seconds: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct ReserveConfig {
+    pub status: u8,
+    pub asset_tier: u8,
+    pub host_fixed_interest_rate_bps: u16,
+    pub reserved_2: [u8; 2],
+    pub reserved_3: [u8; 8],
+    pub protocol_take_rate_pct: u8,
+    pub protocol_liquidation_fee_pct: u8,
+    pub loan_to_value_pct: u8,
+    pub liquidation_threshold_pct: u8,
+    pub min_liquidation_bonus_bps: u16,
+    pub max_liquidation_bonus_bps: u16,
+    pub bad_debt_liquidation_bonus_bps: u16,
+    pub deleveraging_margin_call_period_secs: u64,
+    pub deleveraging_threshold_decrease_bps_per_day: u64,
+    pub fees: ReserveFees,
+    pub borrow_rate_curve: BorrowRateCurve,
+    pub borrow_factor_pct: u64,
+    pub deposit_limit: u64,
+    pub borrow_limit: u64,
+    pub token_info: TokenInfo,
+    pub deposit_withdrawal_cap: WithdrawalCaps,
+    pub debt_withdrawal_cap: WithdrawalCaps,
+    pub elevation_groups: [u8; 20],
+    pub disable_usage_as_coll_outside_emode: u8,
+    pub utilization_limit_block_borrowing_above_pct: u8,
+    pub autodeleverage_enabled: u8,
+    pub reserved_1: [u8; 1],
+    pub borrow_limit_outside_elevation_group: u64,
+    pub borrow_limit_against_this_collateral_in_elevation_group: [u64; 32],
+    pub deleveraging_bonus_increase_bps_per_day: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct Reserve {
+    pub version: u64,
+    pub last_update: LastUpdate,
+    pub lending_market: Pubkey,
+    pub farm_collateral: Pubkey,
+    pub farm_debt: Pubkey,
+    pub liquidity: ReserveLiquidity,
+    pub reserve_liquidity_padding: [u64; 150],
+    pub collateral: ReserveCollateral,
+    pub reserve_collateral_padding: [u64; 150],
+    pub config: ReserveConfig,
+    pub config_padding: [u64; 116],
+    pub borrowed_amount_outside_elevation_group: u64,
+    pub borrowed_amounts_against_this_reserve_in_elevation_groups: [u64; 32],
+    pub padding: [u64; 207],
+}
+
+impl Reserve {
+    pub fn try_from_account_data(data: &[u8]) -> Option<Self> {
+        if data.len() <= 8 {
             return None;
         }
-        
-        let oracle_bytes = &data[oracle_offset..oracle_offset + 32];
-        let oracle_pubkey = Pubkey::new_from_array(
-            oracle_bytes.try_into().ok()?
-        );
-
-        let price_offset = oracle_offset + 32 + 8 + 16 + 16;
-        let market_price = if data.len() >= price_offset + 16 {
-            u128::from_le_bytes(
-                data[price_offset..price_offset + 16].try_into().unwrap_or([0; 16])
-            )
-        } else {
-            0
-        };
-
-        let token_name = Self::extract_token_name_from_data(data).unwrap_or_else(|| {
-            Self::generate_name_from_mint(&mint_pubkey.to_string())
-        });
-
-        Some(ReserveData {
-            mint_pubkey,
-            decimals: *decimals,
-            market_price,
-            oracle_pubkey,
-            token_name,
-        })
-    }
-
-    fn extract_token_name_from_data(data: &[u8]) -> Option<String> {
-        for chunk in data.chunks(32) {
-            if chunk.len() == 32 {
-                if let Some(null_pos) = chunk.iter().position(|&b| b == 0) {
-                    if null_pos > 0 && null_pos < 20 {
-                        if let Ok(name) = std::str::from_utf8(&chunk[..null_pos]) {
-                            if name.chars().all(|c| c.is_alphanumeric() || ".-_".contains(c)) && name.len() >= 2 {
-                                return Some(name.to_string());
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        None
-    }
-
-    fn generate_name_from_mint(mint: &str) -> String {
-        match mint {
-            "So11111111111111111111111111111111111111112" => "SOL".to_string(),
-            "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v" => "USDC".to_string(),
-            "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB" => "USDT".to_string(),
-            "mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So" => "mSOL".to_string(),
-            "7dHbWXmci3dT8UFYWYZweBLXgycu7Y3iL6trKn1Y7ARj" => "stSOL".to_string(),
-            "bSo13r4TkiE4KumL71LsHTPpL2euBYLFx6h9HP3piy1" => "bSOL".to_string(),
-            "J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn" => "jitoSOL".to_string(),
-            "7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs" => "ETH".to_string(),
-            "9n4nbM75f5Ui33ZbPYXn59EwSgE8CGsHtAeTH5YFeJ9E" => "BTC".to_string(),
-            _ => format!("TOKEN_{}", &mint[..8])
+        Reserve::deserialize(&mut &data[8..]).ok()
+    }
+
+    pub fn mint(&self) -> Pubkey {
+        self.liquidity.mint_pubkey
+    }
+
+    pub fn decimals(&self) -> u32 {
+        self.liquidity.mint_decimals as u32
+    }
+
+    pub fn market_price(&self) -> f64 {
+        sf_to_f64(self.liquidity.market_price_sf)
+    }
+
+    pub fn total_liquidity(&self) -> f64 {
+        self.liquidity.available_amount as f64
+            + sf_to_f64(self.liquidity.borrowed_amount_sf)
+            - sf_to_f64(self.liquidity.accumulated_protocol_fees_sf)
+            - sf_to_f64(self.liquidity.accumulated_referrer_fees_sf)
+            - sf_to_f64(self.liquidity.pending_referrer_fees_sf)
+    }
+
+    pub fn collateral_exchange_rate(&self) -> f64 {
+        let total_liquidity = self.total_liquidity();
+        if self.collateral.mint_total_supply == 0 || total_liquidity <= 0.0 {
+            return 1.0;
         }
+        total_liquidity / self.collateral.mint_total_supply as f64
     }
-}
\ No newline at end of file
+
+    pub fn cumulative_borrow_rate(&self) -> f64 {
+        self.liquidity.cumulative_borrow_rate_bsf.to_f64()
+    }
+
+    pub fn pyth_oracle(&self) -> Option<Pubkey> {
+        non_default(self.config.token_info.pyth_configuration.price)
+    }
+
+    pub fn switchboard_price_oracle(&self) -> Option<Pubkey> {
+        non_default(self.config.token_info.switchboard_configuration.price_aggregator)
+    }
+
+    pub fn switchboard_twap_oracle(&self) -> Option<Pubkey> {
+        non_default(self.config.token_info.switchboard_configuration.twap_aggregator)
+    }
+
+    pub fn scope_prices(&self) -> Option<Pubkey> {
+        non_default(self.config.token_info.scope_configuration.price_feed)
+    }
+
+    pub fn oracle_accounts(&self) -> Vec<Pubkey> {
+        [
+            self.pyth_oracle(),
+            self.switchboard_price_oracle(),
+            self.switchboard_twap_oracle(),
+            self.scope_prices(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+fn non_default(pubkey: Pubkey) -> Option<Pubkey> {
+    (pubkey != Pubkey::default()).then_some(pubkey)
+}
@@ -0,0 +1,404 @@
+use crate::kamino::{Obligation, Reserve};
+use anyhow::{anyhow, Result};
+use borsh::BorshSerialize;
+use solana_sdk::{
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    pubkey,
+    pubkey::Pubkey,
+    system_program,
+    sysvar,
+};
+use std::collections::HashMap;
+
+pub const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+#[derive(BorshSerialize)]
+struct LiquidateArgs {
+    liquidity_amount: u64,
+    min_acceptable_received_liquidity_amount: u64,
+    max_allowed_ltv_override_percent: u64,
+}
+
+pub struct LiquidationAccounts<'a> {
+    pub program_id: Pubkey,
+    pub lending_market: Pubkey,
+    pub liquidator: Pubkey,
+    pub obligation_address: Pubkey,
+    pub obligation: &'a Obligation,
+    pub reserves: &'a HashMap<Pubkey, Reserve>,
+    pub repay_reserve: Pubkey,
+    pub withdraw_reserve: Pubkey,
+}
+
+pub fn anchor_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(format!("global:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+pub fn lending_market_authority(program_id: &Pubkey, lending_market: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"lma", lending_market.as_ref()], program_id).0
+}
+
+pub fn associated_token_address(wallet: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+pub fn create_associated_token_account_idempotent(
+    payer: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: ASSOCIATED_TOKEN_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(associated_token_address(wallet, mint, token_program), false),
+            AccountMeta::new_readonly(*wallet, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*token_program, false),
+        ],
+        data: vec![1],
+    }
+}
+
+pub fn refresh_reserve(
+    program_id: &Pubkey,
+    lending_market: &Pubkey,
+    reserve_address: &Pubkey,
+    reserve: &Reserve,
+) -> Instruction {
+    let optional = |account: Option<Pubkey>| {
+        AccountMeta::new_readonly(account.unwrap_or(*program_id), false)
+    };
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*reserve_address, false),
+            AccountMeta::new_readonly(*lending_market, false),
+            optional(reserve.pyth_oracle()),
+            optional(reserve.switchboard_price_oracle()),
+            optional(reserve.switchboard_twap_oracle()),
+            optional(reserve.scope_prices()),
+        ],
+        data: anchor_discriminator("refresh_reserve").to_vec(),
+    }
+}
+
+pub fn refresh_obligation(
+    program_id: &Pubkey,
+    lending_market: &Pubkey,
+    obligation_address: &Pubkey,
+    obligation: &Obligation,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*lending_market, false),
+        AccountMeta::new(*obligation_address, false),
+    ];
+    accounts.extend(
+        obligation
+            .active_deposits()
+            .map(|deposit| AccountMeta::new_readonly(deposit.deposit_reserve, false)),
+    );
+    accounts.extend(
+        obligation
+            .active_borrows()
+            .map(|borrow| AccountMeta::new_readonly(borrow.borrow_reserve, false)),
+    );
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: anchor_discriminator("refresh_obligation").to_vec(),
+    }
+}
+
+pub fn liquidate_obligation_and_redeem_reserve_collateral(
+    accounts: &LiquidationAccounts,
+    liquidity_amount: u64,
+    min_acceptable_received_liquidity_amount: u64,
+    max_allowed_ltv_override_percent: u64,
+) -> Result<Instruction> {
+    let repay = reserve(accounts, &accounts.repay_reserve)?;
+    let withdraw = reserve(accounts, &accounts.withdraw_reserve)?;
+    let repay_token_program = token_program(repay);
+    let withdraw_token_program = token_program(withdraw);
+
+    let mut data = anchor_discriminator("liquidate_obligation_and_redeem_reserve_collateral").to_vec();
+    LiquidateArgs {
+        liquidity_amount,
+        min_acceptable_received_liquidity_amount,
+        max_allowed_ltv_override_percent,
+    }
+    .serialize(&mut data)?;
+
+    Ok(Instruction {
+        program_id: accounts.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(accounts.liquidator, true),
+            AccountMeta::new(accounts.obligation_address, false),
+            AccountMeta::new_readonly(accounts.lending_market, false),
+            AccountMeta::new_readonly(
+                lending_market_authority(&accounts.program_id, &accounts.lending_market),
+                false,
+            ),
+            AccountMeta::new(accounts.repay_reserve, false),
+            AccountMeta::new_readonly(repay.liquidity.mint_pubkey, false),
+            AccountMeta::new(repay.liquidity.supply_vault, false),
+            AccountMeta::new(accounts.withdraw_reserve, false),
+            AccountMeta::new_readonly(withdraw.liquidity.mint_pubkey, false),
+            AccountMeta::new(withdraw.collateral.mint_pubkey, false),
+            AccountMeta::new(withdraw.collateral.supply_vault, false),
+            AccountMeta::new(withdraw.liquidity.supply_vault, false),
+            AccountMeta::new(withdraw.liquidity.fee_vault, false),
+            AccountMeta::new(
+                associated_token_address(&accounts.liquidator, &repay.liquidity.mint_pubkey, &repay_token_program),
+                false,
+            ),
+            AccountMeta::new(
+                associated_token_address(&accounts.liquidator, &withdraw.collateral.mint_pubkey, &TOKEN_PROGRAM_ID),
+                false,
+            ),
+            AccountMeta::new(
+                associated_token_address(
+                    &accounts.liquidator,
+                    &withdraw.liquidity.mint_pubkey,
+                    &withdraw_token_program,
+                ),
+                false,
+            ),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(repay_token_program, false),
+            AccountMeta::new_readonly(withdraw_token_program, false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ],
+        data,
+    })
+}
+
+pub fn liquidation_instructions(
+    accounts: &LiquidationAccounts,
+    liquidity_amount: u64,
+    min_acceptable_received_liquidity_amount: u64,
+) -> Result<Vec<Instruction>> {
+    let withdraw = reserve(accounts, &accounts.withdraw_reserve)?;
+
+    let mut instructions = vec![
+        create_associated_token_account_idempotent(
+            &accounts.liquidator,
+            &accounts.liquidator,
+            &withdraw.collateral.mint_pubkey,
+            &TOKEN_PROGRAM_ID,
+        ),
+        create_associated_token_account_idempotent(
+            &accounts.liquidator,
+            &accounts.liquidator,
+            &withdraw.liquidity.mint_pubkey,
+            &token_program(withdraw),
+        ),
+    ];
+
+    for reserve_address in accounts.obligation.get_reserve_addresses() {
+        let reserve = reserve(accounts, &reserve_address)?;
+        instructions.push(refresh_reserve(
+            &accounts.program_id,
+            &accounts.lending_market,
+            &reserve_address,
+            reserve,
+        ));
+    }
+
+    instructions.push(refresh_obligation(
+        &accounts.program_id,
+        &accounts.lending_market,
+        &accounts.obligation_address,
+        accounts.obligation,
+    ));
+
+    instructions.push(liquidate_obligation_and_redeem_reserve_collateral(
+        accounts,
+        liquidity_amount,
+        min_acceptable_received_liquidity_amount,
+        0,
+    )?);
+
+    Ok(instructions)
+}
+
+fn reserve<'a>(accounts: &'a LiquidationAccounts, address: &Pubkey) -> Result<&'a Reserve> {
+    accounts
+        .reserves
+        .get(address)
+        .ok_or_else(|| anyhow!("reserve {} not loaded", address))
+}
+
+fn token_program(reserve: &Reserve) -> Pubkey {
+    if reserve.liquidity.token_program == Pubkey::default() {
+        TOKEN_PROGRAM_ID
+    } else {
+        reserve.liquidity.token_program
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::zeroed;
+
+    const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+    fn reserve(token_program: Pubkey) -> Reserve {
+        let mut reserve = zeroed::<Reserve>();
+        reserve.liquidity.mint_pubkey = Pubkey::new_unique();
+        reserve.liquidity.supply_vault = Pubkey::new_unique();
+        reserve.liquidity.fee_vault = Pubkey::new_unique();
+        reserve.liquidity.token_program = token_program;
+        reserve.collateral.mint_pubkey = Pubkey::new_unique();
+        reserve.collateral.supply_vault = Pubkey::new_unique();
+        reserve
+    }
+
+    struct Fixture {
+        program_id: Pubkey,
+        lending_market: Pubkey,
+        liquidator: Pubkey,
+        obligation_address: Pubkey,
+        obligation: Obligation,
+        reserves: HashMap<Pubkey, Reserve>,
+        repay_reserve: Pubkey,
+        withdraw_reserve: Pubkey,
+    }
+
+    impl Fixture {
+        // Repays a Token-2022 reserve, withdraws from an SPL token reserve that leaves the
+        // token program unset.
+        fn new() -> Self {
+            let (repay_reserve, withdraw_reserve) = (Pubkey::new_unique(), Pubkey::new_unique());
+            let mut obligation = zeroed::<Obligation>();
+            obligation.deposits[0].deposit_reserve = withdraw_reserve;
+            obligation.borrows[0].borrow_reserve = repay_reserve;
+            Fixture {
+                program_id: Pubkey::new_unique(),
+                lending_market: Pubkey::new_unique(),
+                liquidator: Pubkey::new_unique(),
+                obligation_address: Pubkey::new_unique(),
+                obligation,
+                reserves: HashMap::from([
+                    (repay_reserve, reserve(TOKEN_2022_PROGRAM_ID)),
+                    (withdraw_reserve, reserve(Pubkey::default())),
+                ]),
+                repay_reserve,
+                withdraw_reserve,
+            }
+        }
+
+        fn accounts(&self) -> LiquidationAccounts<'_> {
+            LiquidationAccounts {
+                program_id: self.program_id,
+                lending_market: self.lending_market,
+                liquidator: self.liquidator,
+                obligation_address: self.obligation_address,
+                obligation: &self.obligation,
+                reserves: &self.reserves,
+                repay_reserve: self.repay_reserve,
+                withdraw_reserve: self.withdraw_reserve,
+            }
+        }
+    }
+
+    #[test]
+    fn liquidate_lists_accounts_in_the_program_order() {
+        let fixture = Fixture::new();
+        let instruction = liquidate_obligation_and_redeem_reserve_collateral(&fixture.accounts(), 1_000, 900, 0).unwrap();
+
+        let repay = &fixture.reserves[&fixture.repay_reserve];
+        let withdraw = &fixture.reserves[&fixture.withdraw_reserve];
+        let liquidator = fixture.liquidator;
+        let expected = vec![
+            AccountMeta::new_readonly(liquidator, true),
+            AccountMeta::new(fixture.obligation_address, false),
+            AccountMeta::new_readonly(fixture.lending_market, false),
+            AccountMeta::new_readonly(lending_market_authority(&fixture.program_id, &fixture.lending_market), false),
+            AccountMeta::new(fixture.repay_reserve, false),
+            AccountMeta::new_readonly(repay.liquidity.mint_pubkey, false),
+            AccountMeta::new(repay.liquidity.supply_vault, false),
+            AccountMeta::new(fixture.withdraw_reserve, false),
+            AccountMeta::new_readonly(withdraw.liquidity.mint_pubkey, false),
+            AccountMeta::new(withdraw.collateral.mint_pubkey, false),
+            AccountMeta::new(withdraw.collateral.supply_vault, false),
+            AccountMeta::new(withdraw.liquidity.supply_vault, false),
+            AccountMeta::new(withdraw.liquidity.fee_vault, false),
+            AccountMeta::new(
+                associated_token_address(&liquidator, &repay.liquidity.mint_pubkey, &TOKEN_2022_PROGRAM_ID),
+                false,
+            ),
+            AccountMeta::new(
+                associated_token_address(&liquidator, &withdraw.collateral.mint_pubkey, &TOKEN_PROGRAM_ID),
+                false,
+            ),
+            AccountMeta::new(
+                associated_token_address(&liquidator, &withdraw.liquidity.mint_pubkey, &TOKEN_PROGRAM_ID),
+                false,
+            ),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(TOKEN_2022_PROGRAM_ID, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ];
+        assert_eq!(instruction.program_id, fixture.program_id);
+        assert_eq!(instruction.accounts, expected);
+
+        // The klend IDL discriminator, then liquidity_amount, min_acceptable_received_liquidity_amount
+        // and max_allowed_ltv_override_percent as little-endian u64s.
+        assert_eq!(instruction.data[..8], [177, 71, 154, 188, 226, 133, 74, 55]);
+        assert_eq!(instruction.data[8..16], 1_000u64.to_le_bytes());
+        assert_eq!(instruction.data[16..24], 900u64.to_le_bytes());
+        assert_eq!(instruction.data[24..], 0u64.to_le_bytes());
+    }
+
+    #[test]
+    fn refreshes_reserves_and_the_obligation_before_liquidating() {
+        let fixture = Fixture::new();
+        let instructions = liquidation_instructions(&fixture.accounts(), 1_000, 900).unwrap();
+        let withdraw = &fixture.reserves[&fixture.withdraw_reserve];
+
+        assert_eq!(instructions.len(), 6);
+        assert_eq!(instructions[0].program_id, ASSOCIATED_TOKEN_PROGRAM_ID);
+        assert_eq!(instructions[0].accounts[3].pubkey, withdraw.collateral.mint_pubkey);
+        assert_eq!(instructions[1].accounts[3].pubkey, withdraw.liquidity.mint_pubkey);
+
+        let reserves = fixture.obligation.get_reserve_addresses();
+        for (instruction, reserve_address) in instructions[2..4].iter().zip(&reserves) {
+            assert_eq!(instruction.data, [2, 218, 138, 235, 79, 201, 25, 102]);
+            assert_eq!(instruction.accounts[0], AccountMeta::new(*reserve_address, false));
+            // Unset oracles are passed as the program id.
+            assert!(instruction.accounts[2..].iter().all(|account| account.pubkey == fixture.program_id));
+        }
+
+        assert_eq!(instructions[4].data, [33, 132, 147, 228, 151, 192, 72, 89]);
+        let refreshed: Vec<Pubkey> = instructions[4].accounts.iter().map(|account| account.pubkey).collect();
+        assert_eq!(
+            refreshed,
+            vec![fixture.lending_market, fixture.obligation_address, fixture.withdraw_reserve, fixture.repay_reserve]
+        );
+        assert_eq!(instructions[5].data[..8], [177, 71, 154, 188, 226, 133, 74, 55]);
+    }
+
+    #[test]
+    fn fails_without_the_reserves_loaded() {
+        let mut fixture = Fixture::new();
+        fixture.reserves.remove(&fixture.repay_reserve);
+        assert!(liquidate_obligation_and_redeem_reserve_collateral(&fixture.accounts(), 1, 1, 0).is_err());
+        assert!(liquidation_instructions(&fixture.accounts(), 1, 1).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::events::{Event, EventSender};
+use crate::kamino::{Obligation, Reserve};
+use crate::price_listener::{run_listener, Listener};
+use borsh::BorshDeserialize;
+use solana_sdk::{hash::hash, pubkey::Pubkey};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn};
+use yellowstone_grpc_proto::geyser::{
+    subscribe_request_filter_accounts_filter::Filter,
+    subscribe_request_filter_accounts_filter_memcmp::Data, subscribe_update::UpdateOneof,
+    CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts,
+    SubscribeRequestFilterAccountsFilter, SubscribeRequestFilterAccountsFilterMemcmp,
+    SubscribeUpdateAccount,
+};
+
+// Reserves and obligations both store their lending market right after the version and
+// last_update fields.
+const LENDING_MARKET_OFFSET: u64 = 32;
+
+pub fn account_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(format!("account:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+// Streams every klend account of the lending market and forwards decoded reserve and
+// obligation updates to the evaluator.
+pub struct KlendAccountListener {
+    program_id: Pubkey,
+    lending_market: Pubkey,
+    reserve_discriminator: [u8; 8],
+    obligation_discriminator: [u8; 8],
+    events: EventSender,
+}
+
+impl KlendAccountListener {
+    pub fn new(program_id: Pubkey, lending_market: Pubkey, events: EventSender) -> Self {
+        KlendAccountListener {
+            program_id,
+            lending_market,
+            reserve_discriminator: account_discriminator("Reserve"),
+            obligation_discriminator: account_discriminator("Obligation"),
+            events,
+        }
+    }
+
+    pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            run_listener(self).await;
+        })
+    }
+}
+
+impl Listener for KlendAccountListener {
+    fn get_subscription_request(&self) -> SubscribeRequest {
+        info!(
+            "Creating Yellowstone gRPC subscription for klend accounts of market {}",
+            self.lending_market
+        );
+
+        let account_filter = SubscribeRequestFilterAccounts {
+            account: vec![],
+            owner: vec![self.program_id.to_string()],
+            filters: vec![SubscribeRequestFilterAccountsFilter {
+                filter: Some(Filter::Memcmp(SubscribeRequestFilterAccountsFilterMemcmp {
+                    offset: LENDING_MARKET_OFFSET,
+                    data: Some(Data::Bytes(self.lending_market.to_bytes().to_vec())),
+                })),
+            }],
+        };
+
+        let mut accounts_map = HashMap::new();
+        accounts_map.insert("klend_market_accounts".to_string(), account_filter);
+
+        SubscribeRequest {
+            accounts: accounts_map,
+            commitment: Some(CommitmentLevel::Processed as i32),
+            ..Default::default()
+        }
+    }
+
+    fn handle_update(&self, update: UpdateOneof) {
+        let (account_info, slot) = match update {
+            UpdateOneof::Account(SubscribeUpdateAccount {
+                account: Some(account_info),
+                slot,
+                ..
+            }) => (account_info, slot),
+            _ => return,
+        };
+
+        let address = match <[u8; 32]>::try_from(account_info.pubkey.as_slice()) {
+            Ok(bytes) => Pubkey::new_from_array(bytes),
+            Err(_) => return,
+        };
+        if account_info.data.len() <= 8 {
+            return;
+        }
+
+        let received_at = Instant::now();
+        let discriminator = &account_info.data[..8];
+        let event = if discriminator == self.reserve_discriminator {
+            match Reserve::try_from_account_data(&account_info.data) {
+                Some(reserve) => Event::Reserve {
+                    address,
+                    reserve: Box::new(reserve),
+                    slot,
+                    received_at,
+                },
+                None => {
+                    warn!("Failed to decode reserve {} at slot {}", address, slot);
+                    return;
+                }
+            }
+        } else if discriminator == self.obligation_discriminator {
+            match Obligation::try_from_slice(&account_info.data[8..]) {
+                Ok(obligation) => Event::Obligation {
+                    address,
+                    obligation: Box::new(obligation),
+                    slot,
+                    received_at,
+                },
+                Err(e) => {
+                    warn!("Failed to decode obligation {} at slot {}: {}", address, slot, e);
+                    return;
+                }
+            }
+        } else {
+            return;
+        };
+
+        let _ = self.events.send(event);
+    }
+
+    fn name(&self) -> String {
+        "KlendAccountListener".to_string()
+    }
+}
@@ -0,0 +1,246 @@
+use crate::health::ObligationHealth;
+use crate::kamino::{Obligation, Reserve};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+pub const ALERT_PRICE_DISTANCE: f64 = 0.02;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LiquidationPrice {
+    pub token_mint: String,
+    pub current_price: f64,
+    pub liquidation_price: Option<f64>,
+    pub price_change: Option<f64>,
+    pub dominant: bool,
+}
+
+impl LiquidationPrice {
+    pub fn is_near(&self, distance: f64) -> bool {
+        self.price_change.is_some_and(|change| change.abs() <= distance)
+    }
+}
+
+pub fn liquidation_prices(
+    obligation: &Obligation,
+    health: &ObligationHealth,
+    reserves: &HashMap<Pubkey, Reserve>,
+) -> Vec<LiquidationPrice> {
+    if health.bf_adjusted_debt_value <= 0.0 {
+        return Vec::new();
+    }
+
+    let dominant_mint = obligation
+        .single_collateral()
+        .and_then(|collateral| reserves.get(&collateral.deposit_reserve))
+        .map(|reserve| reserve.mint().to_string())
+        .or_else(|| health.largest_deposit().map(|deposit| deposit.token_mint.clone()));
+
+    let mut mints: Vec<&str> = health
+        .deposits
+        .iter()
+        .map(|deposit| deposit.token_mint.as_str())
+        .collect();
+    mints.sort_unstable();
+    mints.dedup();
+
+    mints
+        .into_iter()
+        .filter_map(|mint| {
+            let current_price = health
+                .deposits
+                .iter()
+                .find(|deposit| deposit.token_mint == mint)?
+                .price;
+            let liquidation_price = solve_liquidation_price(health, mint);
+            Some(LiquidationPrice {
+                token_mint: mint.to_string(),
+                current_price,
+                liquidation_price,
+                price_change: liquidation_price
+                    .filter(|_| current_price > 0.0)
+                    .map(|price| price / current_price - 1.0),
+                dominant: dominant_mint.as_deref() == Some(mint),
+            })
+        })
+        .collect()
+}
+
+// With every other price held fixed, the unhealthy borrow value and the borrow-factor
+// adjusted debt are both linear in this mint's price p:
+//   unhealthy(p) = U0 + a_u * p,  debt(p) = D0 + a_d * p
+// so the obligation becomes liquidatable at p = (D0 - U0) / (a_u - a_d).
+pub fn solve_liquidation_price(health: &ObligationHealth, mint: &str) -> Option<f64> {
+    let (mut unhealthy_rest, mut unhealthy_slope) = (0.0, 0.0);
+    for deposit in &health.deposits {
+        if deposit.token_mint == mint {
+            unhealthy_slope += deposit.amount * deposit.liquidation_threshold;
+        } else {
+            unhealthy_rest += deposit.value * deposit.liquidation_threshold;
+        }
+    }
+
+    let (mut debt_rest, mut debt_slope) = (0.0, 0.0);
+    for borrow in &health.borrows {
+        if borrow.token_mint == mint {
+            debt_slope += borrow.amount * borrow.borrow_factor;
+        } else {
+            debt_rest += borrow.value * borrow.borrow_factor;
+        }
+    }
+
+    let slope = unhealthy_slope - debt_slope;
+    if slope <= 0.0 {
+        return None;
+    }
+
+    let price = (debt_rest - unhealthy_rest) / slope;
+    (price > 0.0).then_some(price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::PositionValue;
+    use crate::test_fixtures::{self, zeroed};
+
+    fn position(mint: &str, amount: f64, price: f64, liquidation_threshold: f64, borrow_factor: f64) -> PositionValue {
+        PositionValue {
+            token_mint: mint.to_string(),
+            liquidation_threshold,
+            borrow_factor,
+            ..test_fixtures::position(Pubkey::new_unique(), amount, 9, price)
+        }
+    }
+
+    fn health(deposits: Vec<PositionValue>, borrows: Vec<PositionValue>) -> ObligationHealth {
+        let unhealthy_borrow_value: f64 =
+            deposits.iter().map(|deposit| deposit.value * deposit.liquidation_threshold).sum();
+        let bf_adjusted_debt_value: f64 = borrows.iter().map(|borrow| borrow.value * borrow.borrow_factor).sum();
+        ObligationHealth {
+            deposited_value: deposits.iter().map(|deposit| deposit.value).sum(),
+            borrowed_value: borrows.iter().map(|borrow| borrow.value).sum(),
+            bf_adjusted_debt_value,
+            unhealthy_borrow_value,
+            health_factor: unhealthy_borrow_value / bf_adjusted_debt_value,
+            deposits,
+            borrows,
+            ..Default::default()
+        }
+    }
+
+    // Re-prices every position in the mint and recomputes the health factor from scratch.
+    fn health_factor_at(health: &ObligationHealth, mint: &str, price: f64) -> f64 {
+        let reprice = |positions: &[PositionValue]| -> Vec<PositionValue> {
+            positions
+                .iter()
+                .map(|position| {
+                    if position.token_mint == mint {
+                        PositionValue { price, value: position.amount * price, ..position.clone() }
+                    } else {
+                        position.clone()
+                    }
+                })
+                .collect()
+        };
+        self::health(reprice(&health.deposits), reprice(&health.borrows)).health_factor
+    }
+
+    fn empty_obligation() -> Obligation {
+        zeroed::<Obligation>()
+    }
+
+    #[test]
+    fn single_asset_reaches_health_one_at_the_solved_price() {
+        // 10 SOL at $100 with an 80% threshold against 600 USDC: 8p = 600 at p = 75.
+        let health = health(
+            vec![position("sol", 10.0, 100.0, 0.8, 1.0)],
+            vec![position("usdc", 600.0, 1.0, 0.0, 1.0)],
+        );
+
+        let price = solve_liquidation_price(&health, "sol").unwrap();
+        assert!((price - 75.0).abs() < 1e-9);
+        assert!((health_factor_at(&health, "sol", price) - 1.0).abs() < 1e-12);
+
+        // The debt asset has no price at which the obligation becomes liquidatable by falling.
+        assert_eq!(solve_liquidation_price(&health, "usdc"), None);
+    }
+
+    #[test]
+    fn multi_asset_reaches_health_one_at_the_solved_price() {
+        // SOL 10 @ $100 (80%), mSOL 5 @ $110 (70%), USDC 200 (90%) against 900 USDC and
+        // 100 JTO @ $2 with a 125% borrow factor.
+        let health = health(
+            vec![
+                position("sol", 10.0, 100.0, 0.8, 1.0),
+                position("msol", 5.0, 110.0, 0.7, 1.0),
+                position("usdc", 200.0, 1.0, 0.9, 1.0),
+            ],
+            vec![position("usdc", 900.0, 1.0, 0.0, 1.0), position("jto", 100.0, 2.0, 0.0, 1.25)],
+        );
+
+        // SOL: (900 + 250 - 385 - 180) / 8 = 73.125.
+        let price = solve_liquidation_price(&health, "sol").unwrap();
+        assert!((price - 73.125).abs() < 1e-9);
+        assert!((health_factor_at(&health, "sol", price) - 1.0).abs() < 1e-12);
+
+        // mSOL: (1150 - 800 - 180) / 3.5.
+        let price = solve_liquidation_price(&health, "msol").unwrap();
+        assert!((price - 170.0 / 3.5).abs() < 1e-9);
+        assert!((health_factor_at(&health, "msol", price) - 1.0).abs() < 1e-12);
+
+        // USDC is on both sides: slope 0.9 - 1.0 < 0, so a falling USDC price only helps.
+        assert_eq!(solve_liquidation_price(&health, "usdc"), None);
+    }
+
+    #[test]
+    fn mint_on_both_sides_nets_the_slopes() {
+        // 10 SOL deposited (80%) against 2 SOL and 300 USDC borrowed: 8p - 2p = 300 at p = 50.
+        let health = health(
+            vec![position("sol", 10.0, 100.0, 0.8, 1.0)],
+            vec![position("sol", 2.0, 100.0, 0.0, 1.0), position("usdc", 300.0, 1.0, 0.0, 1.0)],
+        );
+
+        let price = solve_liquidation_price(&health, "sol").unwrap();
+        assert!((price - 50.0).abs() < 1e-9);
+        assert!((health_factor_at(&health, "sol", price) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn collateral_alone_covering_the_debt_has_no_liquidation_price() {
+        // USDC collateral alone covers the debt, so SOL would have to go negative.
+        let health = health(
+            vec![position("sol", 1.0, 100.0, 0.8, 1.0), position("usdc", 1000.0, 1.0, 0.9, 1.0)],
+            vec![position("usdc", 500.0, 1.0, 0.0, 1.0)],
+        );
+        assert_eq!(solve_liquidation_price(&health, "sol"), None);
+    }
+
+    #[test]
+    fn lists_each_deposit_mint_with_its_distance() {
+        let health = health(
+            vec![position("sol", 10.0, 100.0, 0.8, 1.0), position("msol", 1.0, 110.0, 0.7, 1.0)],
+            vec![position("usdc", 677.0, 1.0, 0.0, 1.0)],
+        );
+
+        let prices = liquidation_prices(&empty_obligation(), &health, &HashMap::new());
+        assert_eq!(prices.iter().map(|price| price.token_mint.as_str()).collect::<Vec<_>>(), vec!["msol", "sol"]);
+
+        // The largest deposit is dominant when the obligation has no single collateral.
+        let sol = prices.iter().find(|price| price.token_mint == "sol").unwrap();
+        assert!(sol.dominant);
+        // (677 - 77) / 8 = 75: a 25% drop.
+        assert!((sol.liquidation_price.unwrap() - 75.0).abs() < 1e-9);
+        assert!((sol.price_change.unwrap() + 0.25).abs() < 1e-9);
+        assert!(!sol.is_near(ALERT_PRICE_DISTANCE));
+        assert!(sol.is_near(0.25 + 1e-9));
+
+        let msol = prices.iter().find(|price| price.token_mint == "msol").unwrap();
+        assert!(!msol.dominant);
+        assert_eq!(msol.liquidation_price, None);
+        assert!(!msol.is_near(ALERT_PRICE_DISTANCE));
+
+        let debt_free = self::health(vec![position("sol", 10.0, 100.0, 0.8, 1.0)], Vec::new());
+        assert!(liquidation_prices(&empty_obligation(), &debt_free, &HashMap::new()).is_empty());
+    }
+}
@@ -0,0 +1,529 @@
+use crate::kamino::Reserve;
+use crate::klend_ix::lending_market_authority;
+use crate::rpc_pool::RpcPool;
+use anyhow::{anyhow, bail, Result};
+use dashmap::{DashMap, DashSet};
+use solana_sdk::{
+    address_lookup_table::{
+        instruction::{create_lookup_table, extend_lookup_table},
+        state::{AddressLookupTable, LOOKUP_TABLE_MAX_ADDRESSES},
+    },
+    address_lookup_table_account::AddressLookupTableAccount,
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+pub const DEFAULT_CACHE_PATH: &str = "lookup_tables.json";
+const EXTEND_BATCH_SIZE: usize = 20;
+const ACTIVATION_POLL_INTERVAL: Duration = Duration::from_millis(400);
+const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct LookupTableManager {
+    rpc_pool: Arc<RpcPool>,
+    authority: Arc<Keypair>,
+    tables: DashMap<Pubkey, AddressLookupTableAccount>,
+    pending: DashSet<Pubkey>,
+    cache_path: PathBuf,
+}
+
+impl LookupTableManager {
+    pub fn new(rpc_pool: Arc<RpcPool>, authority: Arc<Keypair>, cache_path: PathBuf) -> Self {
+        LookupTableManager {
+            rpc_pool,
+            authority,
+            tables: DashMap::new(),
+            pending: DashSet::new(),
+            cache_path,
+        }
+    }
+
+    pub async fn load_cached(&self) -> Result<usize> {
+        if !self.cache_path.exists() {
+            return Ok(0);
+        }
+
+        let contents = fs::read_to_string(&self.cache_path)?;
+        let addresses: Vec<String> = serde_json::from_str(&contents)?;
+
+        for address in addresses {
+            let table_address = Pubkey::from_str(&address)?;
+            if let Err(e) = self.refresh(&table_address).await {
+                warn!("Failed to load cached lookup table {}: {:?}", table_address, e);
+            }
+        }
+
+        info!(
+            "Loaded {} address lookup tables from {}",
+            self.tables.len(),
+            self.cache_path.display()
+        );
+        Ok(self.tables.len())
+    }
+
+    // Caches the addresses a transaction can look up now: those added by the latest extension
+    // only become usable once its slot has passed.
+    pub async fn refresh(&self, table_address: &Pubkey) -> Result<AddressLookupTableAccount> {
+        let response = self
+            .rpc_pool
+            .call_async(|client| async move {
+                client
+                    .get_account_with_commitment(table_address, client.commitment())
+                    .await
+            })
+            .await?;
+        let account = response
+            .value
+            .ok_or_else(|| anyhow!("lookup table {} not found", table_address))?;
+        let table = AddressLookupTable::deserialize(&account.data)
+            .map_err(|e| anyhow!("invalid lookup table {}: {:?}", table_address, e))?;
+
+        let table_account = AddressLookupTableAccount {
+            key: *table_address,
+            addresses: active_addresses(&table, response.context.slot),
+        };
+        self.tables.insert(*table_address, table_account.clone());
+        Ok(table_account)
+    }
+
+    pub fn contains(&self, address: &Pubkey) -> bool {
+        self.tables
+            .iter()
+            .any(|entry| entry.value().addresses.contains(address))
+    }
+
+    pub fn tables_for(&self, instructions: &[Instruction]) -> Vec<AddressLookupTableAccount> {
+        let accounts = lookup_candidates(instructions);
+        self.tables
+            .iter()
+            .filter(|entry| entry.value().addresses.iter().any(|a| accounts.contains(a)))
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    pub fn queue_missing(&self, instructions: &[Instruction]) -> usize {
+        let mut queued = 0;
+        for address in lookup_candidates(instructions) {
+            if !self.contains(&address) && self.pending.insert(address) {
+                queued += 1;
+            }
+        }
+        queued
+    }
+
+    // Queues the market and every reserve's vault, mint and oracle accounts, which nearly
+    // every liquidation touches, so the first transactions already fit.
+    pub fn seed(&self, program_id: &Pubkey, lending_market: &Pubkey, reserves: &HashMap<Pubkey, Reserve>) -> usize {
+        let mut addresses = vec![*lending_market, lending_market_authority(program_id, lending_market)];
+        for (address, reserve) in reserves {
+            addresses.extend([
+                *address,
+                reserve.liquidity.mint_pubkey,
+                reserve.liquidity.supply_vault,
+                reserve.liquidity.fee_vault,
+                reserve.collateral.mint_pubkey,
+                reserve.collateral.supply_vault,
+            ]);
+            addresses.extend(reserve.oracle_accounts());
+        }
+
+        addresses
+            .into_iter()
+            .filter(|address| *address != Pubkey::default() && !self.contains(address))
+            .filter(|address| self.pending.insert(*address))
+            .count()
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub async fn ensure_addresses(&self, addresses: &[Pubkey]) -> Result<()> {
+        let mut missing: Vec<Pubkey> = addresses
+            .iter()
+            .filter(|address| !self.contains(address))
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        missing.sort();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        info!("Adding {} addresses to lookup tables", missing.len());
+
+        while !missing.is_empty() {
+            let table_address = match self.table_with_capacity() {
+                Some(table_address) => table_address,
+                None => self.create_table().await?,
+            };
+
+            let capacity = LOOKUP_TABLE_MAX_ADDRESSES
+                - self
+                    .tables
+                    .get(&table_address)
+                    .map(|table| table.addresses.len())
+                    .unwrap_or_default();
+            let batch: Vec<Pubkey> = missing.drain(..capacity.min(missing.len())).collect();
+
+            self.extend_table(&table_address, &batch).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn flush_pending(&self) -> Result<()> {
+        let pending: Vec<Pubkey> = self.pending.iter().map(|address| *address).collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        self.ensure_addresses(&pending).await?;
+        for address in pending {
+            self.pending.remove(&address);
+        }
+        Ok(())
+    }
+
+    pub fn start_maintenance(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if self.pending_count() == 0 {
+                    continue;
+                }
+
+                if let Err(e) = self.flush_pending().await {
+                    warn!("Failed to extend lookup tables: {:?}", e);
+                }
+            }
+        })
+    }
+
+    pub async fn create_table(&self) -> Result<Pubkey> {
+        let recent_slot = self
+            .rpc_pool
+            .call_async(|client| async move {
+                client.get_slot_with_commitment(CommitmentConfig::finalized()).await
+            })
+            .await?;
+        let (instruction, table_address) = create_lookup_table(
+            self.authority.pubkey(),
+            self.authority.pubkey(),
+            recent_slot,
+        );
+
+        self.send(&[instruction]).await?;
+        self.tables.insert(
+            table_address,
+            AddressLookupTableAccount {
+                key: table_address,
+                addresses: vec![],
+            },
+        );
+        self.save_cache()?;
+
+        info!("Created address lookup table {}", table_address);
+        Ok(table_address)
+    }
+
+    pub async fn extend_table(&self, table_address: &Pubkey, addresses: &[Pubkey]) -> Result<()> {
+        for chunk in addresses.chunks(EXTEND_BATCH_SIZE) {
+            let instruction = extend_lookup_table(
+                *table_address,
+                self.authority.pubkey(),
+                Some(self.authority.pubkey()),
+                chunk.to_vec(),
+            );
+            self.send(&[instruction]).await?;
+        }
+        self.await_activation(table_address, addresses).await?;
+
+        info!(
+            "Extended lookup table {} with {} addresses",
+            table_address,
+            addresses.len()
+        );
+        Ok(())
+    }
+
+    async fn await_activation(&self, table_address: &Pubkey, addresses: &[Pubkey]) -> Result<()> {
+        let started = std::time::Instant::now();
+        loop {
+            let table = self.refresh(table_address).await?;
+            if addresses.iter().all(|address| table.addresses.contains(address)) {
+                return Ok(());
+            }
+            if started.elapsed() >= ACTIVATION_TIMEOUT {
+                bail!("lookup table {} extension did not activate within {:?}", table_address, ACTIVATION_TIMEOUT);
+            }
+            tokio::time::sleep(ACTIVATION_POLL_INTERVAL).await;
+        }
+    }
+
+    fn table_with_capacity(&self) -> Option<Pubkey> {
+        self.tables
+            .iter()
+            .find(|entry| entry.value().addresses.len() < LOOKUP_TABLE_MAX_ADDRESSES)
+            .map(|entry| *entry.key())
+    }
+
+    async fn send(&self, instructions: &[Instruction]) -> Result<()> {
+        let blockhash = self
+            .rpc_pool
+            .call_async(|client| async move { client.get_latest_blockhash().await })
+            .await?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.authority.pubkey()),
+            &[self.authority.as_ref()],
+            blockhash,
+        );
+        let transaction = &transaction;
+        self.rpc_pool
+            .call_async(|client| async move {
+                client.send_and_confirm_transaction(transaction).await
+            })
+            .await?;
+        Ok(())
+    }
+
+    fn save_cache(&self) -> Result<()> {
+        let addresses: Vec<String> = self.tables.iter().map(|entry| entry.key().to_string()).collect();
+        fs::write(&self.cache_path, serde_json::to_string_pretty(&addresses)?)?;
+        Ok(())
+    }
+}
+
+fn active_addresses(table: &AddressLookupTable, current_slot: u64) -> Vec<Pubkey> {
+    let active = if current_slot > table.meta.last_extended_slot {
+        table.addresses.len()
+    } else {
+        table.meta.last_extended_slot_start_index as usize
+    };
+    table.addresses[..active.min(table.addresses.len())].to_vec()
+}
+
+fn lookup_candidates(instructions: &[Instruction]) -> HashSet<Pubkey> {
+    let program_ids: HashSet<Pubkey> = instructions.iter().map(|ix| ix.program_id).collect();
+    instructions
+        .iter()
+        .flat_map(|ix| ix.accounts.iter())
+        .filter(|meta| !meta.is_signer && !program_ids.contains(&meta.pubkey))
+        .map(|meta| meta.pubkey)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rpc::{with_context, MockRpc};
+    use crate::test_fixtures::{rpc_pool, zeroed};
+    use base64::Engine;
+    use serde_json::{json, Value};
+    use solana_sdk::address_lookup_table::{self, instruction::ProgramInstruction, state::LookupTableMeta};
+    use solana_sdk::instruction::AccountMeta;
+    use std::borrow::Cow;
+    use std::sync::Mutex;
+
+    fn manager(url: String) -> LookupTableManager {
+        let cache_path = std::env::temp_dir().join(format!("lookup-tables-{}.json", Pubkey::new_unique()));
+        LookupTableManager::new(rpc_pool(&url), Arc::new(Keypair::new()), cache_path)
+    }
+
+    fn table(addresses: &[Pubkey], last_extended_slot: u64, start_index: u8) -> AddressLookupTable<'static> {
+        AddressLookupTable {
+            meta: LookupTableMeta {
+                last_extended_slot,
+                last_extended_slot_start_index: start_index,
+                ..LookupTableMeta::default()
+            },
+            addresses: Cow::Owned(addresses.to_vec()),
+        }
+    }
+
+    #[test]
+    fn addresses_of_the_latest_extension_activate_in_the_next_slot() {
+        let addresses: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let extended = table(&addresses, 100, 3);
+
+        assert_eq!(active_addresses(&extended, 100), addresses[..3]);
+        assert_eq!(active_addresses(&extended, 101), addresses);
+    }
+
+    #[test]
+    fn queues_missing_accounts_once_skipping_signers_and_programs() {
+        let manager = manager("http://127.0.0.1:1".to_string());
+        let cached = Pubkey::new_unique();
+        let table_address = Pubkey::new_unique();
+        manager.tables.insert(
+            table_address,
+            AddressLookupTableAccount {
+                key: table_address,
+                addresses: vec![cached],
+            },
+        );
+
+        let program = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let instruction = Instruction::new_with_bytes(
+            program,
+            &[],
+            vec![
+                AccountMeta::new(signer, true),
+                AccountMeta::new(first, false),
+                AccountMeta::new_readonly(cached, false),
+                AccountMeta::new_readonly(second, false),
+                AccountMeta::new_readonly(program, false),
+            ],
+        );
+
+        assert_eq!(manager.queue_missing(std::slice::from_ref(&instruction)), 2);
+        assert_eq!(manager.queue_missing(&[instruction]), 0);
+        assert_eq!(manager.pending_count(), 2);
+        assert!(manager.pending.contains(&first) && manager.pending.contains(&second));
+    }
+
+    #[test]
+    fn seeds_market_reserve_vault_and_oracle_accounts() {
+        let manager = manager("http://127.0.0.1:1".to_string());
+        let (program_id, lending_market, reserve_address) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut reserve = zeroed::<Reserve>();
+        reserve.liquidity.mint_pubkey = Pubkey::new_unique();
+        reserve.liquidity.supply_vault = Pubkey::new_unique();
+        reserve.liquidity.fee_vault = Pubkey::new_unique();
+        reserve.collateral.mint_pubkey = Pubkey::new_unique();
+        reserve.collateral.supply_vault = Pubkey::new_unique();
+        reserve.config.token_info.pyth_configuration.price = Pubkey::new_unique();
+
+        // Market, its authority, the reserve, 5 vault and mint accounts and one oracle; the
+        // unset Switchboard and Scope oracles are skipped.
+        let reserves = HashMap::from([(reserve_address, reserve.clone())]);
+        assert_eq!(manager.seed(&program_id, &lending_market, &reserves), 9);
+        for address in [
+            lending_market,
+            lending_market_authority(&program_id, &lending_market),
+            reserve_address,
+            reserve.collateral.supply_vault,
+            reserve.config.token_info.pyth_configuration.price,
+        ] {
+            assert!(manager.pending.contains(&address));
+        }
+        assert!(!manager.pending.contains(&Pubkey::default()));
+        assert_eq!(manager.seed(&program_id, &lending_market, &reserves), 0);
+    }
+
+    // A cluster holding one lookup table: extensions land in the current slot and every read
+    // of the table advances the slot by one.
+    struct Chain {
+        slot: u64,
+        table: AddressLookupTable<'static>,
+    }
+
+    fn handle(chain: &Mutex<Chain>, method: &str, params: &Value) -> Result<Value, Value> {
+        let mut chain = chain.lock().unwrap();
+        match method {
+            "getLatestBlockhash" => Ok(with_context(
+                chain.slot,
+                json!({"blockhash": solana_sdk::hash::Hash::default().to_string(), "lastValidBlockHeight": 1_000}),
+            )),
+            "sendTransaction" => {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(params[0].as_str().unwrap())
+                    .unwrap();
+                let transaction: Transaction = bincode::deserialize(&data).unwrap();
+                for instruction in &transaction.message.instructions {
+                    let program = transaction.message.account_keys[instruction.program_id_index as usize];
+                    assert_eq!(program, address_lookup_table::program::id());
+                    if let Ok(ProgramInstruction::ExtendLookupTable { new_addresses }) =
+                        bincode::deserialize(&instruction.data)
+                    {
+                        let start_index = chain.table.addresses.len() as u8;
+                        let slot = chain.slot;
+                        chain.table.meta.last_extended_slot = slot;
+                        chain.table.meta.last_extended_slot_start_index = start_index;
+                        chain.table.addresses.to_mut().extend(new_addresses);
+                    }
+                }
+                Ok(json!(transaction.signatures[0].to_string()))
+            }
+            "getSignatureStatuses" => Ok(with_context(
+                chain.slot,
+                json!([{"slot": chain.slot, "confirmations": null, "err": null, "status": {"Ok": null}, "confirmationStatus": "finalized"}]),
+            )),
+            "getAccountInfo" => {
+                let slot = chain.slot;
+                chain.slot += 1;
+                let data = chain.table.clone().serialize_for_tests().unwrap();
+                Ok(with_context(
+                    slot,
+                    json!({
+                        "lamports": 1_000_000,
+                        "data": [base64::engine::general_purpose::STANDARD.encode(data), "base64"],
+                        "owner": address_lookup_table::program::id().to_string(),
+                        "executable": false,
+                        "rentEpoch": 0,
+                        "space": 0,
+                    }),
+                ))
+            }
+            _ => Err(json!({"code": -32601, "message": format!("unexpected {}", method)})),
+        }
+    }
+
+    #[tokio::test]
+    async fn caches_extended_addresses_only_once_they_are_active() {
+        let existing = Pubkey::new_unique();
+        let chain = Arc::new(Mutex::new(Chain {
+            slot: 500,
+            table: table(&[existing], 10, 0),
+        }));
+        let rpc = MockRpc::new({
+            let chain = Arc::clone(&chain);
+            move |method, params| handle(&chain, method, params)
+        })
+        .serve()
+        .await
+        .unwrap();
+
+        let manager = manager(rpc.url.clone());
+        let table_address = Pubkey::new_unique();
+        manager.refresh(&table_address).await.unwrap();
+        assert!(manager.contains(&existing));
+
+        let added: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let mut requested = added.clone();
+        requested.push(existing);
+        manager.ensure_addresses(&requested).await.unwrap();
+
+        // One extension for the missing addresses only; the first read after it still sees the
+        // extension slot, so the addresses are cached after the second.
+        let methods = rpc.methods();
+        assert_eq!(methods.iter().filter(|method| *method == "sendTransaction").count(), 1);
+        let reads_after_send = methods
+            .iter()
+            .skip_while(|method| *method != "sendTransaction")
+            .filter(|method| *method == "getAccountInfo")
+            .count();
+        assert_eq!(reads_after_send, 2);
+        assert!(added.iter().all(|address| manager.contains(address)));
+        assert_eq!(chain.lock().unwrap().table.addresses.len(), 4);
+
+        manager.ensure_addresses(&added).await.unwrap();
+        assert_eq!(rpc.methods().len(), methods.len());
+    }
+}
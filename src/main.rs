@@ -1,70 +1,66 @@
+// solana_client's ClientError is large and returned from every RPC closure handed to RpcPool.
+#![allow(clippy::result_large_err)]
+
 mod utils;
+mod account_source;
 mod kamino;
 mod price_listener;
+mod jito;
+mod fees;
+mod lookup_table;
+mod tx_builder;
+mod blockhash_listener;
+mod health;
+mod orders;
+mod planner;
+mod deleverage;
+mod klend_ix;
+mod executor;
+mod price_guard;
+mod volatility;
+mod liquidation_price;
+mod watchlist;
+mod obligation_store;
+mod events;
+mod evaluator;
+mod snapshot;
+mod klend_listener;
+mod persistence;
+mod backtest;
+mod recorder;
+mod rpc_pool;
+mod geyser_stream;
+mod geyser_sources;
+mod pubsub_stream;
+#[cfg(test)]
+mod mock_geyser;
+#[cfg(test)]
+mod mock_pubsub;
+#[cfg(test)]
+mod mock_rpc;
+#[cfg(test)]
+mod test_fixtures;
 
+use account_source::{AccountSource, FixtureAccountSource};
 use anyhow::Result;
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
 use std::time::Duration;
 use std::collections::{HashMap, HashSet};
-use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::Write;
+use std::path::PathBuf;
 use std::sync::Arc;
-use price_listener::{PriceListener, get_current_price_info, get_token_symbol};
+use blockhash_listener::BlockHashListener;
+use evaluator::HealthEvaluator;
+use events::{evaluation_channel, event_channel, execution_channel};
+use executor::Executor;
+use klend_listener::KlendAccountListener;
+use obligation_store::ObligationStore;
+use persistence::Database;
+use price_listener::PriceListener;
+use rpc_pool::RpcPool;
+use snapshot::{SnapshotSink, DEFAULT_SNAPSHOT_PATH};
 use tracing::info;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PriceInfo {
-    pub symbol: String,
-    pub price: f64,
-    pub confidence: f64,
-    pub status: String,
-    pub last_updated: String,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct DepositInfo {
-    pub reserve_address: String,
-    pub token_mint: String,
-    pub token_symbol: String,
-    pub deposited_amount: u64,
-    pub market_value: String,
-    pub live_price: Option<f64>,
-    pub price_status: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct BorrowInfo {
-    pub reserve_address: String,
-    pub token_mint: String,
-    pub token_symbol: String,
-    pub borrowed_amount: String,
-    pub market_value: String,
-    pub live_price: Option<f64>,
-    pub price_status: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct ObligationInfo {
-    pub obligation_address: String,
-    pub owner: String,
-    pub deposited_value: String,
-    pub borrowed_value: String,
-    pub allowed_borrow_value: String,
-    pub unhealthy_borrow_value: String,
-    pub elevation_group: u8,
-    pub has_debt: bool,
-    pub active_deposits_count: usize,
-    pub active_borrows_count: usize,
-    pub deposits: Vec<DepositInfo>,
-    pub borrows: Vec<BorrowInfo>,
-    pub all_token_mints: Vec<String>,
-    pub live_prices: HashMap<String, PriceInfo>,
-    pub last_updated: String,
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
@@ -74,50 +70,127 @@ async fn main() -> Result<()> {
     info!("Starting Kamino Liquidator with CONSOLIDATED Pyth Price Listener");
     info!("================================================================================");
     
-    let rpc_url = std::env::var("RPC_URL").expect("RPC_URL must be set");
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("backtest") {
+        let fixture = args.get(2).ok_or_else(|| anyhow::anyhow!("usage: backtest <fixture.json> [latency_slots] [fee_lamports]"))?;
+        let mut config = backtest::BacktestConfig::default();
+        if let Some(latency_slots) = args.get(3) {
+            config.latency_slots = latency_slots.parse()?;
+        }
+        if let Some(fee_lamports) = args.get(4) {
+            config.fee_lamports = fee_lamports.parse()?;
+        }
+        let report = backtest::run_fixture(fixture, config)?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let database = Database::from_env()?;
+
+    if args.get(1).map(String::as_str) == Some("history") {
+        let database = database.ok_or_else(|| anyhow::anyhow!("history requires DATABASE_PATH"))?;
+        let address = Pubkey::from_str(args.get(2).ok_or_else(|| anyhow::anyhow!("usage: history <obligation> [limit]"))?)?;
+        let limit = args.get(3).map(|limit| limit.parse()).transpose()?.unwrap_or(100);
+        let history = serde_json::json!({
+            "obligation": address.to_string(),
+            "health": database.health_history(&address, limit)?,
+            "versions": database.obligation_versions(&address, limit)?,
+        });
+        println!("{}", serde_json::to_string_pretty(&history)?);
+        return Ok(());
+    }
     
-    let rpc_client = RpcClient::new_with_timeout_and_commitment(
-        rpc_url,
-        Duration::from_secs(60),
-        CommitmentConfig::confirmed()
-    );
+    let rpc_pool = Arc::new(RpcPool::from_env()?);
+    let _rpc_health_task_handle = Arc::clone(&rpc_pool).start_health_checks(Duration::from_secs(10));
+    geyser_stream::set_gap_fill_source(Arc::clone(&rpc_pool) as Arc<dyn AccountSource>);
     
     let program_id = Pubkey::from_str("KLend2g3cP87fffoy8q1mQqGKjrxjC8boSyAYavgmjD")?;
     let lending_market = Pubkey::from_str("7u3HeHxYDLhnCoErrtycNokbQYbWGzLs6JSDqGAv5PfF")?;
-    
-    info!("Fetching obligations for lending market...");
-    let mut obligations = utils::get_all_obligations_for_market(&rpc_client, &program_id, &lending_market).await?;
-    
-    if obligations.is_empty() {
-        info!("No obligations found using filters, trying fallback method...");
-        obligations = utils::get_all_program_accounts(&rpc_client, &program_id, &lending_market).await?;
-    }
-    
-    info!("Found {} obligations total", obligations.len());
-    
-    let obligations_with_borrows = utils::filter_obligations_with_borrows(obligations);
-    
-    info!("Found {} obligations with active borrows", obligations_with_borrows.len());
-    
-    if obligations_with_borrows.is_empty() {
-        info!("No obligations with borrows found. Exiting.");
+
+    if args.get(1).map(String::as_str) == Some("dump-market") {
+        let dir = PathBuf::from(args.get(2).ok_or_else(|| anyhow::anyhow!("usage: dump-market <dir>"))?);
+        let (obligations, reserves) = utils::dump_market(rpc_pool.as_ref(), &program_id, &lending_market, &dir).await?;
+        info!("Wrote {} obligations and {} reserves to {}", obligations, reserves, dir.display());
         return Ok(());
     }
-    
-    info!("Collecting unique reserve addresses...");
-    let mut all_reserve_addresses = HashSet::new();
-    for (obligation, _) in &obligations_with_borrows {
-        let reserve_addresses = obligation.get_reserve_addresses();
-        for addr in reserve_addresses {
-            all_reserve_addresses.insert(addr);
+
+    let warm_start_max_age = Duration::from_secs(
+        std::env::var("WARM_START_MAX_AGE_SECS").ok().and_then(|value| value.parse().ok()).unwrap_or(300),
+    );
+    let warm_state = match &database {
+        Some(database) => database.warm_state(warm_start_max_age)?,
+        None => None,
+    };
+
+    let (obligations_with_borrows, reserves, reserve_to_mint_map) = match warm_state {
+        Some(warm_state) => {
+            info!("Warm start from persisted state at slot {} ({}s old): {} obligations, {} reserves",
+                  warm_state.slot, warm_state.age_secs, warm_state.obligations.len(), warm_state.reserves.len());
+            let reserve_to_mint_map: HashMap<Pubkey, String> = warm_state.reserves
+                .iter()
+                .map(|(address, reserve)| (*address, reserve.mint().to_string()))
+                .collect();
+            (warm_state.obligations, warm_state.reserves, reserve_to_mint_map)
         }
-    }
-    
-    let unique_reserves: Vec<Pubkey> = all_reserve_addresses.into_iter().collect();
-    info!("Found {} unique reserves", unique_reserves.len());
-    
-    info!("Fetching reserves in batches...");
-    let reserve_to_mint_map = utils::create_reserve_to_mint_mapping(&rpc_client, &program_id, unique_reserves).await?;
+        None => {
+            let account_source: Arc<dyn AccountSource> = match std::env::var("ACCOUNT_DUMP_PATH") {
+                Ok(path) if !path.is_empty() => {
+                    let source = FixtureAccountSource::load(&path)?;
+                    info!("Discovering accounts from {} ({} accounts)", path, source.len());
+                    Arc::new(source)
+                }
+                _ => Arc::clone(&rpc_pool) as Arc<dyn AccountSource>,
+            };
+
+            info!("Fetching obligations for lending market...");
+            let mut obligations = utils::get_all_obligations_for_market(account_source.as_ref(), &program_id, &lending_market).await?;
+            
+            if obligations.is_empty() {
+                info!("No obligations found using filters, trying fallback method...");
+                obligations = utils::get_all_program_accounts(account_source.as_ref(), &program_id, &lending_market).await?;
+            }
+            
+            info!("Found {} obligations total", obligations.len());
+            
+            let obligations_with_borrows = utils::filter_obligations_with_borrows(obligations);
+            
+            info!("Found {} obligations with active borrows", obligations_with_borrows.len());
+            
+            if obligations_with_borrows.is_empty() {
+                info!("No obligations with borrows found. Exiting.");
+                return Ok(());
+            }
+            
+            info!("Collecting unique reserve addresses...");
+            let mut all_reserve_addresses = HashSet::new();
+            for (obligation, _) in &obligations_with_borrows {
+                let reserve_addresses = obligation.get_reserve_addresses();
+                for addr in reserve_addresses {
+                    all_reserve_addresses.insert(addr);
+                }
+            }
+            
+            let unique_reserves: Vec<Pubkey> = all_reserve_addresses.into_iter().collect();
+            info!("Found {} unique reserves", unique_reserves.len());
+            
+            info!("Fetching reserves in batches...");
+            let reserves = utils::get_reserves(account_source.as_ref(), &program_id, &unique_reserves).await?;
+            info!("Decoded {} reserves", reserves.len());
+            let reserve_to_mint_map = utils::create_reserve_to_mint_mapping(account_source.as_ref(), &program_id, unique_reserves).await?;
+
+            if let Some(database) = &database {
+                let slot = account_source.get_slot().await.unwrap_or_default();
+                for (obligation, address) in &obligations_with_borrows {
+                    database.record_obligation(*address, slot, obligation);
+                }
+                for (address, reserve) in &reserves {
+                    database.record_reserve(*address, slot, reserve);
+                }
+            }
+
+            (obligations_with_borrows, reserves, reserve_to_mint_map)
+        }
+    };
     
     let all_token_mints: HashSet<String> = reserve_to_mint_map.values()
         .filter(|mint| *mint != "UNKNOWN" && *mint != "PARSE_FAIL" && *mint != "INVALID" && *mint != "NOT_FOUND")
@@ -127,134 +200,51 @@ async fn main() -> Result<()> {
     let token_mints: Vec<String> = all_token_mints.into_iter().collect();
     info!("Found {} unique token mints", token_mints.len());
     
-    let price_listener = PriceListener::new(token_mints);
+    let obligation_store = Arc::new(ObligationStore::new());
+    for (reserve, mint) in &reserve_to_mint_map {
+        obligation_store.set_reserve_mint(*reserve, mint.clone());
+    }
+    for (obligation, address) in obligations_with_borrows {
+        obligation_store.insert(address, obligation);
+    }
+
+    let (event_sender, event_receiver) = event_channel();
+    let price_listener = PriceListener::new(token_mints)
+        .with_events(Arc::clone(&obligation_store), event_sender.clone());
     let price_listener_arc = Arc::new(price_listener);
     
-    let _price_task_handle = price_listener_arc.start();
-    
-    info!("Starting obligation processing (waiting 30s for REAL Pyth prices)...");
-    tokio::time::sleep(Duration::from_secs(30)).await;
-    
-    let obligations_with_borrows = Arc::new(obligations_with_borrows);
-    let reserve_to_mint_map = Arc::new(reserve_to_mint_map);
-    
-    let mut update_counter = 0;
-    
-    loop {
-        update_counter += 1;
-        let mut obligations_info = Vec::new();
-        let current_timestamp = chrono::Utc::now().to_rfc3339();
-        
-        info!("Processing obligations update #{}", update_counter);
-        
-        for (obligation, address) in obligations_with_borrows.iter() {
-            let reserve_addresses = obligation.get_reserve_addresses();
-            let all_token_mints: Vec<String> = reserve_addresses
-                .iter()
-                .map(|addr| reserve_to_mint_map.get(addr).cloned().unwrap_or_else(|| "UNKNOWN".to_string()))
-                .collect();
-            
-            let mut live_prices: HashMap<String, PriceInfo> = HashMap::new();
-            for mint in &all_token_mints {
-                if let Some(price_info) = get_current_price_info(mint) {
-                    live_prices.insert(mint.clone(), PriceInfo {
-                        symbol: price_info.symbol,
-                        price: price_info.price,
-                        confidence: price_info.confidence,
-                        status: price_info.status,
-                        last_updated: price_info.last_updated.to_rfc3339(),
-                    });
-                }
-            }
-            
-            let deposits: Vec<DepositInfo> = obligation.deposits
-                .iter()
-                .filter(|deposit| deposit.deposit_reserve != Pubkey::default())
-                .map(|deposit| {
-                    let token_mint = reserve_to_mint_map.get(&deposit.deposit_reserve)
-                        .cloned()
-                        .unwrap_or_else(|| "UNKNOWN".to_string());
-                    
-                    let token_symbol = get_token_symbol(&token_mint).to_string();
-                    let (live_price, price_status) = 
-                        if let Some(price_info) = get_current_price_info(&token_mint) {
-                            (Some(price_info.price), Some(price_info.status))
-                        } else {
-                            (None, Some("No Pyth Data".to_string()))
-                        };
-                    
-                    DepositInfo {
-                        reserve_address: deposit.deposit_reserve.to_string(),
-                        token_mint,
-                        token_symbol,
-                        deposited_amount: deposit.deposited_amount,
-                        market_value: deposit.market_value_sf.to_string(),
-                        live_price,
-                        price_status,
-                    }
-                })
-                .collect();
-            
-            let borrows: Vec<BorrowInfo> = obligation.borrows
-                .iter()
-                .filter(|borrow| borrow.borrow_reserve != Pubkey::default() && borrow.borrowed_amount_sf > 0)
-                .map(|borrow| {
-                    let token_mint = reserve_to_mint_map.get(&borrow.borrow_reserve)
-                        .cloned()
-                        .unwrap_or_else(|| "UNKNOWN".to_string());
-                    
-                    let token_symbol = get_token_symbol(&token_mint).to_string();
-                    let (live_price, price_status) = 
-                        if let Some(price_info) = get_current_price_info(&token_mint) {
-                            (Some(price_info.price), Some(price_info.status))
-                        } else {
-                            (None, Some("No Pyth Data".to_string()))
-                        };
-                    
-                    BorrowInfo {
-                        reserve_address: borrow.borrow_reserve.to_string(),
-                        token_mint,
-                        token_symbol,
-                        borrowed_amount: borrow.borrowed_amount_sf.to_string(),
-                        market_value: borrow.market_value_sf.to_string(),
-                        live_price,
-                        price_status,
-                    }
-                })
-                .collect();
-            
-            let obligation_info = ObligationInfo {
-                obligation_address: address.to_string(),
-                owner: obligation.owner.to_string(),
-                deposited_value: obligation.deposited_value_sf.to_string(),
-                borrowed_value: obligation.borrowed_assets_market_value_sf.to_string(),
-                allowed_borrow_value: obligation.allowed_borrow_value_sf.to_string(),
-                unhealthy_borrow_value: obligation.unhealthy_borrow_value_sf.to_string(),
-                elevation_group: obligation.elevation_group,
-                has_debt: obligation.has_debt != 0,
-                active_deposits_count: deposits.len(),
-                active_borrows_count: borrows.len(),
-                deposits,
-                borrows,
-                all_token_mints,
-                live_prices,
-                last_updated: current_timestamp.clone(),
-            };
-            
-            obligations_info.push(obligation_info);
+    let _price_task_handle = Arc::clone(&price_listener_arc).start();
+    let _blockhash_task_handle = Arc::new(BlockHashListener::new()).start();
+    let _klend_task_handle = Arc::new(KlendAccountListener::new(program_id, lending_market, event_sender)).start();
+
+    let execution_sender = match Executor::from_env(program_id, lending_market, Arc::clone(&rpc_pool), &reserves).await? {
+        Some(executor) => {
+            let (execution_sender, execution_receiver) = execution_channel();
+            Arc::new(executor).start(execution_receiver);
+            Some(execution_sender)
         }
+        None => None,
+    };
 
-        let json_string = serde_json::to_string_pretty(&obligations_info)?;
-        let mut file = File::create("obligations_with_pyth_prices.json")?;
-        file.write_all(json_string.as_bytes())?;
-        
-        let total_live_prices: usize = obligations_info.iter()
-            .map(|o| o.live_prices.len())
-            .sum();
-        
-        info!("Updated obligations file (#{}) - {} obligations, {} Pyth prices", 
-              update_counter, obligations_info.len(), total_live_prices);
-        
-        tokio::time::sleep(Duration::from_secs(20)).await;
-    }
-}
\ No newline at end of file
+    let evaluations = evaluation_channel();
+    let evaluator = HealthEvaluator::new(
+        Arc::clone(&obligation_store),
+        reserves,
+        reserve_to_mint_map,
+        execution_sender,
+        evaluations.clone(),
+    )
+    .with_database(database.clone())
+    .with_price_listener(Arc::clone(&price_listener_arc));
+    let _snapshot_task_handle = SnapshotSink::new(
+        PathBuf::from(DEFAULT_SNAPSHOT_PATH),
+        Duration::from_secs(20),
+        evaluator.volatility_config.horizon,
+    )
+    .with_database(database)
+    .start(evaluations.subscribe());
+
+    info!("Starting event-driven obligation processing");
+    evaluator.start(event_receiver).await?;
+    Ok(())
+}
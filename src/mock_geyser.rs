@@ -0,0 +1,648 @@
+use crate::kamino::Reserve;
+use crate::klend_listener::account_discriminator;
+use crate::price_listener::{PYTH_MAGIC, PYTH_PRICE_ACCOUNT_MIN_SIZE, PYTH_VERSION};
+use crate::test_fixtures::zeroed;
+use anyhow::Result;
+use base64::Engine;
+use borsh::BorshSerialize;
+use futures::Stream;
+use solana_sdk::pubkey::Pubkey;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+use yellowstone_grpc_proto::geyser::{
+    geyser_server::{Geyser, GeyserServer},
+    subscribe_request_filter_accounts_filter::Filter,
+    subscribe_request_filter_accounts_filter_memcmp::Data,
+    subscribe_update::UpdateOneof,
+    GetBlockHeightRequest, GetBlockHeightResponse, GetLatestBlockhashRequest,
+    GetLatestBlockhashResponse, GetSlotRequest, GetSlotResponse, GetVersionRequest,
+    GetVersionResponse, IsBlockhashValidRequest, IsBlockhashValidResponse, PingRequest,
+    PongResponse, SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeUpdate,
+    SubscribeUpdateAccount, SubscribeUpdateAccountInfo, SubscribeUpdateBlockMeta,
+    SubscribeUpdatePong, SubscribeUpdateSlot,
+};
+
+type UpdateStream = Pin<Box<dyn Stream<Item = Result<SubscribeUpdate, Status>> + Send>>;
+type UpdateSender = mpsc::UnboundedSender<Result<SubscribeUpdate, Status>>;
+
+pub fn account_update(pubkey: Pubkey, owner: Pubkey, slot: u64, data: Vec<u8>) -> UpdateOneof {
+    UpdateOneof::Account(SubscribeUpdateAccount {
+        account: Some(SubscribeUpdateAccountInfo {
+            pubkey: pubkey.to_bytes().to_vec(),
+            owner: owner.to_bytes().to_vec(),
+            lamports: 1,
+            data,
+            write_version: slot,
+            ..Default::default()
+        }),
+        slot,
+        is_startup: false,
+    })
+}
+
+pub fn slot_update(slot: u64) -> UpdateOneof {
+    UpdateOneof::Slot(SubscribeUpdateSlot {
+        slot,
+        parent: slot.checked_sub(1),
+        status: 0,
+    })
+}
+
+pub fn block_meta_update(slot: u64, blockhash: String) -> UpdateOneof {
+    UpdateOneof::BlockMeta(SubscribeUpdateBlockMeta {
+        slot,
+        blockhash,
+        parent_slot: slot.saturating_sub(1),
+        ..Default::default()
+    })
+}
+
+pub fn pyth_price_account(price: i64, expo: i32, slot: u64) -> Vec<u8> {
+    let mut data = vec![0u8; PYTH_PRICE_ACCOUNT_MIN_SIZE];
+    let timestamp = chrono::Utc::now().timestamp();
+    data[0..4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+    data[4..8].copy_from_slice(&PYTH_VERSION.to_le_bytes());
+    data[20..24].copy_from_slice(&expo.to_le_bytes());
+    data[48..56].copy_from_slice(&price.to_le_bytes());
+    data[96..104].copy_from_slice(&timestamp.to_le_bytes());
+    data[208..216].copy_from_slice(&price.to_le_bytes());
+    data[216..224].copy_from_slice(&((price / 1_000) as u64).to_le_bytes());
+    data[224..228].copy_from_slice(&1u32.to_le_bytes());
+    data[232..240].copy_from_slice(&slot.to_le_bytes());
+    data
+}
+
+pub fn reserve_account(lending_market: Pubkey) -> Vec<u8> {
+    let mut reserve = zeroed::<Reserve>();
+    reserve.lending_market = lending_market;
+    let mut data = account_discriminator("Reserve").to_vec();
+    data.extend(reserve.try_to_vec().unwrap());
+    data
+}
+
+// In-process Geyser server: every subscriber receives the scripted updates that match its
+// SubscribeRequest filters, then the stream stays open until the client goes away. Ping
+// requests are answered with pongs unless disabled, and are not recorded as subscriptions.
+#[derive(Clone)]
+pub struct MockGeyser {
+    script: Arc<Vec<UpdateOneof>>,
+    requests: Arc<Mutex<Vec<SubscribeRequest>>>,
+    subscribers: Arc<Mutex<Vec<UpdateSender>>>,
+    subscriptions: Arc<AtomicUsize>,
+    pongs: bool,
+    x_token: Option<String>,
+}
+
+pub struct MockGeyserHandle {
+    pub endpoint: String,
+    requests: Arc<Mutex<Vec<SubscribeRequest>>>,
+    subscribers: Arc<Mutex<Vec<UpdateSender>>>,
+    subscriptions: Arc<AtomicUsize>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockGeyserHandle {
+    pub fn requests(&self) -> Vec<SubscribeRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    // Number of subscribe streams opened, as opposed to requests sent on them.
+    pub fn subscriptions(&self) -> usize {
+        self.subscriptions.load(Ordering::Relaxed)
+    }
+
+    // Fails every open subscription stream, as a provider restart would.
+    pub fn disconnect(&self) {
+        for subscriber in self.subscribers.lock().unwrap().drain(..) {
+            let _ = subscriber.send(Err(Status::unavailable("mock geyser disconnected")));
+        }
+    }
+}
+
+impl Drop for MockGeyserHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl MockGeyser {
+    pub fn new(script: Vec<UpdateOneof>) -> Self {
+        MockGeyser {
+            script: Arc::new(script),
+            requests: Arc::new(Mutex::new(Vec::new())),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            subscriptions: Arc::new(AtomicUsize::new(0)),
+            pongs: true,
+            x_token: None,
+        }
+    }
+
+    // Subscriptions without this x-token header are rejected as unauthenticated.
+    pub fn with_x_token(mut self, x_token: &str) -> Self {
+        self.x_token = Some(x_token.to_string());
+        self
+    }
+
+    // Streams stay open but silent: pings go unanswered.
+    pub fn without_pongs(mut self) -> Self {
+        self.pongs = false;
+        self
+    }
+
+    pub async fn serve(self) -> Result<MockGeyserHandle> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let endpoint = format!("http://{}", listener.local_addr()?);
+        let requests = Arc::clone(&self.requests);
+        let subscribers = Arc::clone(&self.subscribers);
+        let subscriptions = Arc::clone(&self.subscriptions);
+
+        let incoming = futures::stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            Some((connection, listener))
+        });
+        let task = tokio::spawn(async move {
+            let _ = tonic::transport::Server::builder()
+                .add_service(GeyserServer::new(self))
+                .serve_with_incoming(incoming)
+                .await;
+        });
+
+        Ok(MockGeyserHandle {
+            endpoint,
+            requests,
+            subscribers,
+            subscriptions,
+            task,
+        })
+    }
+
+    fn last_slot(&self) -> u64 {
+        self.script.iter().map(update_slot).max().unwrap_or_default()
+    }
+}
+
+#[tonic::async_trait]
+impl Geyser for MockGeyser {
+    type SubscribeStream = UpdateStream;
+
+    async fn subscribe(
+        &self,
+        request: Request<Streaming<SubscribeRequest>>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        if let Some(expected) = &self.x_token {
+            let x_token = request.metadata().get("x-token").and_then(|value| value.to_str().ok());
+            if x_token != Some(expected.as_str()) {
+                return Err(Status::unauthenticated("invalid x-token"));
+            }
+        }
+        let mut incoming = request.into_inner();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let script = Arc::clone(&self.script);
+        let requests = Arc::clone(&self.requests);
+        let pongs = self.pongs;
+        self.subscribers.lock().unwrap().push(sender.clone());
+        self.subscriptions.fetch_add(1, Ordering::Relaxed);
+
+        tokio::spawn(async move {
+            while let Ok(Some(request)) = incoming.message().await {
+                if let Some(ping) = request.ping {
+                    if pongs {
+                        let pong = SubscribeUpdate {
+                            filters: vec![],
+                            update_oneof: Some(UpdateOneof::Pong(SubscribeUpdatePong { id: ping.id })),
+                        };
+                        if sender.send(Ok(pong)).is_err() {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                requests.lock().unwrap().push(request.clone());
+                for update in script.iter() {
+                    let filters = matching_filters(&request, update);
+                    if filters.is_empty() {
+                        continue;
+                    }
+                    let message = SubscribeUpdate {
+                        filters,
+                        update_oneof: Some(update.clone()),
+                    };
+                    if sender.send(Ok(message)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(UnboundedReceiverStream::new(receiver))))
+    }
+
+    async fn ping(&self, request: Request<PingRequest>) -> Result<Response<PongResponse>, Status> {
+        Ok(Response::new(PongResponse {
+            count: request.into_inner().count,
+        }))
+    }
+
+    async fn get_latest_blockhash(
+        &self,
+        _request: Request<GetLatestBlockhashRequest>,
+    ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+        let latest = self.script.iter().rev().find_map(|update| match update {
+            UpdateOneof::BlockMeta(meta) => Some(meta.clone()),
+            _ => None,
+        });
+        Ok(Response::new(GetLatestBlockhashResponse {
+            slot: latest.as_ref().map(|meta| meta.slot).unwrap_or_default(),
+            blockhash: latest.map(|meta| meta.blockhash).unwrap_or_default(),
+            last_valid_block_height: 0,
+        }))
+    }
+
+    async fn get_block_height(
+        &self,
+        _request: Request<GetBlockHeightRequest>,
+    ) -> Result<Response<GetBlockHeightResponse>, Status> {
+        Ok(Response::new(GetBlockHeightResponse {
+            block_height: self.last_slot(),
+        }))
+    }
+
+    async fn get_slot(&self, _request: Request<GetSlotRequest>) -> Result<Response<GetSlotResponse>, Status> {
+        Ok(Response::new(GetSlotResponse { slot: self.last_slot() }))
+    }
+
+    async fn is_blockhash_valid(
+        &self,
+        request: Request<IsBlockhashValidRequest>,
+    ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+        let blockhash = request.into_inner().blockhash;
+        let valid = self.script.iter().any(|update| {
+            matches!(update, UpdateOneof::BlockMeta(meta) if meta.blockhash == blockhash)
+        });
+        Ok(Response::new(IsBlockhashValidResponse {
+            slot: self.last_slot(),
+            valid,
+        }))
+    }
+
+    async fn get_version(
+        &self,
+        _request: Request<GetVersionRequest>,
+    ) -> Result<Response<GetVersionResponse>, Status> {
+        Ok(Response::new(GetVersionResponse {
+            version: "mock-geyser".to_string(),
+        }))
+    }
+}
+
+fn update_slot(update: &UpdateOneof) -> u64 {
+    match update {
+        UpdateOneof::Account(account) => account.slot,
+        UpdateOneof::Slot(slot) => slot.slot,
+        UpdateOneof::BlockMeta(meta) => meta.slot,
+        _ => 0,
+    }
+}
+
+pub fn matching_filters(request: &SubscribeRequest, update: &UpdateOneof) -> Vec<String> {
+    match update {
+        UpdateOneof::Account(SubscribeUpdateAccount {
+            account: Some(account),
+            ..
+        }) => request
+            .accounts
+            .iter()
+            .filter(|(_, filter)| account_matches(filter, account))
+            .map(|(name, _)| name.clone())
+            .collect(),
+        UpdateOneof::Slot(_) => request.slots.keys().cloned().collect(),
+        UpdateOneof::BlockMeta(_) => request.blocks_meta.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn account_matches(filter: &SubscribeRequestFilterAccounts, account: &SubscribeUpdateAccountInfo) -> bool {
+    let pubkey = bs58::encode(&account.pubkey).into_string();
+    let owner = bs58::encode(&account.owner).into_string();
+
+    if !filter.account.is_empty() && !filter.account.contains(&pubkey) {
+        return false;
+    }
+    if !filter.owner.is_empty() && !filter.owner.contains(&owner) {
+        return false;
+    }
+
+    filter.filters.iter().all(|filter| match &filter.filter {
+        Some(Filter::Memcmp(memcmp)) => {
+            let expected = match &memcmp.data {
+                Some(Data::Bytes(bytes)) => bytes.clone(),
+                Some(Data::Base58(value)) => bs58::decode(value).into_vec().unwrap_or_default(),
+                Some(Data::Base64(value)) => base64::engine::general_purpose::STANDARD
+                    .decode(value)
+                    .unwrap_or_default(),
+                None => return false,
+            };
+            let offset = memcmp.offset as usize;
+            account.data.get(offset..offset + expected.len()) == Some(expected.as_slice())
+        }
+        Some(Filter::Datasize(size)) => account.data.len() as u64 == *size,
+        Some(Filter::TokenAccountState(_)) => account.data.len() == 165,
+        None => true,
+    })
+}
+
+mod tests {
+    use super::*;
+    use crate::account_source::InMemoryAccountSource;
+    use crate::blockhash_listener::{current_slot, get_cached_blockhash, BlockHashListener};
+    use crate::events::{event_channel, Event};
+    use crate::geyser_sources::{run_redundant_streams, GeyserSources};
+    use crate::geyser_stream::{run_stream, GeyserEndpoint, StreamConfig};
+    use crate::klend_listener::KlendAccountListener;
+    use crate::price_listener::{get_current_price, Listener, PriceListener};
+    use solana_sdk::account::Account;
+    use yellowstone_grpc_proto::geyser::CommitmentLevel;
+    use solana_sdk::hash::Hash;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const USDC_PRICE_ACCOUNT: &str = "Gnt27xtC473ZT2Mw5u8wZ68Z3gULkSTb5DuxJy7eJotD";
+
+    fn spawn_listener<L: Listener>(listener: L, endpoint: &str) -> tokio::task::JoinHandle<()> {
+        let endpoint = endpoint.to_string();
+        tokio::spawn(async move { run_stream(Arc::new(listener), GeyserEndpoint::new(endpoint), StreamConfig::from_env()).await })
+    }
+
+    async fn wait_for<F: Fn() -> bool>(condition: F) -> bool {
+        for _ in 0..100 {
+            if condition() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn price_listener_receives_subscribed_price_accounts() {
+        let price_account = Pubkey::from_str(USDC_PRICE_ACCOUNT).unwrap();
+        let pyth_program = Pubkey::new_unique();
+        let geyser = MockGeyser::new(vec![
+            account_update(Pubkey::new_unique(), pyth_program, 99, pyth_price_account(1, -8, 99)),
+            account_update(price_account, pyth_program, 100, pyth_price_account(100_012_345, -8, 100)),
+        ])
+        .serve()
+        .await
+        .unwrap();
+
+        let listener = spawn_listener(PriceListener::new(vec![USDC_MINT.to_string()]), &geyser.endpoint);
+        assert!(wait_for(|| get_current_price(USDC_MINT).is_some()).await);
+        assert!((get_current_price(USDC_MINT).unwrap() - 1.00012345).abs() < 1e-9);
+
+        let requests = geyser.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].accounts["real_pyth_prices"].account, vec![USDC_PRICE_ACCOUNT.to_string()]);
+        listener.abort();
+    }
+
+    #[tokio::test]
+    async fn price_listener_updates_its_subscription_without_reconnecting() {
+        let mint = Pubkey::new_unique().to_string();
+        let price_account = Pubkey::new_unique();
+        let pyth_program = Pubkey::new_unique();
+        let geyser = MockGeyser::new(vec![account_update(
+            price_account,
+            pyth_program,
+            100,
+            pyth_price_account(4_200_000_000, -8, 100),
+        )])
+        .serve()
+        .await
+        .unwrap();
+
+        let listener = Arc::new(PriceListener::new(vec![mint.clone()]));
+        let endpoint = geyser.endpoint.clone();
+        let task = tokio::spawn({
+            let listener = Arc::clone(&listener);
+            async move { run_stream(listener, GeyserEndpoint::new(endpoint), StreamConfig::from_env()).await }
+        });
+        assert!(wait_for(|| geyser.requests().len() == 1).await);
+        assert!(geyser.requests()[0].accounts.is_empty());
+
+        assert!(listener.add_price_account(&mint, price_account));
+        assert!(!listener.add_price_account(&mint, price_account));
+        assert!(wait_for(|| get_current_price(&mint).is_some()).await);
+        assert!((get_current_price(&mint).unwrap() - 42.0).abs() < 1e-9);
+        assert_eq!(geyser.subscriptions(), 1);
+        assert_eq!(geyser.requests().len(), 2);
+
+        assert!(listener.remove_price_account(&price_account));
+        assert!(wait_for(|| geyser.requests().len() == 3).await);
+        assert!(geyser.requests()[2].accounts.is_empty());
+        assert_eq!(geyser.subscriptions(), 1);
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn klend_listener_only_receives_its_market() {
+        let program_id = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let ours = Pubkey::new_unique();
+        let geyser = MockGeyser::new(vec![
+            account_update(Pubkey::new_unique(), program_id, 10, reserve_account(Pubkey::new_unique())),
+            account_update(Pubkey::new_unique(), Pubkey::new_unique(), 11, reserve_account(lending_market)),
+            account_update(ours, program_id, 12, reserve_account(lending_market)),
+        ])
+        .serve()
+        .await
+        .unwrap();
+
+        let (sender, mut receiver) = event_channel();
+        let listener = spawn_listener(KlendAccountListener::new(program_id, lending_market, sender), &geyser.endpoint);
+
+        let event = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match event {
+            Event::Reserve { address, slot, reserve, .. } => {
+                assert_eq!(address, ours);
+                assert_eq!(slot, 12);
+                assert_eq!(reserve.lending_market, lending_market);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(tokio::time::timeout(Duration::from_millis(300), receiver.recv()).await.is_err());
+        listener.abort();
+    }
+
+    #[tokio::test]
+    async fn redundant_endpoints_deliver_each_account_version_once() {
+        let program_id = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let reserve = Pubkey::new_unique();
+        let script = vec![
+            account_update(reserve, program_id, 20, reserve_account(lending_market)),
+            account_update(reserve, program_id, 21, reserve_account(lending_market)),
+        ];
+        let fast = MockGeyser::new(script.clone()).serve().await.unwrap();
+        let slow = MockGeyser::new(script).serve().await.unwrap();
+
+        let (sender, mut receiver) = event_channel();
+        let listener = Arc::new(KlendAccountListener::new(program_id, lending_market, sender));
+        let sources = Arc::new(GeyserSources::new(vec!["fast".to_string(), "slow".to_string()]));
+        let endpoints = vec![GeyserEndpoint::new(fast.endpoint.clone()), GeyserEndpoint::new(slow.endpoint.clone())];
+        let task = tokio::spawn({
+            let sources = Arc::clone(&sources);
+            async move { run_redundant_streams(listener, endpoints, fast_reconnects(None), sources).await }
+        });
+
+        // Depending on arrival order slot 20 may be skipped, but every applied update is newer.
+        let mut slots = Vec::new();
+        while let Ok(Some(event)) = tokio::time::timeout(Duration::from_millis(500), receiver.recv()).await {
+            match event {
+                Event::Reserve { address, slot, .. } if address == reserve => slots.push(slot),
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+        assert_eq!(slots.last(), Some(&21));
+        assert!(slots.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", slots);
+
+        let stats = sources.sources();
+        assert!(stats.iter().all(|source| source.updates == 2));
+        assert_eq!(stats.iter().map(|source| source.first).sum::<u64>() as usize, slots.len());
+        assert_eq!(stats.iter().map(|source| source.first + source.late + source.stale).sum::<u64>(), 4);
+        assert_eq!(fast.requests().len(), 1);
+        assert_eq!(slow.requests().len(), 1);
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn blockhash_listener_tracks_block_meta_and_slots() {
+        let blockhash = Hash::new_unique();
+        let geyser = MockGeyser::new(vec![block_meta_update(500, blockhash.to_string()), slot_update(505)])
+            .serve()
+            .await
+            .unwrap();
+
+        let listener = spawn_listener(BlockHashListener::new(), &geyser.endpoint);
+        assert!(wait_for(|| current_slot() == Some(505)).await);
+        assert_eq!(get_cached_blockhash(100), Some((blockhash, 500)));
+        assert_eq!(get_cached_blockhash(1), None);
+        listener.abort();
+    }
+
+    fn fast_reconnects(gap_fill: Option<Arc<InMemoryAccountSource>>) -> StreamConfig {
+        StreamConfig {
+            initial_backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_millis(100),
+            ping_interval: Duration::from_millis(100),
+            stale_timeout: Duration::from_millis(400),
+            gap_fill: gap_fill.map(|source| source as Arc<dyn crate::account_source::AccountSource>),
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnects_and_gap_fills_accounts_missed_while_disconnected() {
+        let program_id = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let ours = Pubkey::new_unique();
+        let geyser = MockGeyser::new(vec![]).serve().await.unwrap();
+        let source = Arc::new(InMemoryAccountSource::new());
+
+        let (sender, mut receiver) = event_channel();
+        let listener = Arc::new(KlendAccountListener::new(program_id, lending_market, sender));
+        let endpoint = geyser.endpoint.clone();
+        let config = fast_reconnects(Some(Arc::clone(&source)));
+        let task = tokio::spawn(async move { run_stream(listener, GeyserEndpoint::new(endpoint), config).await });
+
+        assert!(wait_for(|| geyser.requests().len() == 1).await);
+        // The first subscription follows discovery, so nothing is re-read.
+        assert!(tokio::time::timeout(Duration::from_millis(300), receiver.recv()).await.is_err());
+
+        let account = |market| Account {
+            lamports: 1,
+            data: reserve_account(market),
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        };
+        source.insert(ours, account(lending_market));
+        source.insert(Pubkey::new_unique(), account(Pubkey::new_unique()));
+        source.set_slot(77);
+        geyser.disconnect();
+
+        let event = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match event {
+            Event::Reserve { address, slot, .. } => {
+                assert_eq!(address, ours);
+                assert_eq!(slot, 77);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(tokio::time::timeout(Duration::from_millis(300), receiver.recv()).await.is_err());
+        assert_eq!(geyser.requests().len(), 2);
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn keeps_answered_streams_and_reconnects_silent_ones() {
+        let answered = MockGeyser::new(vec![]).serve().await.unwrap();
+        let silent = MockGeyser::new(vec![]).without_pongs().serve().await.unwrap();
+
+        // A request timeout shorter than the test shows it does not cut open streams.
+        let tasks = [&answered, &silent].map(|geyser| {
+            let mut endpoint = GeyserEndpoint::new(geyser.endpoint.clone());
+            endpoint.request_timeout = Duration::from_millis(200);
+            tokio::spawn(async move {
+                run_stream(Arc::new(BlockHashListener::new()), endpoint, fast_reconnects(None)).await
+            })
+        });
+
+        assert!(wait_for(|| silent.requests().len() >= 2).await);
+        assert_eq!(answered.requests().len(), 1);
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticates_with_x_token_and_overrides_commitment() {
+        let geyser = MockGeyser::new(vec![]).with_x_token("secret").serve().await.unwrap();
+        let endpoints = [
+            GeyserEndpoint {
+                x_token: Some("wrong".to_string()),
+                ..GeyserEndpoint::new(geyser.endpoint.clone())
+            },
+            GeyserEndpoint {
+                x_token: Some("secret".to_string()),
+                commitment: Some(CommitmentLevel::Finalized),
+                ..GeyserEndpoint::new(geyser.endpoint.clone())
+            },
+        ];
+
+        let tasks = endpoints.map(|endpoint| {
+            tokio::spawn(async move {
+                run_stream(Arc::new(BlockHashListener::new()), endpoint, fast_reconnects(None)).await
+            })
+        });
+
+        assert!(wait_for(|| !geyser.requests().is_empty()).await);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let requests = geyser.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].commitment, Some(CommitmentLevel::Finalized as i32));
+        for task in tasks {
+            task.abort();
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::Result;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::rpc_config::RpcProgramAccountsConfig;
+use solana_sdk::{account::Account, account::AccountSharedData, pubkey::Pubkey};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use yellowstone_grpc_proto::geyser::{subscribe_update::UpdateOneof, SubscribeUpdateAccountInfo};
+
+// In-process Solana PubSub websocket server: every accountSubscribe, programSubscribe and
+// slotSubscribe is answered with the scripted updates it matches. Subscribe and unsubscribe
+// requests are recorded; getVersion is answered but not recorded.
+#[derive(Clone)]
+pub struct MockPubsub {
+    script: Arc<Vec<UpdateOneof>>,
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+pub struct MockPubsubHandle {
+    pub endpoint: String,
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+    connections: Arc<AtomicUsize>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockPubsubHandle {
+    pub fn requests(&self) -> Vec<(String, Value)> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn methods(&self) -> Vec<String> {
+        self.requests().into_iter().map(|(method, _)| method).collect()
+    }
+
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MockPubsubHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl MockPubsub {
+    pub fn new(script: Vec<UpdateOneof>) -> Self {
+        MockPubsub {
+            script: Arc::new(script),
+            requests: Arc::new(Mutex::new(Vec::new())),
+            connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub async fn serve(self) -> Result<MockPubsubHandle> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let endpoint = format!("ws://{}", listener.local_addr()?);
+        let requests = Arc::clone(&self.requests);
+        let connections = Arc::clone(&self.connections);
+
+        let task = tokio::spawn(async move {
+            let mut sessions = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                self.connections.fetch_add(1, Ordering::Relaxed);
+                sessions.push(AbortOnDrop(tokio::spawn(self.clone().session(stream))));
+            }
+        });
+
+        Ok(MockPubsubHandle {
+            endpoint,
+            requests,
+            connections,
+            task,
+        })
+    }
+
+    async fn session(self, stream: TcpStream) {
+        let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await else {
+            return;
+        };
+        let mut next_subscription = 0u64;
+
+        while let Some(Ok(message)) = ws.next().await {
+            let Message::Text(text) = message else {
+                continue;
+            };
+            let Ok(request) = serde_json::from_str::<Value>(&text) else {
+                continue;
+            };
+            let id = request["id"].clone();
+            let method = request["method"].as_str().unwrap_or_default().to_string();
+            let params = request["params"].clone();
+
+            if method == "getVersion" {
+                let result = json!({"solana-core": "1.18.26", "feature-set": 0});
+                let _ = ws.send(response(&id, result)).await;
+                continue;
+            }
+            self.requests.lock().unwrap().push((method.clone(), params.clone()));
+
+            let Some(operation) = method.strip_suffix("Subscribe") else {
+                let _ = ws.send(response(&id, json!(true))).await;
+                continue;
+            };
+            next_subscription += 1;
+            let subscription = next_subscription;
+            if ws.send(response(&id, json!(subscription))).await.is_err() {
+                return;
+            }
+            for result in self.notifications(operation, &params) {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": format!("{}Notification", operation),
+                    "params": {"result": result, "subscription": subscription},
+                });
+                if ws.send(Message::Text(notification.to_string())).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn notifications(&self, operation: &str, params: &Value) -> Vec<Value> {
+        let target = params[0].as_str().and_then(|key| Pubkey::from_str(key).ok());
+        let program_config: Option<RpcProgramAccountsConfig> = serde_json::from_value(params[1].clone()).ok();
+
+        self.script
+            .iter()
+            .filter_map(|update| match (operation, update) {
+                ("account", UpdateOneof::Account(update)) => {
+                    let (pubkey, account) = decode(update.account.as_ref()?)?;
+                    (Some(pubkey) == target).then(|| {
+                        json!({
+                            "context": {"slot": update.slot},
+                            "value": UiAccount::encode(&pubkey, &account, UiAccountEncoding::Base64, None, None),
+                        })
+                    })
+                }
+                ("program", UpdateOneof::Account(update)) => {
+                    let (pubkey, account) = decode(update.account.as_ref()?)?;
+                    let shared = AccountSharedData::from(account.clone());
+                    let filters = program_config.as_ref().and_then(|config| config.filters.clone()).unwrap_or_default();
+                    (Some(account.owner) == target && filters.iter().all(|filter| filter.allows(&shared))).then(|| {
+                        json!({
+                            "context": {"slot": update.slot},
+                            "value": {
+                                "pubkey": pubkey.to_string(),
+                                "account": UiAccount::encode(&pubkey, &account, UiAccountEncoding::Base64, None, None),
+                            },
+                        })
+                    })
+                }
+                ("slot", UpdateOneof::Slot(update)) => Some(json!({
+                    "slot": update.slot,
+                    "parent": update.parent.unwrap_or_default(),
+                    "root": update.slot.saturating_sub(32),
+                })),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn response(id: &Value, result: Value) -> Message {
+    Message::Text(json!({"jsonrpc": "2.0", "result": result, "id": id}).to_string())
+}
+
+fn decode(info: &SubscribeUpdateAccountInfo) -> Option<(Pubkey, Account)> {
+    let pubkey = Pubkey::try_from(info.pubkey.as_slice()).ok()?;
+    let account = Account {
+        lamports: info.lamports,
+        data: info.data.clone(),
+        owner: Pubkey::try_from(info.owner.as_slice()).ok()?,
+        executable: info.executable,
+        rent_epoch: info.rent_epoch,
+    };
+    Some((pubkey, account))
+}
+
+mod tests {
+    use super::*;
+    use crate::events::{event_channel, Event};
+    use crate::geyser_stream::StreamConfig;
+    use crate::klend_listener::KlendAccountListener;
+    use crate::blockhash_listener::{current_slot, BlockHashListener};
+    use crate::mock_geyser::{account_update, pyth_price_account, reserve_account, slot_update};
+    use crate::price_listener::{get_current_price, PriceListener};
+    use crate::pubsub_stream::run_pubsub_stream;
+    use std::time::Duration;
+
+    fn fast_reconnects() -> StreamConfig {
+        StreamConfig {
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(200),
+            ping_interval: Duration::from_millis(100),
+            stale_timeout: Duration::from_secs(30),
+            gap_fill: None,
+        }
+    }
+
+    async fn wait_for<F: Fn() -> bool>(condition: F) -> bool {
+        for _ in 0..100 {
+            if condition() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn price_listener_follows_subscription_changes_over_websocket() {
+        let mint = Pubkey::new_unique().to_string();
+        let price_account = Pubkey::new_unique();
+        let pubsub = MockPubsub::new(vec![account_update(
+            price_account,
+            Pubkey::new_unique(),
+            100,
+            pyth_price_account(250_000_000, -8, 100),
+        )])
+        .serve()
+        .await
+        .unwrap();
+
+        let listener = Arc::new(PriceListener::new(vec![mint.clone()]));
+        let task = tokio::spawn(run_pubsub_stream(Arc::clone(&listener), pubsub.endpoint.clone(), fast_reconnects()));
+        // Only the heartbeat until the listener has accounts.
+        assert!(wait_for(|| pubsub.methods() == ["slotSubscribe"]).await);
+        assert_eq!(pubsub.connections(), 1);
+
+        assert!(listener.add_price_account(&mint, price_account));
+        assert!(wait_for(|| get_current_price(&mint).is_some()).await);
+        assert!((get_current_price(&mint).unwrap() - 2.5).abs() < 1e-9);
+        let requests = pubsub.requests();
+        assert_eq!(requests[1].0, "accountSubscribe");
+        assert_eq!(requests[1].1[0], price_account.to_string());
+        assert_eq!(requests[1].1[1]["encoding"], "base64");
+
+        assert!(listener.remove_price_account(&price_account));
+        assert!(wait_for(|| pubsub.methods() == ["slotSubscribe", "accountSubscribe", "accountUnsubscribe"]).await);
+        assert_eq!(pubsub.connections(), 1);
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn klend_listener_receives_its_market_from_program_notifications() {
+        let program_id = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let ours = Pubkey::new_unique();
+        let pubsub = MockPubsub::new(vec![
+            account_update(Pubkey::new_unique(), program_id, 10, reserve_account(Pubkey::new_unique())),
+            account_update(ours, program_id, 12, reserve_account(lending_market)),
+        ])
+        .serve()
+        .await
+        .unwrap();
+
+        let (sender, mut receiver) = event_channel();
+        let listener = Arc::new(KlendAccountListener::new(program_id, lending_market, sender));
+        let task = tokio::spawn(run_pubsub_stream(listener, pubsub.endpoint.clone(), fast_reconnects()));
+
+        let event = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match event {
+            Event::Reserve { address, slot, reserve, .. } => {
+                assert_eq!(address, ours);
+                assert_eq!(slot, 12);
+                assert_eq!(reserve.lending_market, lending_market);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(tokio::time::timeout(Duration::from_millis(300), receiver.recv()).await.is_err());
+
+        let requests = pubsub.requests();
+        assert_eq!(pubsub.methods(), ["slotSubscribe", "programSubscribe"]);
+        assert_eq!(requests[1].1[0], program_id.to_string());
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn reconnects_a_websocket_whose_heartbeat_goes_silent() {
+        let mint = Pubkey::new_unique().to_string();
+        let price_account = Pubkey::new_unique();
+        let pubsub = MockPubsub::new(vec![slot_update(300)]).serve().await.unwrap();
+
+        let listener = Arc::new(PriceListener::new(vec![mint.clone()]));
+        assert!(listener.add_price_account(&mint, price_account));
+        let config = StreamConfig {
+            stale_timeout: Duration::from_millis(300),
+            ..fast_reconnects()
+        };
+        let task = tokio::spawn(run_pubsub_stream(listener, pubsub.endpoint.clone(), config));
+
+        // Each connection gets one slot, then nothing: it is dropped and resubscribed.
+        assert!(wait_for(|| pubsub.connections() >= 3).await);
+        let methods = pubsub.methods();
+        assert!(methods.iter().filter(|method| *method == "slotSubscribe").count() >= 2, "{:?}", methods);
+        assert!(methods.iter().filter(|method| *method == "accountSubscribe").count() >= 2, "{:?}", methods);
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn blockhash_listener_tracks_slots_over_websocket() {
+        let pubsub = MockPubsub::new(vec![slot_update(400), slot_update(401)]).serve().await.unwrap();
+
+        let task = tokio::spawn(run_pubsub_stream(
+            Arc::new(BlockHashListener::new()),
+            pubsub.endpoint.clone(),
+            fast_reconnects(),
+        ));
+
+        assert!(wait_for(|| pubsub.methods() == ["slotSubscribe"]).await);
+        // Other tests share the slot state and may have moved it further.
+        assert!(wait_for(|| current_slot().is_some_and(|slot| slot >= 401)).await);
+        task.abort();
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+type Handler = dyn Fn(&str, &Value) -> std::result::Result<Value, Value> + Send + Sync;
+
+// In-process JSON-RPC over HTTP server for the Solana RPC and the Jito block engine. Each
+// request is answered by the handler with a result or, on Err, a JSON-RPC error object.
+// Requests are recorded except getVersion, which is answered here.
+pub struct MockRpc {
+    handler: Arc<Handler>,
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+}
+
+pub struct MockRpcHandle {
+    pub url: String,
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockRpcHandle {
+    pub fn requests(&self) -> Vec<(String, Value)> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn methods(&self) -> Vec<String> {
+        self.requests().into_iter().map(|(method, _)| method).collect()
+    }
+}
+
+impl Drop for MockRpcHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl MockRpc {
+    pub fn new(handler: impl Fn(&str, &Value) -> std::result::Result<Value, Value> + Send + Sync + 'static) -> Self {
+        MockRpc {
+            handler: Arc::new(handler),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub async fn serve(self) -> Result<MockRpcHandle> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let requests = Arc::clone(&self.requests);
+
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = Arc::clone(&self.handler);
+                let requests = Arc::clone(&self.requests);
+                tokio::spawn(async move {
+                    let _ = connection(stream, handler.as_ref(), &requests).await;
+                });
+            }
+        });
+
+        Ok(MockRpcHandle { url, requests, task })
+    }
+}
+
+async fn connection(mut stream: TcpStream, handler: &Handler, requests: &Mutex<Vec<(String, Value)>>) -> Result<()> {
+    let mut buffered = Vec::new();
+    let mut buffer = [0u8; 8192];
+
+    // Keep-alive: serve requests until the client closes the connection.
+    loop {
+        let (headers_end, content_length) = loop {
+            if let Some(headers_end) = buffered.windows(4).position(|window| window == b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&buffered[..headers_end]).to_lowercase();
+                let content_length = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .and_then(|value| value.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                break (headers_end + 4, content_length);
+            }
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            buffered.extend_from_slice(&buffer[..read]);
+        };
+        while buffered.len() < headers_end + content_length {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            buffered.extend_from_slice(&buffer[..read]);
+        }
+
+        let body: Vec<u8> = buffered.drain(..headers_end + content_length).skip(headers_end).collect();
+        let request: Value = serde_json::from_slice(&body).unwrap_or_default();
+        let method = request["method"].as_str().unwrap_or_default().to_string();
+        let params = request["params"].clone();
+
+        let outcome = if method == "getVersion" {
+            Ok(json!({"solana-core": "1.18.26", "feature-set": 0}))
+        } else {
+            requests.lock().unwrap().push((method.clone(), params.clone()));
+            handler(&method, &params)
+        };
+        let response = match outcome {
+            Ok(result) => json!({"jsonrpc": "2.0", "result": result, "id": request["id"]}),
+            Err(error) => json!({"jsonrpc": "2.0", "error": error, "id": request["id"]}),
+        }
+        .to_string();
+
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .as_bytes(),
+            )
+            .await?;
+    }
+}
+
+// The {context, value} envelope of RPC responses read at a slot.
+pub fn with_context(slot: u64, value: Value) -> Value {
+    json!({"context": {"slot": slot}, "value": value})
+}
@@ -0,0 +1,193 @@
+use crate::kamino::Obligation;
+use dashmap::{DashMap, DashSet};
+use solana_sdk::pubkey::Pubkey;
+use std::sync::Arc;
+
+// Obligations keyed by address, plus reverse indexes from reserve and mint to the
+// obligations that hold them so a single price or reserve update can be fanned out
+// to exactly the affected obligations.
+#[derive(Default)]
+pub struct ObligationStore {
+    obligations: DashMap<Pubkey, Arc<Obligation>>,
+    by_reserve: DashMap<Pubkey, DashSet<Pubkey>>,
+    by_mint: DashMap<String, DashSet<Pubkey>>,
+    reserve_to_mint: DashMap<Pubkey, String>,
+}
+
+impl ObligationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.obligations.len()
+    }
+
+    pub fn get(&self, address: &Pubkey) -> Option<Arc<Obligation>> {
+        self.obligations.get(address).map(|entry| Arc::clone(entry.value()))
+    }
+
+    pub fn addresses(&self) -> Vec<Pubkey> {
+        self.obligations.iter().map(|entry| *entry.key()).collect()
+    }
+
+    pub fn set_reserve_mint(&self, reserve: Pubkey, mint: String) {
+        let previous = self.reserve_to_mint.insert(reserve, mint.clone());
+        if previous.as_deref() == Some(mint.as_str()) {
+            return;
+        }
+
+        let holders = self.obligations_for_reserve(&reserve);
+        if let Some(previous) = previous {
+            if let Some(addresses) = self.by_mint.get(&previous) {
+                for holder in &holders {
+                    addresses.remove(holder);
+                }
+            }
+        }
+
+        let addresses = self.by_mint.entry(mint).or_default();
+        for holder in holders {
+            addresses.insert(holder);
+        }
+    }
+
+    pub fn mint_for_reserve(&self, reserve: &Pubkey) -> Option<String> {
+        self.reserve_to_mint.get(reserve).map(|entry| entry.value().clone())
+    }
+
+    pub fn insert(&self, address: Pubkey, obligation: Obligation) {
+        self.unindex(&address);
+
+        for reserve in obligation.get_reserve_addresses() {
+            self.by_reserve.entry(reserve).or_default().insert(address);
+            if let Some(mint) = self.mint_for_reserve(&reserve) {
+                self.by_mint.entry(mint).or_default().insert(address);
+            }
+        }
+        self.obligations.insert(address, Arc::new(obligation));
+    }
+
+    pub fn remove(&self, address: &Pubkey) -> Option<Arc<Obligation>> {
+        self.unindex(address);
+        self.obligations.remove(address).map(|(_, obligation)| obligation)
+    }
+
+    pub fn obligations_for_reserve(&self, reserve: &Pubkey) -> Vec<Pubkey> {
+        self.by_reserve
+            .get(reserve)
+            .map(|addresses| addresses.iter().map(|address| *address).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn obligations_for_mint(&self, mint: &str) -> Vec<Pubkey> {
+        self.by_mint
+            .get(mint)
+            .map(|addresses| addresses.iter().map(|address| *address).collect())
+            .unwrap_or_default()
+    }
+
+    fn unindex(&self, address: &Pubkey) {
+        let previous = match self.obligations.get(address) {
+            Some(entry) => entry.get_reserve_addresses(),
+            None => return,
+        };
+
+        for reserve in previous {
+            if let Some(addresses) = self.by_reserve.get(&reserve) {
+                addresses.remove(address);
+            }
+            if let Some(mint) = self.mint_for_reserve(&reserve) {
+                if let Some(addresses) = self.by_mint.get(&mint) {
+                    addresses.remove(address);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::zeroed;
+
+    fn obligation(deposits: &[Pubkey], borrows: &[Pubkey]) -> Obligation {
+        let mut obligation = zeroed::<Obligation>();
+        for (slot, reserve) in obligation.deposits.iter_mut().zip(deposits) {
+            slot.deposit_reserve = *reserve;
+            slot.deposited_amount = 1;
+        }
+        for (slot, reserve) in obligation.borrows.iter_mut().zip(borrows) {
+            slot.borrow_reserve = *reserve;
+            slot.borrowed_amount_sf = 1;
+        }
+        obligation
+    }
+
+    fn sorted(mut addresses: Vec<Pubkey>) -> Vec<Pubkey> {
+        addresses.sort();
+        addresses
+    }
+
+    #[test]
+    fn indexes_obligations_by_reserve_and_mint() {
+        let store = ObligationStore::new();
+        let (sol, usdc, jto) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        // A mint known before the obligation arrives is indexed on insert.
+        store.set_reserve_mint(sol, "sol".to_string());
+        store.insert(a, obligation(&[sol], &[usdc]));
+        store.insert(b, obligation(&[sol, jto], &[usdc]));
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(sorted(store.obligations_for_reserve(&sol)), sorted(vec![a, b]));
+        assert_eq!(store.obligations_for_reserve(&jto), vec![b]);
+        assert_eq!(sorted(store.obligations_for_mint("sol")), sorted(vec![a, b]));
+        assert!(store.obligations_for_mint("usdc").is_empty());
+
+        // A mint learned afterwards picks up the obligations already holding the reserve.
+        store.set_reserve_mint(usdc, "usdc".to_string());
+        assert_eq!(sorted(store.obligations_for_mint("usdc")), sorted(vec![a, b]));
+        assert_eq!(store.mint_for_reserve(&usdc).as_deref(), Some("usdc"));
+
+        // A reserve whose mint changes moves its holders to the new mint.
+        store.set_reserve_mint(jto, "jto".to_string());
+        store.set_reserve_mint(jto, "jto-v2".to_string());
+        assert!(store.obligations_for_mint("jto").is_empty());
+        assert_eq!(store.obligations_for_mint("jto-v2"), vec![b]);
+
+        assert!(store.obligations_for_reserve(&Pubkey::new_unique()).is_empty());
+    }
+
+    #[test]
+    fn later_updates_replace_earlier_ones_and_their_index_entries() {
+        let store = ObligationStore::new();
+        let (sol, usdc, msol) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let address = Pubkey::new_unique();
+        store.set_reserve_mint(sol, "sol".to_string());
+        store.set_reserve_mint(msol, "msol".to_string());
+
+        // Updates applied in slot order: the position moves from SOL to mSOL collateral.
+        store.insert(address, obligation(&[sol], &[usdc]));
+        store.insert(address, obligation(&[msol], &[usdc]));
+
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get(&address).unwrap().get_reserve_addresses(), sorted(vec![msol, usdc]));
+        assert!(store.obligations_for_reserve(&sol).is_empty());
+        assert!(store.obligations_for_mint("sol").is_empty());
+        assert_eq!(store.obligations_for_reserve(&msol), vec![address]);
+        assert_eq!(store.obligations_for_mint("msol"), vec![address]);
+        assert_eq!(store.obligations_for_reserve(&usdc), vec![address]);
+
+        // Closing the position drops every index entry.
+        assert!(store.remove(&address).is_some());
+        assert!(store.remove(&address).is_none());
+        assert_eq!(store.len(), 0);
+        assert!(store.get(&address).is_none());
+        assert!(store.obligations_for_reserve(&msol).is_empty());
+        assert!(store.obligations_for_reserve(&usdc).is_empty());
+        assert!(store.obligations_for_mint("msol").is_empty());
+        assert!(store.addresses().is_empty());
+    }
+}
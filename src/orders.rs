@@ -0,0 +1,227 @@
+use crate::health::ObligationHealth;
+use crate::kamino::{ConditionType, Obligation, ObligationOrder, OpportunityType};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderEvaluation {
+    pub order_index: usize,
+    pub condition: ConditionType,
+    pub opportunity: OpportunityType,
+    pub threshold: f64,
+    pub current_value: f64,
+    pub supported: bool,
+    pub triggered: bool,
+    pub execution_bonus_rate: f64,
+    pub repay_reserve: String,
+    pub withdraw_reserve: String,
+    pub repay_amount: f64,
+    pub repay_value: f64,
+    pub expected_bonus_value: f64,
+}
+
+pub fn evaluate_orders(obligation: &Obligation, health: &ObligationHealth) -> Vec<OrderEvaluation> {
+    obligation
+        .active_orders()
+        .filter_map(|(index, order)| evaluate_order(index, order, obligation, health))
+        .collect()
+}
+
+pub fn evaluate_order(
+    order_index: usize,
+    order: &ObligationOrder,
+    obligation: &Obligation,
+    health: &ObligationHealth,
+) -> Option<OrderEvaluation> {
+    let condition = order.condition_type()?;
+    let opportunity = order.opportunity_type()?;
+    let threshold = order.condition_threshold();
+    let supported = order.is_supported_by(obligation);
+
+    let repay = health.largest_borrow()?;
+    let withdraw = health.largest_deposit()?;
+
+    let current_value = match condition {
+        ConditionType::Never => return None,
+        ConditionType::UserLtvAbove | ConditionType::UserLtvBelow => health.ltv,
+        ConditionType::DebtCollPriceRatioAbove | ConditionType::DebtCollPriceRatioBelow => {
+            if withdraw.price <= 0.0 {
+                return None;
+            }
+            repay.price / withdraw.price
+        }
+    };
+
+    let triggered = supported
+        && match condition {
+            ConditionType::UserLtvAbove | ConditionType::DebtCollPriceRatioAbove => {
+                current_value > threshold
+            }
+            ConditionType::UserLtvBelow | ConditionType::DebtCollPriceRatioBelow => {
+                current_value < threshold
+            }
+            ConditionType::Never => false,
+        };
+
+    let repay_amount = match opportunity {
+        OpportunityType::DeleverageAllDebt => repay.amount,
+        OpportunityType::DeleverageSingleDebtAmount => {
+            let parameter = order.opportunity_parameter() / 10f64.powi(repay.decimals as i32);
+            parameter.min(repay.amount)
+        }
+    };
+
+    let execution_bonus_rate = if triggered {
+        execution_bonus_rate(order, condition, threshold, current_value, health.ltv)
+    } else {
+        0.0
+    };
+    let repay_value = repay_amount * repay.price;
+
+    Some(OrderEvaluation {
+        order_index,
+        condition,
+        opportunity,
+        threshold,
+        current_value,
+        supported,
+        triggered,
+        execution_bonus_rate,
+        repay_reserve: repay.reserve_address.clone(),
+        withdraw_reserve: withdraw.reserve_address.clone(),
+        repay_amount,
+        repay_value,
+        expected_bonus_value: repay_value * execution_bonus_rate,
+    })
+}
+
+// Interpolates between the order's min and max bonus by how far the condition has been
+// crossed, normalized to [0, 1], and never pays more than the collateral can cover.
+pub fn execution_bonus_rate(
+    order: &ObligationOrder,
+    condition: ConditionType,
+    threshold: f64,
+    current_value: f64,
+    ltv: f64,
+) -> f64 {
+    let range = order.execution_bonus_rate_range();
+    let (min_bonus, max_bonus) = (*range.start(), *range.end());
+
+    let distance = match condition {
+        ConditionType::UserLtvAbove if threshold < 1.0 => (current_value - threshold) / (1.0 - threshold),
+        ConditionType::UserLtvAbove | ConditionType::DebtCollPriceRatioAbove if current_value > 0.0 => {
+            (current_value - threshold) / current_value
+        }
+        ConditionType::UserLtvBelow | ConditionType::DebtCollPriceRatioBelow if threshold > 0.0 => {
+            (threshold - current_value) / threshold
+        }
+        _ => 0.0,
+    }
+    .clamp(0.0, 1.0);
+
+    let bonus = min_bonus + (max_bonus - min_bonus) * distance;
+    let bad_debt_cap = if ltv > 0.0 { (1.0 / ltv - 1.0).max(0.0) } else { bonus };
+    bonus.min(bad_debt_cap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kamino::f64_to_sf;
+    use crate::test_fixtures::{position, zeroed};
+    use solana_sdk::pubkey::Pubkey;
+
+    fn order(condition: ConditionType, threshold: f64, opportunity: OpportunityType, parameter: f64) -> ObligationOrder {
+        ObligationOrder {
+            condition_threshold_sf: f64_to_sf(threshold),
+            opportunity_parameter_sf: f64_to_sf(parameter),
+            min_execution_bonus_bps: 100,
+            max_execution_bonus_bps: 500,
+            condition_type: condition as u8,
+            opportunity_type: opportunity as u8,
+            ..Default::default()
+        }
+    }
+
+    // 1 SOL at $140 against 98 USDC: LTV 0.7 and a debt/collateral price ratio of 1/140.
+    fn single_position() -> (Obligation, ObligationHealth) {
+        let (sol, usdc) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut obligation = zeroed::<Obligation>();
+        obligation.deposits[0].deposit_reserve = sol;
+        obligation.borrows[0].borrow_reserve = usdc;
+        let health = ObligationHealth {
+            ltv: 0.7,
+            deposits: vec![position(sol, 1.0, 9, 140.0)],
+            borrows: vec![position(usdc, 98.0, 6, 1.0)],
+            ..Default::default()
+        };
+        (obligation, health)
+    }
+
+    fn bonus(condition: ConditionType, threshold: f64, current_value: f64, ltv: f64) -> f64 {
+        let order = order(condition, threshold, OpportunityType::DeleverageAllDebt, 0.0);
+        execution_bonus_rate(&order, condition, threshold, current_value, ltv)
+    }
+
+    #[test]
+    fn interpolates_the_bonus_by_how_far_the_condition_is_crossed() {
+        // LTV above 0.6 normalizes over the remaining room to 1.0: 0.1 / 0.4 = 0.25 of the way
+        // from 1% to 5%.
+        assert!((bonus(ConditionType::UserLtvAbove, 0.6, 0.7, 0.7) - 0.02).abs() < 1e-9);
+        assert!((bonus(ConditionType::UserLtvAbove, 0.6, 0.6, 0.6) - 0.01).abs() < 1e-9);
+        assert!((bonus(ConditionType::UserLtvAbove, 0.6, 0.95, 0.95) - 0.045).abs() < 1e-9);
+        // Below conditions normalize by the threshold: (0.5 - 0.4) / 0.5 = 0.2.
+        assert!((bonus(ConditionType::UserLtvBelow, 0.5, 0.4, 0.4) - 0.018).abs() < 1e-9);
+        assert!((bonus(ConditionType::UserLtvBelow, 0.5, 0.0, 0.4) - 0.05).abs() < 1e-9);
+        // Ratio above normalizes by the current value: (0.01 - 0.008) / 0.01 = 0.2.
+        assert!((bonus(ConditionType::DebtCollPriceRatioAbove, 0.008, 0.01, 0.5) - 0.018).abs() < 1e-9);
+        assert!((bonus(ConditionType::DebtCollPriceRatioBelow, 0.01, 0.0075, 0.5) - 0.02).abs() < 1e-9);
+    }
+
+    #[test]
+    fn caps_the_bonus_at_what_the_collateral_covers() {
+        // 0.95 of the way gives 4.8%, but at LTV 0.98 the collateral only covers 1/0.98 - 1.
+        let capped = bonus(ConditionType::UserLtvAbove, 0.6, 0.98, 0.98);
+        assert!((capped - (1.0 / 0.98 - 1.0)).abs() < 1e-9);
+        assert_eq!(bonus(ConditionType::UserLtvAbove, 0.6, 1.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn sizes_a_triggered_order_from_its_parameter() {
+        let (mut obligation, health) = single_position();
+        // Repay 50 USDC, in base units, of the 98 borrowed.
+        obligation.orders[0] = order(ConditionType::UserLtvAbove, 0.6, OpportunityType::DeleverageSingleDebtAmount, 50e6);
+
+        let evaluations = evaluate_orders(&obligation, &health);
+        assert_eq!(evaluations.len(), 1);
+        let evaluation = &evaluations[0];
+        assert!(evaluation.supported && evaluation.triggered);
+        assert!((evaluation.current_value - 0.7).abs() < 1e-9);
+        assert!((evaluation.repay_amount - 50.0).abs() < 1e-9);
+        assert!((evaluation.execution_bonus_rate - 0.02).abs() < 1e-9);
+        assert!((evaluation.expected_bonus_value - 1.0).abs() < 1e-9);
+
+        // Repaying all debt, or more than is borrowed, repays the 98 USDC.
+        obligation.orders[0] = order(ConditionType::UserLtvAbove, 0.6, OpportunityType::DeleverageSingleDebtAmount, 500e6);
+        assert!((evaluate_orders(&obligation, &health)[0].repay_amount - 98.0).abs() < 1e-9);
+        obligation.orders[0] = order(ConditionType::UserLtvAbove, 0.6, OpportunityType::DeleverageAllDebt, 0.0);
+        assert!((evaluate_orders(&obligation, &health)[0].repay_value - 98.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn does_not_trigger_uncrossed_or_unsupported_orders() {
+        let (mut obligation, health) = single_position();
+        obligation.orders[0] = order(ConditionType::UserLtvAbove, 0.75, OpportunityType::DeleverageAllDebt, 0.0);
+        obligation.orders[1] = order(ConditionType::DebtCollPriceRatioAbove, 0.005, OpportunityType::DeleverageAllDebt, 0.0);
+
+        let evaluations = evaluate_orders(&obligation, &health);
+        assert!(!evaluations[0].triggered);
+        assert_eq!(evaluations[0].execution_bonus_rate, 0.0);
+        assert!((evaluations[1].current_value - 1.0 / 140.0).abs() < 1e-12);
+        assert!(evaluations[1].triggered);
+
+        // Price ratio conditions need a single collateral.
+        obligation.deposits[1].deposit_reserve = Pubkey::new_unique();
+        let evaluations = evaluate_orders(&obligation, &health);
+        assert!(!evaluations[1].supported && !evaluations[1].triggered);
+    }
+}
@@ -0,0 +1,562 @@
+use crate::kamino::{Obligation, Reserve};
+use crate::price_listener::TokenPrice;
+use crate::snapshot::ObligationInfo;
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS obligations (
+        address TEXT PRIMARY KEY,
+        slot INTEGER NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS obligation_history (
+        address TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS obligation_history_address ON obligation_history (address, slot);
+    CREATE TABLE IF NOT EXISTS reserves (
+        address TEXT PRIMARY KEY,
+        slot INTEGER NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS snapshots (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        slot INTEGER NOT NULL,
+        created_at INTEGER NOT NULL,
+        obligation_count INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS prices (
+        snapshot_id INTEGER NOT NULL,
+        mint TEXT NOT NULL,
+        price REAL NOT NULL,
+        confidence REAL NOT NULL,
+        publish_slot INTEGER NOT NULL,
+        publish_time INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS health (
+        snapshot_id INTEGER NOT NULL,
+        obligation TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        deposited_value REAL NOT NULL,
+        borrowed_value REAL NOT NULL,
+        ltv REAL NOT NULL,
+        liquidation_ltv REAL NOT NULL,
+        health_factor REAL NOT NULL,
+        is_liquidatable INTEGER NOT NULL,
+        prices_trusted INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS health_obligation ON health (obligation, snapshot_id);
+";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HealthHistoryEntry {
+    pub snapshot_id: i64,
+    pub snapshot_slot: u64,
+    pub created_at: i64,
+    pub slot: u64,
+    pub deposited_value: f64,
+    pub borrowed_value: f64,
+    pub ltv: f64,
+    pub liquidation_ltv: f64,
+    pub health_factor: f64,
+    pub is_liquidatable: bool,
+    pub prices_trusted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ObligationVersion {
+    pub slot: u64,
+    pub recorded_at: i64,
+    pub deposited_value_sf: String,
+    pub borrowed_value_sf: String,
+    pub has_debt: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthRow {
+    pub obligation: String,
+    pub slot: u64,
+    pub deposited_value: f64,
+    pub borrowed_value: f64,
+    pub ltv: f64,
+    pub liquidation_ltv: f64,
+    pub health_factor: f64,
+    pub is_liquidatable: bool,
+    pub prices_trusted: bool,
+}
+
+impl From<&ObligationInfo> for HealthRow {
+    fn from(info: &ObligationInfo) -> Self {
+        HealthRow {
+            obligation: info.obligation_address.clone(),
+            slot: info.slot,
+            deposited_value: info.health.deposited_value,
+            borrowed_value: info.health.borrowed_value,
+            ltv: info.health.ltv,
+            liquidation_ltv: info.health.liquidation_ltv,
+            health_factor: info.health.health_factor,
+            is_liquidatable: info.health.is_liquidatable,
+            prices_trusted: info.health.prices_trusted,
+        }
+    }
+}
+
+pub struct WarmState {
+    pub slot: u64,
+    pub age_secs: u64,
+    pub obligations: Vec<(Obligation, Pubkey)>,
+    pub reserves: HashMap<Pubkey, Reserve>,
+}
+
+enum WriteOp {
+    Obligation { address: Pubkey, slot: u64, data: Vec<u8> },
+    Reserve { address: Pubkey, slot: u64, data: Vec<u8> },
+    Snapshot { slot: u64, health: Vec<HealthRow>, prices: Vec<TokenPrice> },
+    #[cfg(test)]
+    Flush(mpsc::Sender<()>),
+}
+
+// Writes go through a dedicated thread with its own connection so the async pipeline never
+// blocks on SQLite; reads open a separate connection against the same WAL database.
+#[derive(Clone)]
+pub struct Database {
+    path: PathBuf,
+    writer: mpsc::Sender<WriteOp>,
+}
+
+impl Database {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let connection = open_connection(&path)?;
+        connection.execute_batch(SCHEMA)?;
+
+        let (writer, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("sqlite-writer".to_string())
+            .spawn(move || run_writer(connection, receiver))?;
+
+        info!("Persistence enabled at {}", path.display());
+        Ok(Database { path, writer })
+    }
+
+    // Persistence is opt-in: the history tables grow with every update and snapshot.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var("DATABASE_PATH") {
+            Ok(path) if !path.is_empty() => Database::open(path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn record_obligation(&self, address: Pubkey, slot: u64, obligation: &Obligation) {
+        match obligation.try_to_vec() {
+            Ok(data) => self.send(WriteOp::Obligation { address, slot, data }),
+            Err(e) => error!("Failed to serialize obligation {}: {}", address, e),
+        }
+    }
+
+    pub fn record_reserve(&self, address: Pubkey, slot: u64, reserve: &Reserve) {
+        match reserve.try_to_vec() {
+            Ok(data) => self.send(WriteOp::Reserve { address, slot, data }),
+            Err(e) => error!("Failed to serialize reserve {}: {}", address, e),
+        }
+    }
+
+    pub fn record_snapshot(&self, slot: u64, health: Vec<HealthRow>, prices: Vec<TokenPrice>) {
+        self.send(WriteOp::Snapshot { slot, health, prices });
+    }
+
+    // Waits until every write sent before it is committed.
+    #[cfg(test)]
+    fn flush(&self) {
+        let (done, flushed) = mpsc::channel();
+        self.send(WriteOp::Flush(done));
+        flushed.recv().unwrap();
+    }
+
+    fn send(&self, op: WriteOp) {
+        if self.writer.send(op).is_err() {
+            error!("SQLite writer thread has stopped, dropping write");
+        }
+    }
+
+    pub fn latest_snapshot(&self) -> Result<Option<(u64, i64)>> {
+        let connection = open_connection(&self.path)?;
+        Ok(connection
+            .query_row(
+                "SELECT slot, created_at FROM snapshots ORDER BY id DESC LIMIT 1",
+                [],
+                |row| Ok((row.get::<_, i64>(0)? as u64, row.get(1)?)),
+            )
+            .optional()?)
+    }
+
+    pub fn load_obligations(&self) -> Result<Vec<(Obligation, Pubkey)>> {
+        let connection = open_connection(&self.path)?;
+        let mut statement = connection.prepare("SELECT address, data FROM obligations")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })?;
+
+        let mut obligations = Vec::new();
+        for row in rows {
+            let (address, data) = row?;
+            obligations.push((Obligation::try_from_slice(&data)?, parse_pubkey(&address)?));
+        }
+        Ok(obligations)
+    }
+
+    pub fn load_reserves(&self) -> Result<HashMap<Pubkey, Reserve>> {
+        let connection = open_connection(&self.path)?;
+        let mut statement = connection.prepare("SELECT address, data FROM reserves")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })?;
+
+        let mut reserves = HashMap::new();
+        for row in rows {
+            let (address, data) = row?;
+            reserves.insert(parse_pubkey(&address)?, Reserve::try_from_slice(&data)?);
+        }
+        Ok(reserves)
+    }
+
+    // Returns the persisted obligations and reserves if the last snapshot is younger than
+    // max_age, so startup can skip the getProgramAccounts scan.
+    pub fn warm_state(&self, max_age: Duration) -> Result<Option<WarmState>> {
+        let (slot, created_at) = match self.latest_snapshot()? {
+            Some(latest) => latest,
+            None => return Ok(None),
+        };
+        let age = chrono::Utc::now().timestamp() - created_at;
+        if age < 0 || age as u64 > max_age.as_secs() {
+            info!("Persisted state is {}s old, ignoring it", age);
+            return Ok(None);
+        }
+
+        let obligations: Vec<(Obligation, Pubkey)> = self
+            .load_obligations()?
+            .into_iter()
+            .filter(|(obligation, _)| obligation.has_active_borrows())
+            .collect();
+        let reserves = self.load_reserves()?;
+        if obligations.is_empty() || reserves.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(WarmState { slot, age_secs: age as u64, obligations, reserves }))
+    }
+
+    pub fn health_history(&self, obligation: &Pubkey, limit: usize) -> Result<Vec<HealthHistoryEntry>> {
+        let connection = open_connection(&self.path)?;
+        let mut statement = connection.prepare(
+            "SELECT s.id, s.slot, s.created_at, h.slot, h.deposited_value, h.borrowed_value, h.ltv,
+                    h.liquidation_ltv, h.health_factor, h.is_liquidatable, h.prices_trusted
+             FROM health h JOIN snapshots s ON s.id = h.snapshot_id
+             WHERE h.obligation = ?1
+             ORDER BY s.id DESC LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![obligation.to_string(), limit as i64], |row| {
+            Ok(HealthHistoryEntry {
+                snapshot_id: row.get(0)?,
+                snapshot_slot: row.get::<_, i64>(1)? as u64,
+                created_at: row.get(2)?,
+                slot: row.get::<_, i64>(3)? as u64,
+                deposited_value: row.get(4)?,
+                borrowed_value: row.get(5)?,
+                ltv: row.get(6)?,
+                liquidation_ltv: row.get(7)?,
+                health_factor: row.get(8)?,
+                is_liquidatable: row.get(9)?,
+                prices_trusted: row.get(10)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn obligation_versions(&self, obligation: &Pubkey, limit: usize) -> Result<Vec<ObligationVersion>> {
+        let connection = open_connection(&self.path)?;
+        let mut statement = connection.prepare(
+            "SELECT slot, recorded_at, data FROM obligation_history
+             WHERE address = ?1 ORDER BY slot DESC LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![obligation.to_string(), limit as i64], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Vec<u8>>(2)?))
+        })?;
+
+        let mut versions = Vec::new();
+        for row in rows {
+            let (slot, recorded_at, data) = row?;
+            let decoded = Obligation::try_from_slice(&data)?;
+            versions.push(ObligationVersion {
+                slot: slot as u64,
+                recorded_at,
+                deposited_value_sf: decoded.deposited_value_sf.to_string(),
+                borrowed_value_sf: decoded.borrowed_assets_market_value_sf.to_string(),
+                has_debt: decoded.has_debt != 0,
+            });
+        }
+        Ok(versions)
+    }
+}
+
+fn open_connection(path: &Path) -> Result<Connection> {
+    let connection = Connection::open(path)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.pragma_update(None, "synchronous", "NORMAL")?;
+    Ok(connection)
+}
+
+fn parse_pubkey(value: &str) -> Result<Pubkey> {
+    Pubkey::from_str(value).map_err(|e| anyhow!("invalid pubkey {} in database: {}", value, e))
+}
+
+fn run_writer(mut connection: Connection, receiver: mpsc::Receiver<WriteOp>) {
+    while let Ok(op) = receiver.recv() {
+        let mut batch = vec![op];
+        batch.extend(receiver.try_iter());
+        #[cfg(test)]
+        let flushes: Vec<mpsc::Sender<()>> = batch
+            .iter()
+            .filter_map(|op| match op {
+                WriteOp::Flush(done) => Some(done.clone()),
+                _ => None,
+            })
+            .collect();
+        if let Err(e) = write_batch(&mut connection, batch) {
+            error!("Failed to persist batch: {:?}", e);
+        }
+        #[cfg(test)]
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
+
+fn write_batch(connection: &mut Connection, batch: Vec<WriteOp>) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    let transaction = connection.transaction()?;
+
+    for op in batch {
+        match op {
+            WriteOp::Obligation { address, slot, data } => {
+                transaction.execute(
+                    "INSERT INTO obligations (address, slot, recorded_at, data) VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(address) DO UPDATE SET slot = ?2, recorded_at = ?3, data = ?4
+                     WHERE excluded.slot >= obligations.slot",
+                    params![address.to_string(), slot as i64, now, data],
+                )?;
+                transaction.execute(
+                    "INSERT INTO obligation_history (address, slot, recorded_at, data) VALUES (?1, ?2, ?3, ?4)",
+                    params![address.to_string(), slot as i64, now, data],
+                )?;
+            }
+            WriteOp::Reserve { address, slot, data } => {
+                transaction.execute(
+                    "INSERT INTO reserves (address, slot, recorded_at, data) VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(address) DO UPDATE SET slot = ?2, recorded_at = ?3, data = ?4
+                     WHERE excluded.slot >= reserves.slot",
+                    params![address.to_string(), slot as i64, now, data],
+                )?;
+            }
+            WriteOp::Snapshot { slot, health, prices } => {
+                transaction.execute(
+                    "INSERT INTO snapshots (slot, created_at, obligation_count) VALUES (?1, ?2, ?3)",
+                    params![slot as i64, now, health.len() as i64],
+                )?;
+                let snapshot_id = transaction.last_insert_rowid();
+
+                let mut insert_health = transaction.prepare_cached(
+                    "INSERT INTO health (snapshot_id, obligation, slot, deposited_value, borrowed_value, ltv,
+                                         liquidation_ltv, health_factor, is_liquidatable, prices_trusted)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                )?;
+                for row in &health {
+                    insert_health.execute(params![
+                        snapshot_id,
+                        row.obligation,
+                        row.slot as i64,
+                        row.deposited_value,
+                        row.borrowed_value,
+                        row.ltv,
+                        row.liquidation_ltv,
+                        row.health_factor,
+                        row.is_liquidatable,
+                        row.prices_trusted,
+                    ])?;
+                }
+
+                let mut insert_price = transaction.prepare_cached(
+                    "INSERT INTO prices (snapshot_id, mint, price, confidence, publish_slot, publish_time)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?;
+                for price in &prices {
+                    insert_price.execute(params![
+                        snapshot_id,
+                        price.mint,
+                        price.price,
+                        price.confidence,
+                        price.publish_slot as i64,
+                        price.publish_time.map(|time| time.timestamp()),
+                    ])?;
+                }
+            }
+            #[cfg(test)]
+            WriteOp::Flush(_) => {}
+        }
+    }
+
+    transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::zeroed;
+    use chrono::Utc;
+
+    // A shared-cache in-memory database, kept alive by the writer thread's connection.
+    fn in_memory() -> Database {
+        Database::open(format!("file:persistence-{}?mode=memory&cache=shared", Pubkey::new_unique())).unwrap()
+    }
+
+    fn obligation(deposited_value_sf: u128, borrowed_amount_sf: u128) -> Obligation {
+        let mut obligation = zeroed::<Obligation>();
+        obligation.deposited_value_sf = deposited_value_sf;
+        obligation.has_debt = (borrowed_amount_sf > 0) as u8;
+        obligation.borrows[0].borrow_reserve = Pubkey::new_unique();
+        obligation.borrows[0].borrowed_amount_sf = borrowed_amount_sf;
+        obligation
+    }
+
+    fn health(obligation: &Pubkey, slot: u64, health_factor: f64) -> HealthRow {
+        HealthRow {
+            obligation: obligation.to_string(),
+            slot,
+            deposited_value: 200.0,
+            borrowed_value: 100.0,
+            ltv: 0.5,
+            liquidation_ltv: 0.8,
+            health_factor,
+            is_liquidatable: health_factor < 1.0,
+            prices_trusted: true,
+        }
+    }
+
+    fn price(mint: &str) -> TokenPrice {
+        TokenPrice {
+            mint: mint.to_string(),
+            symbol: "SOL".to_string(),
+            price: 150.0,
+            confidence: 0.1,
+            last_updated: Utc::now(),
+            publish_time: Some(Utc::now()),
+            publish_slot: 100,
+            status: "Trading".to_string(),
+            ema_price: 150.0,
+            oracle_ema_price: None,
+            oracle_ema_confidence: None,
+        }
+    }
+
+    #[test]
+    fn records_the_newest_account_versions_and_obligation_history() {
+        let database = in_memory();
+        let address = Pubkey::new_unique();
+        let reserve_address = Pubkey::new_unique();
+        let mut reserve = zeroed::<Reserve>();
+
+        database.record_obligation(address, 10, &obligation(1, 5));
+        database.record_obligation(address, 12, &obligation(3, 0));
+        // A late write for an older slot only lands in the history.
+        database.record_obligation(address, 11, &obligation(2, 5));
+        reserve.liquidity.mint_decimals = 6;
+        database.record_reserve(reserve_address, 20, &reserve);
+        reserve.liquidity.mint_decimals = 9;
+        database.record_reserve(reserve_address, 19, &reserve);
+        database.flush();
+
+        let obligations = database.load_obligations().unwrap();
+        assert_eq!(obligations.len(), 1);
+        assert_eq!((obligations[0].0.deposited_value_sf, obligations[0].1), (3, address));
+        assert_eq!(database.load_reserves().unwrap()[&reserve_address].liquidity.mint_decimals, 6);
+
+        let versions = database.obligation_versions(&address, 10).unwrap();
+        let summary: Vec<(u64, &str, bool)> = versions
+            .iter()
+            .map(|version| (version.slot, version.deposited_value_sf.as_str(), version.has_debt))
+            .collect();
+        assert_eq!(summary, vec![(12, "3", false), (11, "2", true), (10, "1", true)]);
+        assert_eq!(database.obligation_versions(&address, 1).unwrap().len(), 1);
+        assert!(database.obligation_versions(&Pubkey::new_unique(), 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn returns_health_history_newest_snapshot_first() {
+        let database = in_memory();
+        let watched = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        database.record_snapshot(100, vec![health(&watched, 99, 1.4), health(&other, 99, 2.0)], vec![price("sol")]);
+        database.record_snapshot(110, vec![health(&watched, 108, 0.9)], vec![price("sol")]);
+        database.flush();
+
+        let history = database.health_history(&watched, 10).unwrap();
+        let summary: Vec<(u64, u64, f64, bool)> = history
+            .iter()
+            .map(|entry| (entry.snapshot_slot, entry.slot, entry.health_factor, entry.is_liquidatable))
+            .collect();
+        assert_eq!(summary, vec![(110, 108, 0.9, true), (100, 99, 1.4, false)]);
+        assert!(history[0].snapshot_id > history[1].snapshot_id);
+        assert_eq!(database.health_history(&watched, 1).unwrap().len(), 1);
+        assert_eq!(database.health_history(&other, 10).unwrap().len(), 1);
+        assert_eq!(database.latest_snapshot().unwrap().map(|(slot, _)| slot), Some(110));
+    }
+
+    #[test]
+    fn warm_state_requires_a_recent_snapshot_and_keeps_only_obligations_with_borrows() {
+        let database = in_memory();
+        assert!(database.warm_state(Duration::from_secs(300)).unwrap().is_none());
+
+        let borrowing = Pubkey::new_unique();
+        database.record_obligation(borrowing, 10, &obligation(1, 5));
+        database.record_obligation(Pubkey::new_unique(), 10, &obligation(1, 0));
+        database.record_reserve(
+            Pubkey::new_unique(),
+            10,
+            &zeroed::<Reserve>(),
+        );
+        database.record_snapshot(42, vec![], vec![]);
+        database.flush();
+
+        let warm = database.warm_state(Duration::from_secs(300)).unwrap().unwrap();
+        assert_eq!(warm.slot, 42);
+        assert!(warm.age_secs <= 1);
+        assert_eq!(warm.obligations.len(), 1);
+        assert_eq!(warm.obligations[0].1, borrowing);
+        assert_eq!(warm.reserves.len(), 1);
+
+        // Age the snapshot past the cutoff.
+        open_connection(&database.path)
+            .unwrap()
+            .execute("UPDATE snapshots SET created_at = created_at - 301", [])
+            .unwrap();
+        assert!(database.warm_state(Duration::from_secs(300)).unwrap().is_none());
+        assert!(database.warm_state(Duration::from_secs(400)).unwrap().is_some());
+    }
+}
@@ -0,0 +1,332 @@
+use crate::deleverage::DeleverageStatus;
+use crate::health::{ObligationHealth, PositionValue};
+use crate::kamino::{ConditionType, Reserve};
+use crate::orders::OrderEvaluation;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
+pub struct PlannerConfig {
+    pub liquidation_close_factor: f64,
+    pub min_profit_usd: f64,
+}
+
+impl Default for PlannerConfig {
+    fn default() -> Self {
+        PlannerConfig {
+            liquidation_close_factor: 0.2,
+            min_profit_usd: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum OpportunityKind {
+    Liquidation,
+    OrderExecution {
+        order_index: usize,
+        condition: ConditionType,
+    },
+    AutoDeleverage {
+        target_ltv: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Opportunity {
+    pub obligation: Pubkey,
+    pub kind: OpportunityKind,
+    pub repay_reserve: Pubkey,
+    pub withdraw_reserve: Pubkey,
+    pub repay_amount: u64,
+    pub repay_value: f64,
+    pub bonus_rate: f64,
+    pub expected_profit: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpportunityInfo {
+    pub kind: OpportunityKind,
+    pub repay_reserve: String,
+    pub withdraw_reserve: String,
+    pub repay_amount: u64,
+    pub repay_value: f64,
+    pub bonus_rate: f64,
+    pub expected_profit: f64,
+}
+
+impl From<&Opportunity> for OpportunityInfo {
+    fn from(opportunity: &Opportunity) -> Self {
+        OpportunityInfo {
+            kind: opportunity.kind.clone(),
+            repay_reserve: opportunity.repay_reserve.to_string(),
+            withdraw_reserve: opportunity.withdraw_reserve.to_string(),
+            repay_amount: opportunity.repay_amount,
+            repay_value: opportunity.repay_value,
+            bonus_rate: opportunity.bonus_rate,
+            expected_profit: opportunity.expected_profit,
+        }
+    }
+}
+
+pub fn plan_obligation(
+    obligation_address: &Pubkey,
+    health: &ObligationHealth,
+    orders: &[OrderEvaluation],
+    deleverage: Option<&DeleverageStatus>,
+    reserves: &HashMap<Pubkey, Reserve>,
+    config: &PlannerConfig,
+) -> Vec<Opportunity> {
+    let mut opportunities = Vec::new();
+
+    if !health.prices_trusted {
+        return opportunities;
+    }
+
+    if health.is_liquidatable {
+        if let Some(opportunity) = plan_liquidation(obligation_address, health, reserves, config) {
+            opportunities.push(opportunity);
+        }
+    }
+
+    for order in orders.iter().filter(|order| order.triggered) {
+        let (repay, withdraw) = match (
+            find_position(&health.borrows, &order.repay_reserve),
+            find_position(&health.deposits, &order.withdraw_reserve),
+        ) {
+            (Some(repay), Some(withdraw)) => (repay, withdraw),
+            _ => continue,
+        };
+
+        let repay_value = order
+            .repay_value
+            .min(withdraw.value / (1.0 + order.execution_bonus_rate));
+        let repay_amount = if repay.price > 0.0 { repay_value / repay.price } else { 0.0 };
+        opportunities.push(Opportunity {
+            obligation: *obligation_address,
+            kind: OpportunityKind::OrderExecution {
+                order_index: order.order_index,
+                condition: order.condition,
+            },
+            repay_reserve: parse_pubkey(&repay.reserve_address),
+            withdraw_reserve: parse_pubkey(&withdraw.reserve_address),
+            repay_amount: to_lamports(repay_amount, repay.decimals),
+            repay_value,
+            bonus_rate: order.execution_bonus_rate,
+            expected_profit: repay_value * order.execution_bonus_rate,
+        });
+    }
+
+    if let Some(status) = deleverage.filter(|status| status.is_actionable()) {
+        if let Some(repay) = find_position(&health.borrows, &status.repay_reserve) {
+            opportunities.push(Opportunity {
+                obligation: *obligation_address,
+                kind: OpportunityKind::AutoDeleverage {
+                    target_ltv: status.target_ltv,
+                },
+                repay_reserve: parse_pubkey(&status.repay_reserve),
+                withdraw_reserve: parse_pubkey(&status.withdraw_reserve),
+                repay_amount: to_lamports(status.deleverageable_amount, repay.decimals),
+                repay_value: status.deleverageable_value,
+                bonus_rate: status.bonus_rate,
+                expected_profit: status.deleverageable_value * status.bonus_rate,
+            });
+        }
+    }
+
+    opportunities.retain(|opportunity| opportunity.expected_profit >= config.min_profit_usd);
+    opportunities.sort_by(|a, b| b.expected_profit.total_cmp(&a.expected_profit));
+    opportunities
+}
+
+fn plan_liquidation(
+    obligation_address: &Pubkey,
+    health: &ObligationHealth,
+    reserves: &HashMap<Pubkey, Reserve>,
+    config: &PlannerConfig,
+) -> Option<Opportunity> {
+    let repay = health.largest_borrow()?;
+    let withdraw = health.largest_deposit()?;
+    let withdraw_reserve = parse_pubkey(&withdraw.reserve_address);
+    let reserve = reserves.get(&withdraw_reserve)?;
+
+    let min_bonus = reserve.config.min_liquidation_bonus_bps as f64 / 10_000.0;
+    let max_bonus = reserve.config.max_liquidation_bonus_bps as f64 / 10_000.0;
+    let bad_debt_cap = if health.ltv > 0.0 { (1.0 / health.ltv - 1.0).max(0.0) } else { 0.0 };
+    let bonus_rate = (health.ltv - health.liquidation_ltv)
+        .clamp(min_bonus, max_bonus.max(min_bonus))
+        .min(bad_debt_cap);
+
+    let repay_amount = repay.amount * config.liquidation_close_factor;
+    let repay_value = (repay_amount * repay.price).min(withdraw.value / (1.0 + bonus_rate));
+
+    Some(Opportunity {
+        obligation: *obligation_address,
+        kind: OpportunityKind::Liquidation,
+        repay_reserve: parse_pubkey(&repay.reserve_address),
+        withdraw_reserve,
+        repay_amount: to_lamports(repay_value / repay.price.max(f64::MIN_POSITIVE), repay.decimals),
+        repay_value,
+        bonus_rate,
+        expected_profit: repay_value * bonus_rate,
+    })
+}
+
+fn find_position<'a>(positions: &'a [PositionValue], reserve: &str) -> Option<&'a PositionValue> {
+    positions.iter().find(|position| position.reserve_address == reserve)
+}
+
+fn parse_pubkey(value: &str) -> Pubkey {
+    Pubkey::from_str(value).unwrap_or_default()
+}
+
+fn to_lamports(amount: f64, decimals: u32) -> u64 {
+    (amount * 10f64.powi(decimals as i32)).floor() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kamino::OpportunityType;
+    use crate::test_fixtures::{position, zeroed};
+
+    struct Market {
+        reserves: HashMap<Pubkey, Reserve>,
+        sol: Pubkey,
+        eth: Pubkey,
+        usdc: Pubkey,
+    }
+
+    // Collateral reserves pay a 2% to 10% liquidation bonus.
+    fn market() -> Market {
+        let mut reserves = HashMap::new();
+        let (sol, eth, usdc) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        for address in [sol, eth, usdc] {
+            let mut reserve = zeroed::<Reserve>();
+            reserve.config.min_liquidation_bonus_bps = 200;
+            reserve.config.max_liquidation_bonus_bps = 1_000;
+            reserves.insert(address, reserve);
+        }
+        Market { reserves, sol, eth, usdc }
+    }
+
+    // Every deposit has an 80% liquidation threshold.
+    fn health(deposits: Vec<PositionValue>, borrows: Vec<PositionValue>) -> ObligationHealth {
+        let deposited_value: f64 = deposits.iter().map(|position| position.value).sum();
+        let borrowed_value: f64 = borrows.iter().map(|position| position.value).sum();
+        let ltv = borrowed_value / deposited_value;
+        ObligationHealth {
+            deposited_value,
+            borrowed_value,
+            bf_adjusted_debt_value: borrowed_value,
+            unhealthy_borrow_value: deposited_value * 0.8,
+            ltv,
+            liquidation_ltv: 0.8,
+            health_factor: 0.8 / ltv,
+            underwater: ltv >= 0.8,
+            prices_trusted: true,
+            is_liquidatable: ltv >= 0.8,
+            deposits,
+            borrows,
+            ..Default::default()
+        }
+    }
+
+    fn plan(health: &ObligationHealth, market: &Market, config: &PlannerConfig) -> Vec<Opportunity> {
+        plan_obligation(&Pubkey::new_unique(), health, &[], None, &market.reserves, config)
+    }
+
+    #[test]
+    fn sizes_a_liquidation_by_close_factor_and_ltv_bonus() {
+        let market = market();
+        // 12 SOL at $100 against 1050 USDC: LTV 0.875, 0.075 past the liquidation LTV.
+        let health = health(vec![position(market.sol, 12.0, 9, 100.0)], vec![position(market.usdc, 1_050.0, 6, 1.0)]);
+
+        let opportunities = plan(&health, &market, &PlannerConfig::default());
+        assert_eq!(opportunities.len(), 1);
+        let liquidation = &opportunities[0];
+        assert_eq!(liquidation.kind, OpportunityKind::Liquidation);
+        assert_eq!((liquidation.repay_reserve, liquidation.withdraw_reserve), (market.usdc, market.sol));
+        assert!((liquidation.bonus_rate - 0.075).abs() < 1e-9);
+        assert!((liquidation.repay_value - 210.0).abs() < 1e-9);
+        assert_eq!(liquidation.repay_amount, 210_000_000);
+        assert!((liquidation.expected_profit - 15.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn clamps_the_bonus_and_caps_repayment_at_the_largest_collateral() {
+        let market = market();
+        // LTV 0.81 is only 0.01 past the liquidation LTV, below the 2% minimum bonus.
+        let barely = health(vec![position(market.sol, 10.0, 9, 100.0)], vec![position(market.usdc, 810.0, 6, 1.0)]);
+        assert!((plan(&barely, &market, &PlannerConfig::default())[0].bonus_rate - 0.02).abs() < 1e-9);
+
+        // 1000 USDC against $600 of SOL and $500 of ETH: LTV 0.909 pays the 10% maximum, and
+        // repaying everything is limited to what the SOL can pay out, 600 / 1.1.
+        let spread = health(
+            vec![position(market.sol, 6.0, 9, 100.0), position(market.eth, 0.2, 8, 2_500.0)],
+            vec![position(market.usdc, 1_000.0, 6, 1.0)],
+        );
+        let config = PlannerConfig { liquidation_close_factor: 1.0, ..PlannerConfig::default() };
+        let liquidation = &plan(&spread, &market, &config)[0];
+        assert_eq!(liquidation.withdraw_reserve, market.sol);
+        assert!((liquidation.bonus_rate - 0.1).abs() < 1e-9);
+        assert!((liquidation.repay_value - 600.0 / 1.1).abs() < 1e-9);
+        assert_eq!(liquidation.repay_amount, 545_454_545);
+    }
+
+    #[test]
+    fn skips_bad_debt_small_profits_and_untrusted_prices() {
+        let market = market();
+        // Underwater past 1.0 LTV, no bonus is left to pay.
+        let bad_debt = health(vec![position(market.sol, 10.0, 9, 100.0)], vec![position(market.usdc, 1_050.0, 6, 1.0)]);
+        assert!(plan(&bad_debt, &market, &PlannerConfig::default()).is_empty());
+
+        // A fifth of 10 USDC at LTV 0.833 earns 2 * 0.0333 = $0.067, under the $1 minimum.
+        let small = health(vec![position(market.sol, 0.12, 9, 100.0)], vec![position(market.usdc, 10.0, 6, 1.0)]);
+        assert!(plan(&small, &market, &PlannerConfig::default()).is_empty());
+        let config = PlannerConfig { min_profit_usd: 0.05, ..PlannerConfig::default() };
+        assert_eq!(plan(&small, &market, &config).len(), 1);
+
+        let mut untrusted = health(vec![position(market.sol, 12.0, 9, 100.0)], vec![position(market.usdc, 1_050.0, 6, 1.0)]);
+        untrusted.prices_trusted = false;
+        assert!(plan(&untrusted, &market, &PlannerConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn sizes_triggered_orders_within_the_collateral() {
+        let market = market();
+        // LTV 0.77, not liquidatable.
+        let health = health(vec![position(market.sol, 1.0, 9, 130.0)], vec![position(market.usdc, 100.0, 6, 1.0)]);
+        let order = |order_index: usize, repay_value: f64, execution_bonus_rate: f64, triggered: bool| OrderEvaluation {
+            order_index,
+            condition: ConditionType::UserLtvAbove,
+            opportunity: OpportunityType::DeleverageAllDebt,
+            threshold: 0.6,
+            current_value: health.ltv,
+            supported: true,
+            triggered,
+            execution_bonus_rate,
+            repay_reserve: market.usdc.to_string(),
+            withdraw_reserve: market.sol.to_string(),
+            repay_amount: repay_value,
+            repay_value,
+            expected_bonus_value: repay_value * execution_bonus_rate,
+        };
+        // The first order repays 50 USDC at 3%, the second all 100 USDC at 40%, capped at what
+        // 1 SOL covers: 130 / 1.4 = 92.86. The third is not triggered.
+        let orders = [order(0, 50.0, 0.03, true), order(1, 100.0, 0.4, true), order(2, 100.0, 0.05, false)];
+
+        let opportunities = plan_obligation(&Pubkey::new_unique(), &health, &orders, None, &market.reserves, &PlannerConfig::default());
+        let sized: Vec<(f64, u64, f64)> = opportunities
+            .iter()
+            .map(|opportunity| (opportunity.repay_value, opportunity.repay_amount, opportunity.expected_profit))
+            .collect();
+        assert_eq!(sized.len(), 2);
+        assert!((sized[0].0 - 130.0 / 1.4).abs() < 1e-9 && (sized[0].2 - 130.0 / 1.4 * 0.4).abs() < 1e-9);
+        assert_eq!(sized[0].1, 92_857_142);
+        assert!((sized[1].0 - 50.0).abs() < 1e-9 && (sized[1].2 - 1.5).abs() < 1e-9);
+        assert_eq!(sized[1].1, 50_000_000);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::blockhash_listener;
+use crate::kamino::Reserve;
+use crate::price_listener::{get_current_price_info, get_twap, TokenPrice};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct PriceGuardConfig {
+    pub max_age_secs: i64,
+    pub max_slot_lag: u64,
+    pub max_confidence_ratio: f64,
+    pub max_deviation: f64,
+    // Oldest reserve market price used as the deviation reference for a live price.
+    pub max_reference_age_secs: u64,
+    pub twap_window: Duration,
+}
+
+impl Default for PriceGuardConfig {
+    fn default() -> Self {
+        PriceGuardConfig {
+            max_age_secs: 30,
+            max_slot_lag: 75,
+            max_confidence_ratio: 0.02,
+            max_deviation: 0.05,
+            max_reference_age_secs: 30,
+            twap_window: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceVerdict {
+    Ok,
+    Missing,
+    NotTrading,
+    Stale,
+    Uncertain,
+    Deviating,
+}
+
+impl PriceVerdict {
+    pub fn is_ok(&self) -> bool {
+        *self == PriceVerdict::Ok
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PriceQuote {
+    pub price: f64,
+    pub live: bool,
+    pub verdict: PriceVerdict,
+}
+
+pub fn check_price(
+    price: &TokenPrice,
+    reference: Option<f64>,
+    config: &PriceGuardConfig,
+    now: DateTime<Utc>,
+    current_slot: Option<u64>,
+) -> PriceVerdict {
+    if price.price <= 0.0 {
+        return if price.status.starts_with("Non-Trading") {
+            PriceVerdict::NotTrading
+        } else {
+            PriceVerdict::Missing
+        };
+    }
+
+    let published_at = price.publish_time.unwrap_or(price.last_updated);
+    if (now - published_at).num_seconds() > config.max_age_secs {
+        return PriceVerdict::Stale;
+    }
+
+    if let Some(slot) = current_slot {
+        if price.publish_slot > 0 && slot.saturating_sub(price.publish_slot) > config.max_slot_lag {
+            return PriceVerdict::Stale;
+        }
+    }
+
+    if price.confidence / price.price > config.max_confidence_ratio {
+        return PriceVerdict::Uncertain;
+    }
+
+    if let Some(reference) = reference.filter(|reference| *reference > 0.0) {
+        if (price.price - reference).abs() / reference > config.max_deviation {
+            return PriceVerdict::Deviating;
+        }
+    }
+
+    PriceVerdict::Ok
+}
+
+// Prefers the live oracle price and cross-checks it against the reserve's own market price
+// while that is at most max_reference_age_secs old (else the oracle EMA, else our TWAP), then
+// against our TWAP using the reserve's max_twap_divergence_bps, the same bound klend applies
+// on refresh. Without a live price the reserve price is used as long as the reserve refreshed
+// it within the live staleness bound.
+pub fn quote_price(reserve: &Reserve, config: &PriceGuardConfig) -> PriceQuote {
+    let live = get_current_price_info(&reserve.mint().to_string());
+    let twap = live
+        .as_ref()
+        .and_then(|live| get_twap(&live.mint, config.twap_window));
+    quote_price_at(reserve, live.as_ref(), twap, config, Utc::now(), blockhash_listener::current_slot())
+}
+
+// Same as quote_price with the live price, TWAP and clock supplied by the caller, so replays
+// can quote against recorded state.
+pub fn quote_price_at(
+    reserve: &Reserve,
+    live: Option<&TokenPrice>,
+    twap: Option<f64>,
+    config: &PriceGuardConfig,
+    now: DateTime<Utc>,
+    current_slot: Option<u64>,
+) -> PriceQuote {
+    let reserve_price = reserve.market_price();
+    let reserve_age = (now.timestamp().max(0) as u64)
+        .saturating_sub(reserve.liquidity.market_price_last_updated_ts);
+
+    if let Some(live) = live {
+        let reference = if reserve_age <= config.max_reference_age_secs {
+            Some(reserve_price)
+        } else {
+            live.oracle_ema_price.or(twap)
+        };
+        let mut verdict = check_price(live, reference, config, now, current_slot);
+        if verdict.is_ok() && exceeds_twap_divergence(reserve, live, twap) {
+            verdict = PriceVerdict::Deviating;
+        }
+        if live.price > 0.0 {
+            return PriceQuote {
+                price: live.price,
+                live: true,
+                verdict,
+            };
+        }
+        return PriceQuote {
+            price: reserve_price,
+            live: false,
+            verdict,
+        };
+    }
+
+    // Held to the same bound as a live price, or the reserve's own max age when that is tighter.
+    let live_max_age = config.max_age_secs.max(0) as u64;
+    let max_age = match reserve.config.token_info.max_age_price_seconds {
+        0 => live_max_age,
+        reserve_max_age => live_max_age.min(reserve_max_age),
+    };
+    let verdict = if reserve_price <= 0.0 {
+        PriceVerdict::Missing
+    } else if reserve_age > max_age {
+        PriceVerdict::Stale
+    } else {
+        PriceVerdict::Ok
+    };
+
+    PriceQuote {
+        price: reserve_price,
+        live: false,
+        verdict,
+    }
+}
+
+fn exceeds_twap_divergence(reserve: &Reserve, live: &TokenPrice, twap: Option<f64>) -> bool {
+    let max_divergence = reserve.config.token_info.max_twap_divergence_bps as f64 / 10_000.0;
+    if max_divergence <= 0.0 {
+        return false;
+    }
+
+    match twap {
+        Some(twap) if twap > 0.0 => (live.price - twap).abs() / twap > max_divergence,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kamino::f64_to_sf;
+    use crate::test_fixtures::zeroed;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn reserve(price: f64, updated_secs_ago: u64) -> Reserve {
+        let mut reserve = zeroed::<Reserve>();
+        reserve.liquidity.market_price_sf = f64_to_sf(price);
+        reserve.liquidity.market_price_last_updated_ts = NOW as u64 - updated_secs_ago;
+        reserve
+    }
+
+    fn live(price: f64, oracle_ema: Option<f64>) -> TokenPrice {
+        let now = DateTime::from_timestamp(NOW, 0).unwrap();
+        TokenPrice {
+            mint: "mint".to_string(),
+            symbol: "TEST".to_string(),
+            price,
+            confidence: 0.0,
+            last_updated: now,
+            publish_time: Some(now),
+            publish_slot: 0,
+            status: "Trading".to_string(),
+            ema_price: price,
+            oracle_ema_price: oracle_ema,
+            oracle_ema_confidence: None,
+        }
+    }
+
+    fn verdict(reserve: &Reserve, live: &TokenPrice, twap: Option<f64>) -> PriceVerdict {
+        let now = DateTime::from_timestamp(NOW, 0).unwrap();
+        quote_price_at(reserve, Some(live), twap, &PriceGuardConfig::default(), now, None).verdict
+    }
+
+    #[test]
+    fn accepts_deviation_up_to_the_bound_from_a_fresh_reserve_price() {
+        // 5% above a reserve price refreshed 30s ago is still accepted, 6% is not.
+        assert_eq!(verdict(&reserve(100.0, 30), &live(105.0, None), None), PriceVerdict::Ok);
+        assert_eq!(verdict(&reserve(100.0, 30), &live(106.0, None), None), PriceVerdict::Deviating);
+        assert_eq!(verdict(&reserve(100.0, 0), &live(94.0, None), None), PriceVerdict::Deviating);
+    }
+
+    #[test]
+    fn an_older_reserve_price_gives_way_to_the_oracle_ema_then_the_twap() {
+        // At 31s the reserve price no longer counts: 120 is judged against the EMA or TWAP.
+        let stale = reserve(100.0, 31);
+        assert_eq!(verdict(&stale, &live(120.0, Some(118.0)), None), PriceVerdict::Ok);
+        assert_eq!(verdict(&stale, &live(120.0, Some(100.0)), None), PriceVerdict::Deviating);
+        assert_eq!(verdict(&stale, &live(120.0, None), Some(119.0)), PriceVerdict::Ok);
+        assert_eq!(verdict(&stale, &live(120.0, None), Some(110.0)), PriceVerdict::Deviating);
+        assert_eq!(verdict(&stale, &live(120.0, None), None), PriceVerdict::Ok);
+    }
+
+    #[test]
+    fn quotes_the_reserve_price_without_a_live_price_until_it_is_stale() {
+        let now = DateTime::from_timestamp(NOW, 0).unwrap();
+        let config = PriceGuardConfig::default();
+        // The same 30s bound as a live price.
+        let quote = quote_price_at(&reserve(100.0, 30), None, None, &config, now, None);
+        assert_eq!((quote.price, quote.live, quote.verdict), (100.0, false, PriceVerdict::Ok));
+        let quote = quote_price_at(&reserve(100.0, 31), None, None, &config, now, None);
+        assert_eq!(quote.verdict, PriceVerdict::Stale);
+
+        // A tighter max age on the reserve applies at its own boundary...
+        let mut limited = reserve(100.0, 10);
+        limited.config.token_info.max_age_price_seconds = 10;
+        assert_eq!(quote_price_at(&limited, None, None, &config, now, None).verdict, PriceVerdict::Ok);
+        limited.liquidity.market_price_last_updated_ts -= 1;
+        assert_eq!(quote_price_at(&limited, None, None, &config, now, None).verdict, PriceVerdict::Stale);
+
+        // ...but a looser one does not extend the bound.
+        let mut loose = reserve(100.0, 31);
+        loose.config.token_info.max_age_price_seconds = 600;
+        assert_eq!(quote_price_at(&loose, None, None, &config, now, None).verdict, PriceVerdict::Stale);
+    }
+}
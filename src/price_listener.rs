@@ -1,15 +1,20 @@
+use anyhow::{bail, Result};
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
-use futures::stream::StreamExt;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use crate::events::{Event, EventSender};
+use crate::obligation_store::ObligationStore;
+use crate::geyser_sources::run_streams;
+use crate::pubsub_stream::{run_pubsub_stream, websocket_url_from_env};
+use crate::geyser_stream::{GeyserEndpoint, StreamConfig, SubscriptionHandle};
+use crate::recorder::replay_into;
 use tracing::{error, info, warn};
-use yellowstone_grpc_proto::geyser::geyser_client::GeyserClient;
 use yellowstone_grpc_proto::geyser::{
     subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
     SubscribeRequestFilterAccounts, SubscribeUpdateAccount,
@@ -22,12 +27,31 @@ pub struct TokenPrice {
     pub price: f64,
     pub confidence: f64,
     pub last_updated: DateTime<Utc>,
+    pub publish_time: Option<DateTime<Utc>>,
+    pub publish_slot: u64,
     pub status: String,
+    pub ema_price: f64,
+    pub oracle_ema_price: Option<f64>,
+    pub oracle_ema_confidence: Option<f64>,
 }
 
+pub const EMA_PERIOD_SECS: f64 = 60.0;
+pub const PRICE_HISTORY_CAPACITY: usize = 10_000;
+
 pub static PRICE_STATE: Lazy<Arc<DashMap<String, TokenPrice>>> =
     Lazy::new(|| Arc::new(DashMap::new()));
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PriceSample {
+    pub slot: u64,
+    pub publish_time: DateTime<Utc>,
+    pub price: f64,
+    pub confidence: f64,
+}
+
+static PRICE_HISTORY: Lazy<DashMap<String, VecDeque<PriceSample>>> =
+    Lazy::new(DashMap::new);
+
 pub fn get_price_state() -> Arc<DashMap<String, TokenPrice>> {
     Arc::clone(&PRICE_STATE)
 }
@@ -36,12 +60,34 @@ pub trait Listener: Send + Sync + 'static {
     fn get_subscription_request(&self) -> SubscribeRequest;
     fn handle_update(&self, update: UpdateOneof);
     fn name(&self) -> String;
+
+    // Listeners that change their filters at runtime return the handle they update; by
+    // default the subscription is fixed to get_subscription_request.
+    fn subscription(&self) -> SubscriptionHandle {
+        SubscriptionHandle::new(self.get_subscription_request())
+    }
 }
 
+pub const PRICE_ACCOUNTS_FILTER: &str = "real_pyth_prices";
+pub const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+pub const PYTH_VERSION: u32 = 2;
+pub const PYTH_PRICE_ACCOUNT_MIN_SIZE: usize = 240;
+
+const VERIFIED_PRICE_ACCOUNTS: [(&str, &str); 8] = [
+    ("So11111111111111111111111111111111111111112", "H6ARHf6YXtGYeQkjqmvb4v1RcCc7o2Fa6DksJYRHdKjEe"),
+    ("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "Gnt27xtC473ZT2Mw5u8wZ68Z3gULkSTb5DuxJy7eJotD"),
+    ("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", "3vxLXJqLqF3JG5TCbYycbKWRBbCJQLxQmBGCkyqEEefL"),
+    ("7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs", "JBu1AL4obBcCMqKBBxhpWCNUt136ijcuMZLFvTP7iWdB"),
+    ("9n4nbM75f5Ui33ZbPYXn59EwSgE8CGsHtAeTH5YFeJ9E", "GVXRSBjFk6e6J3NbVPXohDJetcTjaeeuykUpbQF8UoMU"),
+    ("mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So", "E4v1BBgoso9s64TQvmyownAVJbhbEPGyzA3qn4n46qj9"),
+    ("J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn", "7yyaeuJ1GGtVBLT2z2xub5ZWYKaNhF28mj1RdV4VDFVk"),
+    ("bSo13r4TkiE4KumL71LsHTPpL2euBYLFx6h9HP3piy1", "AFrYBhb5wKQtxRS9UA9YRS4V3dwFm7SqmS6DHKq6YVgo"),
+];
+
 pub struct PriceListener {
-    pub token_mints: Vec<String>,
-    pub price_accounts: Vec<Pubkey>,
-    pub account_to_mint: HashMap<Pubkey, String>,
+    pub account_to_mint: DashMap<Pubkey, String>,
+    subscription: SubscriptionHandle,
+    events: Option<(Arc<ObligationStore>, EventSender)>,
 }
 
 impl PriceListener {
@@ -87,88 +133,101 @@ impl PriceListener {
         });
 
         PriceListener {
-            token_mints,
-            price_accounts,
-            account_to_mint,
+            subscription: SubscriptionHandle::new(price_subscription_request(&price_accounts)),
+            account_to_mint: account_to_mint.into_iter().collect(),
+            events: None,
+        }
+    }
+
+    // Adds a price account to the live subscription without reconnecting.
+    pub fn add_price_account(&self, mint: &str, price_account: Pubkey) -> bool {
+        self.account_to_mint.insert(price_account, mint.to_string());
+        self.subscription.add_accounts(PRICE_ACCOUNTS_FILTER, &[price_account])
+    }
+
+    pub fn remove_price_account(&self, price_account: &Pubkey) -> bool {
+        self.account_to_mint.remove(price_account);
+        self.subscription.remove_accounts(PRICE_ACCOUNTS_FILTER, &[*price_account])
+    }
+
+    // Subscribes to the verified price account of a mint first seen after startup, e.g. the
+    // mint of a newly listed reserve.
+    pub fn track_mint(&self, mint: &str) -> bool {
+        if self.account_to_mint.iter().any(|entry| entry.value() == mint) {
+            return false;
+        }
+        match verified_price_account(mint) {
+            Some(price_account) => {
+                info!("Subscribing to Pyth price account {} for new mint {}", price_account, get_token_symbol(mint));
+                self.add_price_account(mint, price_account)
+            }
+            None => false,
+        }
+    }
+
+    // Every accepted price update looks up the obligations holding the mint and publishes
+    // them on the event bus for incremental recomputation.
+    pub fn with_events(mut self, store: Arc<ObligationStore>, events: EventSender) -> Self {
+        self.events = Some((store, events));
+        self
+    }
+
+    fn notify_affected(&self, mint: &str) {
+        if let Some((store, events)) = &self.events {
+            let obligations = store.obligations_for_mint(mint);
+            if !obligations.is_empty() {
+                let _ = events.send(Event::Price {
+                    obligations,
+                    received_at: Instant::now(),
+                });
+            }
         }
     }
 
     pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
-            start_price_listener(self).await;
+            run_listener(self).await;
         })
     }
 }
 
-async fn start_price_listener(listener: Arc<PriceListener>) {
-    let grpc_url = "https://solana-yellowstone-grpc.publicnode.com:443";
-    let retry_delay = tokio::time::Duration::from_secs(2);
-    let max_retries = 5;
-    let mut attempt = 0;
-
-    loop {
-        attempt += 1;
-        info!(
-            "{} connecting to: {} (attempt {})",
-            listener.name(),
-            grpc_url,
-            attempt
-        );
-
-        match GeyserClient::connect(grpc_url.to_string()).await {
-            Ok(mut grpc_client) => {
-                info!("{} connected successfully to gRPC server", listener.name());
-
-                let request = listener.get_subscription_request();
-                
-                match grpc_client.subscribe(tokio_stream::once(request)).await {
-                    Ok(response) => {
-                        info!(
-                            "{} subscribed successfully, starting to listen for updates",
-                            listener.name()
-                        );
-                        let mut response_stream = response.into_inner();
-
-                        while let Some(update_result) = response_stream.next().await {
-                            match update_result {
-                                Ok(update) => {
-                                    if let Some(update_oneof) = update.update_oneof {
-                                        listener.handle_update(update_oneof);
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("{} stream error: {:?}", listener.name(), e);
-                                    break;
-                                }
-                            }
-                        }
-                        error!("{} stream ended unexpectedly, retrying...", listener.name());
-                    }
-                    Err(e) => {
-                        error!("{} failed to subscribe: {:?}", listener.name(), e);
-                    }
-                }
-            }
-            Err(e) => {
-                error!("{} failed to connect to gRPC server: {:?}", listener.name(), e);
-            }
+pub async fn run_listener<L: Listener>(listener: Arc<L>) {
+    if let Ok(replay_dir) = std::env::var("REPLAY_DIR") {
+        let speed = std::env::var("REPLAY_SPEED").ok().and_then(|speed| speed.parse().ok());
+        if let Err(e) = replay_into(Arc::clone(&listener), &replay_dir, speed).await {
+            error!("{} failed to replay {}: {:?}", listener.name(), replay_dir, e);
         }
+        return;
+    }
 
-        if attempt >= max_retries {
-            error!(
-                "{} reached max retry attempts ({}) and will stop retrying",
-                listener.name(),
-                max_retries
-            );
-            break;
-        }
+    match ListenerTransport::from_env() {
+        Ok(ListenerTransport::Geyser) => match GeyserEndpoint::all_from_env() {
+            Ok(endpoints) => run_streams(listener, endpoints, StreamConfig::from_env()).await,
+            Err(e) => error!("{} has an invalid Geyser configuration: {:?}", listener.name(), e),
+        },
+        Ok(ListenerTransport::WebSocket) => match websocket_url_from_env() {
+            Ok(url) => run_pubsub_stream(listener, url, StreamConfig::from_env()).await,
+            Err(e) => error!("{} has an invalid websocket configuration: {:?}", listener.name(), e),
+        },
+        Err(e) => error!("{} has an invalid listener transport: {:?}", listener.name(), e),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerTransport {
+    Geyser,
+    // Solana PubSub websocket, for when no Geyser endpoint is available.
+    WebSocket,
+}
 
-        warn!(
-            "{} retrying in {} seconds...",
-            listener.name(),
-            retry_delay.as_secs()
-        );
-        tokio::time::sleep(retry_delay).await;
+impl ListenerTransport {
+    // LISTENER_TRANSPORT: `geyser` (default) or `websocket`.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("LISTENER_TRANSPORT").unwrap_or_default().to_ascii_lowercase().as_str() {
+            "" | "geyser" | "grpc" => Ok(ListenerTransport::Geyser),
+            "websocket" | "ws" | "pubsub" => Ok(ListenerTransport::WebSocket),
+            other => bail!("unknown listener transport {}", other),
+        }
     }
 }
 
@@ -187,12 +246,15 @@ impl Listener for PriceListener {
                 Pubkey::new_from_array(account_info.pubkey.try_into().unwrap_unchecked())
             };
 
-            if let Some(mint) = self.account_to_mint.get(&account_pubkey) {
+            if let Some(mint) = self.account_to_mint.get(&account_pubkey).map(|entry| entry.value().clone()) {
+                let mint = &mint;
                 let symbol = get_token_symbol(mint);
                 
-                if let Some(price_info) = parse_real_pyth_price_account(&account_info.data, mint) {
+                if let Some(mut price_info) = parse_real_pyth_price_account(&account_info.data, mint) {
                     let old_price = PRICE_STATE.get(mint).map(|entry| entry.price);
+                    update_averages(mint, &mut price_info);
                     PRICE_STATE.insert(mint.clone(), price_info.clone());
+                    self.notify_affected(mint);
                     
                     match old_price {
                         Some(old) if (old - price_info.price).abs() > 0.001 => {
@@ -214,64 +276,76 @@ impl Listener for PriceListener {
                         info!("   Account size: {} bytes, first 32 bytes: {:02x?}", 
                               account_info.data.len(), &account_info.data[..32.min(account_info.data.len())]);
                     }
+                    // A zero or out-of-range price is a bad tick on a real feed and the next one
+                    // may be fine; only an account that is not a Pyth price account is dropped.
+                    if !is_pyth_price_account(&account_info.data) {
+                        warn!("   Unsubscribing from {} price account {}", symbol, account_pubkey);
+                        self.remove_price_account(&account_pubkey);
+                    }
                 }
             }
         }
     }
 
     fn get_subscription_request(&self) -> SubscribeRequest {
-        if self.price_accounts.is_empty() {
-            warn!("No REAL Pyth price accounts to subscribe to!");
-            return SubscribeRequest::default();
-        }
-
-        info!("Creating Yellowstone gRPC subscription for {} REAL Pyth price accounts", self.price_accounts.len());
+        self.subscription.current()
+    }
 
-        let account_filter = SubscribeRequestFilterAccounts {
-            account: self.price_accounts.iter().map(|k| k.to_string()).collect(),
-            owner: vec![],
-            filters: vec![],
-            ..Default::default()
-        };
+    fn subscription(&self) -> SubscriptionHandle {
+        self.subscription.clone()
+    }
 
-        let mut accounts_map = HashMap::new();
-        accounts_map.insert("real_pyth_prices".to_string(), account_filter);
+    fn name(&self) -> String {
+        "RealPythPriceListener".to_string()
+    }
+}
 
-        SubscribeRequest {
-            accounts: accounts_map,
+fn price_subscription_request(price_accounts: &[Pubkey]) -> SubscribeRequest {
+    if price_accounts.is_empty() {
+        warn!("No REAL Pyth price accounts to subscribe to!");
+        return SubscribeRequest {
             commitment: Some(CommitmentLevel::Processed as i32),
-            slots: HashMap::new(),
-            transactions: HashMap::new(),
-            transactions_status: HashMap::new(),
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            accounts_data_slice: vec![],
-            ping: None,
-        }
+            ..Default::default()
+        };
     }
 
-    fn name(&self) -> String {
-        "RealPythPriceListener".to_string()
+    info!("Creating Yellowstone gRPC subscription for {} REAL Pyth price accounts", price_accounts.len());
+
+    let account_filter = SubscribeRequestFilterAccounts {
+        account: price_accounts.iter().map(|k| k.to_string()).collect(),
+        owner: vec![],
+        filters: vec![],
+    };
+
+    let mut accounts_map = HashMap::new();
+    accounts_map.insert(PRICE_ACCOUNTS_FILTER.to_string(), account_filter);
+
+    SubscribeRequest {
+        accounts: accounts_map,
+        commitment: Some(CommitmentLevel::Processed as i32),
+        slots: HashMap::new(),
+        transactions: HashMap::new(),
+        transactions_status: HashMap::new(),
+        blocks: HashMap::new(),
+        blocks_meta: HashMap::new(),
+        entry: HashMap::new(),
+        accounts_data_slice: vec![],
+        ping: None,
     }
 }
 
+fn verified_price_account(mint: &str) -> Option<Pubkey> {
+    VERIFIED_PRICE_ACCOUNTS
+        .iter()
+        .find(|(verified_mint, _)| *verified_mint == mint)
+        .and_then(|(_, price_account)| Pubkey::from_str(price_account).ok())
+}
+
 fn get_real_working_pyth_accounts(token_mints: &[String]) -> (Vec<Pubkey>, HashMap<Pubkey, String>) {
     let mut price_accounts = Vec::new();
     let mut account_to_mint = HashMap::new();
     
-    let verified_accounts = [
-        ("So11111111111111111111111111111111111111112", "H6ARHf6YXtGYeQkjqmvb4v1RcCc7o2Fa6DksJYRHdKjEe"),
-        ("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "Gnt27xtC473ZT2Mw5u8wZ68Z3gULkSTb5DuxJy7eJotD"),
-        ("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", "3vxLXJqLqF3JG5TCbYycbKWRBbCJQLxQmBGCkyqEEefL"),
-        ("7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs", "JBu1AL4obBcCMqKBBxhpWCNUt136ijcuMZLFvTP7iWdB"),
-        ("9n4nbM75f5Ui33ZbPYXn59EwSgE8CGsHtAeTH5YFeJ9E", "GVXRSBjFk6e6J3NbVPXohDJetcTjaeeuykUpbQF8UoMU"),
-        ("mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So", "E4v1BBgoso9s64TQvmyownAVJbhbEPGyzA3qn4n46qj9"),
-        ("J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn", "7yyaeuJ1GGtVBLT2z2xub5ZWYKaNhF28mj1RdV4VDFVk"),
-        ("bSo13r4TkiE4KumL71LsHTPpL2euBYLFx6h9HP3piy1", "AFrYBhb5wKQtxRS9UA9YRS4V3dwFm7SqmS6DHKq6YVgo"),
-    ];
-
-    for (mint, price_account_str) in verified_accounts {
+    for (mint, price_account_str) in VERIFIED_PRICE_ACCOUNTS {
         if token_mints.contains(&mint.to_string()) {
             if let Ok(price_account_pubkey) = Pubkey::from_str(price_account_str) {
                 price_accounts.push(price_account_pubkey);
@@ -291,25 +365,63 @@ fn get_real_working_pyth_accounts(token_mints: &[String]) -> (Vec<Pubkey>, HashM
     (price_accounts, account_to_mint)
 }
 
-fn parse_real_pyth_price_account(data: &[u8], mint: &str) -> Option<TokenPrice> {
-    if data.len() < 240 {
-        info!("   Account too small: {} bytes (need 240+)", data.len());
+// Size, magic and version of a Pyth v2 price account, independent of the price it holds.
+pub fn is_pyth_price_account(data: &[u8]) -> bool {
+    data.len() >= PYTH_PRICE_ACCOUNT_MIN_SIZE
+        && data[0..4] == PYTH_MAGIC.to_le_bytes()
+        && data[4..8] == PYTH_VERSION.to_le_bytes()
+}
+
+pub fn parse_real_pyth_price_account(data: &[u8], mint: &str) -> Option<TokenPrice> {
+    if !is_pyth_price_account(data) {
+        info!("   Not a Pyth v2 price account: {} bytes, header {:02x?}", data.len(), &data[..8.min(data.len())]);
         return None;
     }
 
+    let expo_offset = 20;
+    let ema_price_offset = 48;
+    let ema_conf_offset = 72;
+    let timestamp_offset = 96;
     let price_offset = 208;
-    let expo_offset = 216;
-    let conf_offset = 224;
-    let status_offset = 232;
+    let conf_offset = 216;
+    let status_offset = 224;
+    let pub_slot_offset = 232;
 
-    if data.len() < status_offset + 4 {
-        return None;
-    }
+    let expo = i32::from_le_bytes(
+        data[expo_offset..expo_offset + 4].try_into().ok()?
+    );
+
+    let timestamp = i64::from_le_bytes(
+        data[timestamp_offset..timestamp_offset + 8].try_into().ok()?
+    );
 
     let status = u32::from_le_bytes(
         data[status_offset..status_offset + 4].try_into().ok()?
     );
 
+    let publish_slot = u64::from_le_bytes(
+        data[pub_slot_offset..pub_slot_offset + 8].try_into().ok()?
+    );
+
+    let publish_time = DateTime::from_timestamp(timestamp, 0);
+
+    let ema_price_raw = i64::from_le_bytes(
+        data[ema_price_offset..ema_price_offset + 8].try_into().ok()?
+    );
+
+    let ema_conf_raw = u64::from_le_bytes(
+        data[ema_conf_offset..ema_conf_offset + 8].try_into().ok()?
+    );
+
+    let (oracle_ema_price, oracle_ema_confidence) = if ema_price_raw > 0 {
+        (
+            Some((ema_price_raw as f64) * 10f64.powi(expo)),
+            Some((ema_conf_raw as f64) * 10f64.powi(expo)),
+        )
+    } else {
+        (None, None)
+    };
+
     if status != 1 {
         info!("   {} price status not trading: {}", get_token_symbol(mint), status);
         return Some(TokenPrice {
@@ -318,7 +430,12 @@ fn parse_real_pyth_price_account(data: &[u8], mint: &str) -> Option<TokenPrice>
             price: 0.0,
             confidence: 0.0,
             last_updated: Utc::now(),
+            publish_time,
+            publish_slot,
             status: format!("Non-Trading (status: {})", status),
+            ema_price: 0.0,
+            oracle_ema_price,
+            oracle_ema_confidence,
         });
     }
 
@@ -326,10 +443,6 @@ fn parse_real_pyth_price_account(data: &[u8], mint: &str) -> Option<TokenPrice>
         data[price_offset..price_offset + 8].try_into().ok()?
     );
 
-    let expo = i32::from_le_bytes(
-        data[expo_offset..expo_offset + 4].try_into().ok()?
-    );
-
     let conf_raw = u64::from_le_bytes(
         data[conf_offset..conf_offset + 8].try_into().ok()?
     );
@@ -342,8 +455,8 @@ fn parse_real_pyth_price_account(data: &[u8], mint: &str) -> Option<TokenPrice>
     let price = (price_raw as f64) * 10f64.powi(expo);
     let confidence = (conf_raw as f64) * 10f64.powi(expo);
 
-    info!("   {} raw_price={}, expo={}, calculated_price={:.6}", 
-          get_token_symbol(mint), price_raw, expo, price);
+    info!("   {} raw_price={}, expo={}, calculated_price={:.6}, pub_slot={}", 
+          get_token_symbol(mint), price_raw, expo, price, publish_slot);
 
     if price > 0.0 && price < 10_000_000.0 {
         Some(TokenPrice {
@@ -352,7 +465,12 @@ fn parse_real_pyth_price_account(data: &[u8], mint: &str) -> Option<TokenPrice>
             price,
             confidence,
             last_updated: Utc::now(),
+            publish_time,
+            publish_slot,
             status: "REAL Pyth Live".to_string(),
+            ema_price: price,
+            oracle_ema_price,
+            oracle_ema_confidence,
         })
     } else {
         warn!("   {} price sanity check failed: ${:.6}", get_token_symbol(mint), price);
@@ -360,6 +478,99 @@ fn parse_real_pyth_price_account(data: &[u8], mint: &str) -> Option<TokenPrice>
     }
 }
 
+// Folds a fresh price into the per-mint EMA and the bounded price history. Non-trading
+// updates carry the previous EMA forward and are left out of the history.
+fn update_averages(mint: &str, price_info: &mut TokenPrice) {
+    let previous = PRICE_STATE
+        .get(mint)
+        .map(|entry| (entry.ema_price, entry.publish_time.unwrap_or(entry.last_updated)));
+    let sampled_at = price_info.publish_time.unwrap_or(price_info.last_updated);
+
+    if price_info.price <= 0.0 {
+        price_info.ema_price = previous.map(|(ema, _)| ema).unwrap_or_default();
+        return;
+    }
+
+    price_info.ema_price = match previous {
+        Some((ema, previous_at)) if ema > 0.0 => {
+            let elapsed = (sampled_at - previous_at).num_milliseconds().max(0) as f64 / 1000.0;
+            let alpha = 1.0 - (-elapsed / EMA_PERIOD_SECS).exp();
+            ema + alpha * (price_info.price - ema)
+        }
+        _ => price_info.price,
+    };
+
+    let mut history = PRICE_HISTORY.entry(mint.to_string()).or_default();
+    if history.back().is_some_and(|last| {
+        last.publish_time > sampled_at
+            || (last.slot == price_info.publish_slot && last.publish_time == sampled_at)
+    }) {
+        return;
+    }
+
+    if history.len() == PRICE_HISTORY_CAPACITY {
+        history.pop_front();
+    }
+    history.push_back(PriceSample {
+        slot: price_info.publish_slot,
+        publish_time: sampled_at,
+        price: price_info.price,
+        confidence: price_info.confidence,
+    });
+}
+
+pub fn time_weighted_average(
+    samples: &VecDeque<PriceSample>,
+    now: DateTime<Utc>,
+    window: Duration,
+) -> Option<f64> {
+    let start = now - chrono::Duration::from_std(window).ok()?;
+    let mut weighted_sum = 0.0;
+    let mut total_secs = 0.0;
+
+    for (i, sample) in samples.iter().enumerate() {
+        let end = samples.get(i + 1).map(|next| next.publish_time).unwrap_or(now);
+        let begin = sample.publish_time.max(start);
+        if end <= begin {
+            continue;
+        }
+        let secs = (end - begin).num_milliseconds() as f64 / 1000.0;
+        weighted_sum += sample.price * secs;
+        total_secs += secs;
+    }
+
+    if total_secs > 0.0 {
+        Some(weighted_sum / total_secs)
+    } else {
+        samples.back().map(|sample| sample.price)
+    }
+}
+
+// Realized volatility from squared log returns over the window, scaled to one hour.
+fn realized_volatility(samples: &VecDeque<PriceSample>, now: DateTime<Utc>, window: Duration) -> Option<f64> {
+    let start = now - chrono::Duration::from_std(window).ok()?;
+    let in_window: Vec<&PriceSample> = samples
+        .iter()
+        .filter(|sample| sample.publish_time >= start && sample.price > 0.0)
+        .collect();
+    if in_window.len() < 3 {
+        return None;
+    }
+
+    let sum_squared: f64 = in_window
+        .windows(2)
+        .map(|pair| (pair[1].price / pair[0].price).ln().powi(2))
+        .sum();
+    let elapsed = (in_window[in_window.len() - 1].publish_time - in_window[0].publish_time)
+        .num_milliseconds() as f64
+        / 1000.0;
+    if elapsed <= 0.0 {
+        return None;
+    }
+
+    Some((sum_squared / elapsed * 3600.0).sqrt())
+}
+
 fn display_current_prices() {
     let price_state = get_price_state();
     
@@ -419,4 +630,221 @@ pub fn get_current_price(mint: &str) -> Option<f64> {
 
 pub fn get_current_price_info(mint: &str) -> Option<TokenPrice> {
     PRICE_STATE.get(mint).map(|entry| entry.clone())
-}
\ No newline at end of file
+}
+
+pub fn get_twap(mint: &str, window: Duration) -> Option<f64> {
+    let history = PRICE_HISTORY.get(mint)?;
+    time_weighted_average(&history, Utc::now(), window)
+}
+
+pub fn get_realized_volatility(mint: &str, window: Duration) -> Option<f64> {
+    let history = PRICE_HISTORY.get(mint)?;
+    realized_volatility(&history, Utc::now(), window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_geyser::pyth_price_account;
+    use yellowstone_grpc_proto::geyser::SubscribeUpdateAccountInfo;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn sample(secs: i64, price: f64) -> PriceSample {
+        PriceSample { slot: secs as u64, publish_time: at(secs), price, confidence: 0.0 }
+    }
+
+    // Mirrors the listener: fold into the averages, then publish to PRICE_STATE.
+    fn record(mint: &str, slot: u64, secs: i64, price: f64) -> TokenPrice {
+        let mut price_info = TokenPrice {
+            mint: mint.to_string(),
+            symbol: "TEST".to_string(),
+            price,
+            confidence: 0.0,
+            last_updated: at(secs),
+            publish_time: Some(at(secs)),
+            publish_slot: slot,
+            status: "test".to_string(),
+            ema_price: price,
+            oracle_ema_price: None,
+            oracle_ema_confidence: None,
+        };
+        update_averages(mint, &mut price_info);
+        PRICE_STATE.insert(mint.to_string(), price_info.clone());
+        price_info
+    }
+
+    fn history(mint: &str) -> Vec<PriceSample> {
+        PRICE_HISTORY.get(mint).map(|history| history.iter().copied().collect()).unwrap_or_default()
+    }
+
+    fn account_update(pubkey: Pubkey, data: Vec<u8>) -> UpdateOneof {
+        UpdateOneof::Account(SubscribeUpdateAccount {
+            account: Some(SubscribeUpdateAccountInfo {
+                pubkey: pubkey.to_bytes().to_vec(),
+                data,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn subscribed(listener: &PriceListener, price_account: &Pubkey) -> bool {
+        listener.account_to_mint.contains_key(price_account)
+            && listener
+                .get_subscription_request()
+                .accounts
+                .get(PRICE_ACCOUNTS_FILTER)
+                .is_some_and(|filter| filter.account.contains(&price_account.to_string()))
+    }
+
+    #[tokio::test]
+    async fn bad_prices_keep_the_feed_subscribed() {
+        let listener = PriceListener::new(Vec::new());
+        let (mint, price_account) = (Pubkey::new_unique().to_string(), Pubkey::new_unique());
+        listener.add_price_account(&mint, price_account);
+
+        // A zero aggregate price, then one above the sanity range.
+        listener.handle_update(account_update(price_account, pyth_price_account(0, -8, 100)));
+        assert!(subscribed(&listener, &price_account));
+        listener.handle_update(account_update(price_account, pyth_price_account(i64::MAX, -2, 101)));
+        assert!(subscribed(&listener, &price_account));
+        assert!(get_current_price(&mint).is_none());
+
+        // The feed recovers on the next good tick.
+        listener.handle_update(account_update(price_account, pyth_price_account(2_500_000_000, -8, 102)));
+        assert_eq!(get_current_price(&mint), Some(25.0));
+    }
+
+    #[tokio::test]
+    async fn accounts_that_are_not_pyth_price_accounts_are_unsubscribed() {
+        let listener = PriceListener::new(Vec::new());
+        let mint = Pubkey::new_unique().to_string();
+        let (bad_magic, bad_version, too_small) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        for price_account in [bad_magic, bad_version, too_small] {
+            listener.add_price_account(&mint, price_account);
+        }
+
+        let mut data = pyth_price_account(2_500_000_000, -8, 100);
+        data[0] ^= 0xff;
+        listener.handle_update(account_update(bad_magic, data));
+        let mut data = pyth_price_account(2_500_000_000, -8, 100);
+        data[4..8].copy_from_slice(&1u32.to_le_bytes());
+        listener.handle_update(account_update(bad_version, data));
+        let mut data = pyth_price_account(2_500_000_000, -8, 100);
+        data.truncate(PYTH_PRICE_ACCOUNT_MIN_SIZE - 1);
+        listener.handle_update(account_update(too_small, data));
+
+        for price_account in [bad_magic, bad_version, too_small] {
+            assert!(!subscribed(&listener, &price_account));
+        }
+        assert!(get_current_price(&mint).is_none());
+    }
+
+    #[test]
+    fn twap_weights_each_price_by_how_long_it_held() {
+        let samples: VecDeque<PriceSample> = [sample(0, 10.0), sample(10, 20.0), sample(30, 30.0)].into();
+        let now = at(40);
+
+        // 10 for 10s, 20 for 20s, 30 for 10s: 800 / 40.
+        let twap = time_weighted_average(&samples, now, Duration::from_secs(40)).unwrap();
+        assert!((twap - 20.0).abs() < 1e-9);
+
+        // The window starts at t=20, clipping the first price out and the second to 10s: 500 / 20.
+        let twap = time_weighted_average(&samples, now, Duration::from_secs(20)).unwrap();
+        assert!((twap - 25.0).abs() < 1e-9);
+
+        // A window longer than the history only averages the time the history covers.
+        let twap = time_weighted_average(&samples, now, Duration::from_secs(3600)).unwrap();
+        assert!((twap - 20.0).abs() < 1e-9);
+
+        // An empty window falls back to the latest price.
+        assert_eq!(time_weighted_average(&samples, at(30), Duration::ZERO), Some(30.0));
+        assert_eq!(time_weighted_average(&VecDeque::new(), now, Duration::from_secs(40)), None);
+    }
+
+    #[test]
+    fn realized_volatility_scales_squared_log_returns_to_an_hour() {
+        let samples: VecDeque<PriceSample> =
+            [sample(0, 100.0), sample(60, 110.0), sample(120, 99.0), sample(180, 99.0)].into();
+        let now = at(180);
+
+        // ln(1.1)² + ln(0.9)² + 0 over 180s, scaled to 3600s: sqrt(0.020185 * 20) ≈ 0.6354.
+        let expected = ((1.1f64.ln().powi(2) + 0.9f64.ln().powi(2)) / 180.0 * 3600.0).sqrt();
+        let volatility = realized_volatility(&samples, now, Duration::from_secs(180)).unwrap();
+        assert!((volatility - expected).abs() < 1e-12);
+        assert!((volatility - 0.6354).abs() < 1e-4);
+
+        // The 120s window drops the first sample: ln(0.9)² over 120s.
+        let expected = (0.9f64.ln().powi(2) / 120.0 * 3600.0).sqrt();
+        let volatility = realized_volatility(&samples, now, Duration::from_secs(120)).unwrap();
+        assert!((volatility - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn realized_volatility_needs_two_returns() {
+        let now = at(120);
+        let window = Duration::from_secs(3600);
+
+        assert_eq!(realized_volatility(&VecDeque::new(), now, window), None);
+        assert_eq!(realized_volatility(&[sample(0, 100.0)].into(), now, window), None);
+        assert_eq!(realized_volatility(&[sample(0, 100.0), sample(60, 110.0)].into(), now, window), None);
+
+        // Samples outside the window do not count toward the minimum.
+        let samples: VecDeque<PriceSample> = [sample(0, 100.0), sample(60, 110.0), sample(120, 99.0)].into();
+        assert_eq!(realized_volatility(&samples, now, Duration::from_secs(60)), None);
+
+        // Three samples published at the same instant have no elapsed time to scale by.
+        let samples: VecDeque<PriceSample> = [sample(0, 100.0), sample(0, 110.0), sample(0, 99.0)].into();
+        assert_eq!(realized_volatility(&samples, now, window), None);
+    }
+
+    #[test]
+    fn ema_decays_toward_new_prices_by_elapsed_time() {
+        let mint = Pubkey::new_unique().to_string();
+
+        assert_eq!(record(&mint, 1, 0, 10.0).ema_price, 10.0);
+
+        // One full period: alpha = 1 - e^-1.
+        let ema = record(&mint, 2, 60, 20.0).ema_price;
+        let expected = 10.0 + (1.0 - (-1.0f64).exp()) * 10.0;
+        assert!((ema - expected).abs() < 1e-9);
+
+        // No time elapsed: alpha = 0 and the EMA holds.
+        let held = record(&mint, 3, 60, 30.0).ema_price;
+        assert!((held - expected).abs() < 1e-9);
+
+        // A non-trading update carries the EMA forward and stays out of the history.
+        assert!((record(&mint, 4, 90, 0.0).ema_price - expected).abs() < 1e-9);
+        assert_eq!(history(&mint).iter().map(|sample| sample.price).collect::<Vec<_>>(), vec![10.0, 20.0, 30.0]);
+    }
+
+    #[test]
+    fn history_skips_duplicates_and_stale_samples() {
+        let mint = Pubkey::new_unique().to_string();
+
+        record(&mint, 1, 10, 10.0);
+        record(&mint, 1, 10, 10.0);
+        record(&mint, 2, 5, 11.0);
+        record(&mint, 3, 20, 12.0);
+
+        let slots: Vec<u64> = history(&mint).iter().map(|sample| sample.slot).collect();
+        assert_eq!(slots, vec![1, 3]);
+    }
+
+    #[test]
+    fn history_evicts_the_oldest_sample_at_capacity() {
+        let mint = Pubkey::new_unique().to_string();
+
+        for i in 0..=PRICE_HISTORY_CAPACITY as i64 {
+            record(&mint, i as u64, i, 1.0 + i as f64);
+        }
+
+        let history = history(&mint);
+        assert_eq!(history.len(), PRICE_HISTORY_CAPACITY);
+        assert_eq!(history[0].slot, 1);
+        assert_eq!(history[history.len() - 1].slot, PRICE_HISTORY_CAPACITY as u64);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::account_source::{program_accounts_config, AccountFilter};
+use crate::geyser_stream::{account_filters, account_update, gap_fill, StreamConfig, SubscriptionHandle};
+use crate::price_listener::Listener;
+use crate::recorder::Recorder;
+use crate::rpc_pool::endpoint_label;
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use futures::stream::{BoxStream, SelectAll, StreamExt};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+use yellowstone_grpc_proto::geyser::{
+    subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest, SubscribeUpdateSlot,
+};
+
+type Unsubscribe = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+// WS_URL, or the first RPC endpoint with its scheme switched to ws:// / wss://.
+pub fn websocket_url_from_env() -> Result<String> {
+    if let Some(url) = std::env::var("WS_URL").ok().filter(|url| !url.is_empty()) {
+        return Ok(url);
+    }
+    let rpc_url = std::env::var("RPC_URLS")
+        .ok()
+        .and_then(|urls| urls.split(',').map(str::trim).find(|url| !url.is_empty()).map(str::to_string))
+        .or_else(|| std::env::var("RPC_URL").ok().filter(|url| !url.is_empty()))
+        .ok_or_else(|| anyhow!("WS_URL, RPC_URL or RPC_URLS must be set for the websocket transport"))?;
+    Ok(websocket_url(&rpc_url))
+}
+
+pub fn websocket_url(rpc_url: &str) -> String {
+    if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    }
+}
+
+#[derive(Default)]
+struct Session {
+    subscribed: bool,
+    messages: usize,
+}
+
+// Keeps a listener subscribed over the Solana PubSub websocket when no Geyser endpoint is
+// available. Account filters become accountSubscribe / programSubscribe subscriptions;
+// notifications reach handle_update as Geyser updates. Reconnect backoff and gap fill follow
+// the StreamConfig as in run_stream, but PubSub has no subscription pings: every connection
+// holds a slotSubscribe as heartbeat, forwarded only when the request has slot filters, and a
+// connection without any notification for stale_timeout is reconnected. Block metadata and
+// transactions have no websocket equivalent and are never delivered.
+pub async fn run_pubsub_stream<L: Listener>(listener: Arc<L>, url: String, config: StreamConfig) {
+    let recorder = Recorder::from_env(&listener.name());
+    let subscription = listener.subscription();
+    let mut connected_before = false;
+    let mut failures: u32 = 0;
+
+    loop {
+        info!("{} connecting to websocket: {}", listener.name(), endpoint_label(&url));
+        let mut session = Session::default();
+        let result = run_session(
+            listener.as_ref(),
+            &url,
+            &subscription,
+            &config,
+            recorder.as_ref(),
+            connected_before,
+            &mut session,
+        )
+        .await;
+        connected_before |= session.subscribed;
+
+        match result {
+            Ok(()) => warn!("{} websocket closed after {} messages", listener.name(), session.messages),
+            Err(e) => error!("{} websocket failed after {} messages: {:?}", listener.name(), session.messages, e),
+        }
+
+        failures = if session.subscribed && session.messages > 0 { 1 } else { failures + 1 };
+        let delay = config.backoff(failures);
+        warn!("{} reconnecting in {:?} (attempt {})", listener.name(), delay, failures);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+async fn run_session<L: Listener>(
+    listener: &L,
+    url: &str,
+    subscription: &SubscriptionHandle,
+    config: &StreamConfig,
+    recorder: Option<&Recorder>,
+    reconnect: bool,
+    session: &mut Session,
+) -> Result<()> {
+    let client = PubsubClient::new(url).await?;
+    let mut changes = subscription.watch();
+    let mut request = changes.borrow_and_update().clone();
+    let mut gap_fill_pending = reconnect;
+
+    // Slots advance every ~400ms, so a silent heartbeat means a dead connection.
+    let (mut heartbeat, _unsubscribe_heartbeat) = client.slot_subscribe().await?;
+    let mut last_message = Instant::now();
+
+    // PubSub subscriptions cannot be edited, so a subscription change replaces all of them on
+    // the same connection.
+    loop {
+        let (mut updates, unsubscribes) = subscribe(&client, &request).await?;
+        session.subscribed = true;
+        info!("{} subscribed to {} websocket streams", listener.name(), unsubscribes.len());
+
+        if std::mem::take(&mut gap_fill_pending) {
+            if let Some(source) = &config.gap_fill {
+                match gap_fill(listener, &request, source.as_ref(), recorder).await {
+                    Ok(filled) => info!("{} gap fill re-read {} accounts", listener.name(), filled),
+                    Err(e) => error!("{} gap fill failed: {:?}", listener.name(), e),
+                }
+            }
+        }
+
+        loop {
+            tokio::select! {
+                update = updates.next(), if !updates.is_empty() => {
+                    // Every notification stream ends when the websocket closes.
+                    let Some(update) = update else {
+                        return Ok(());
+                    };
+                    session.messages += 1;
+                    last_message = Instant::now();
+                    if let Some(recorder) = recorder {
+                        recorder.record(&update);
+                    }
+                    listener.handle_update(update);
+                }
+                slot = heartbeat.next() => {
+                    let Some(slot) = slot else {
+                        return Ok(());
+                    };
+                    session.messages += 1;
+                    last_message = Instant::now();
+                    if !request.slots.is_empty() {
+                        let update = UpdateOneof::Slot(SubscribeUpdateSlot {
+                            slot: slot.slot,
+                            parent: Some(slot.parent),
+                            status: CommitmentLevel::Processed as i32,
+                        });
+                        if let Some(recorder) = recorder {
+                            recorder.record(&update);
+                        }
+                        listener.handle_update(update);
+                    }
+                }
+                changed = changes.changed() => {
+                    changed?;
+                    break;
+                }
+                _ = tokio::time::sleep_until(last_message + config.stale_timeout) => {
+                    return Err(anyhow!("no messages for {:?}", config.stale_timeout));
+                }
+            }
+        }
+
+        drop(updates);
+        for unsubscribe in unsubscribes {
+            unsubscribe().await;
+        }
+        request = changes.borrow_and_update().clone();
+        info!("{} updating websocket subscriptions to {} account filters", listener.name(), request.accounts.len());
+    }
+}
+
+async fn subscribe<'a>(
+    client: &'a PubsubClient,
+    request: &SubscribeRequest,
+) -> Result<(SelectAll<BoxStream<'a, UpdateOneof>>, Vec<Unsubscribe>)> {
+    let commitment = commitment_config(request.commitment);
+    let mut updates = SelectAll::new();
+    let mut unsubscribes: Vec<Unsubscribe> = Vec::new();
+
+    for (name, filter) in &request.accounts {
+        let owners = parse_pubkeys(&filter.owner)?;
+        let data_filters = account_filters(filter);
+
+        if !filter.account.is_empty() {
+            let config = RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(commitment),
+                data_slice: None,
+                min_context_slot: None,
+            };
+            for address in parse_pubkeys(&filter.account)? {
+                let (stream, unsubscribe) = client.account_subscribe(&address, Some(config.clone())).await?;
+                let owners = owners.clone();
+                let data_filters = data_filters.clone();
+                updates.push(
+                    stream
+                        .filter_map(move |response| {
+                            let update = response
+                                .value
+                                .decode::<Account>()
+                                .filter(|account| matches(account, &owners, &data_filters))
+                                .map(|account| account_update(address, &account, response.context.slot, false));
+                            futures::future::ready(update)
+                        })
+                        .boxed(),
+                );
+                unsubscribes.push(unsubscribe);
+            }
+        } else if !owners.is_empty() {
+            let mut config = program_accounts_config(&data_filters);
+            config.account_config.commitment = Some(commitment);
+            for owner in owners {
+                let (stream, unsubscribe) = client.program_subscribe(&owner, Some(config.clone())).await?;
+                updates.push(
+                    stream
+                        .filter_map(|response| {
+                            let slot = response.context.slot;
+                            let update = Pubkey::from_str(&response.value.pubkey)
+                                .ok()
+                                .zip(response.value.account.decode::<Account>())
+                                .map(|(address, account)| account_update(address, &account, slot, false));
+                            futures::future::ready(update)
+                        })
+                        .boxed(),
+                );
+                unsubscribes.push(unsubscribe);
+            }
+        } else {
+            warn!("Skipping account filter {}: matching every account has no websocket equivalent", name);
+        }
+    }
+
+    if !request.blocks_meta.is_empty() || !request.blocks.is_empty() || !request.transactions.is_empty() {
+        warn!("Block and transaction filters have no websocket equivalent and are not streamed");
+    }
+
+    Ok((updates, unsubscribes))
+}
+
+// Yellowstone streams at processed commitment unless the request says otherwise.
+fn commitment_config(commitment: Option<i32>) -> CommitmentConfig {
+    match commitment.and_then(|level| CommitmentLevel::try_from(level).ok()) {
+        Some(CommitmentLevel::Confirmed) => CommitmentConfig::confirmed(),
+        Some(CommitmentLevel::Finalized) => CommitmentConfig::finalized(),
+        _ => CommitmentConfig::processed(),
+    }
+}
+
+fn parse_pubkeys(values: &[String]) -> Result<Vec<Pubkey>> {
+    values
+        .iter()
+        .map(|value| Pubkey::from_str(value).map_err(|e| anyhow!("invalid pubkey {}: {}", value, e)))
+        .collect()
+}
+
+// accountSubscribe has no owner or data filters, so those of the Geyser filter apply here.
+fn matches(account: &Account, owners: &[Pubkey], data_filters: &[AccountFilter]) -> bool {
+    (owners.is_empty() || owners.contains(&account.owner))
+        && data_filters.iter().all(|filter| filter.matches(&account.data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_websocket_urls_from_rpc_urls() {
+        assert_eq!(websocket_url("https://api.mainnet-beta.solana.com"), "wss://api.mainnet-beta.solana.com");
+        assert_eq!(websocket_url("http://127.0.0.1:8899"), "ws://127.0.0.1:8899");
+        assert_eq!(websocket_url("wss://example.com/ws"), "wss://example.com/ws");
+    }
+
+    #[test]
+    fn keeps_the_subscription_commitment() {
+        assert_eq!(commitment_config(None), CommitmentConfig::processed());
+        assert_eq!(commitment_config(Some(CommitmentLevel::Finalized as i32)), CommitmentConfig::finalized());
+    }
+}
@@ -0,0 +1,386 @@
+use crate::backtest::AccountUpdate;
+use crate::price_listener::Listener;
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use yellowstone_grpc_proto::geyser::{
+    subscribe_update::UpdateOneof, SubscribeUpdateAccount, SubscribeUpdateAccountInfo,
+};
+
+pub const RECORDING_MAGIC: &[u8; 8] = b"KLREC001";
+pub const RECORDING_EXTENSION: &str = "rec";
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 256 * 1024 * 1024;
+const MAX_RECORD_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordedAccount {
+    pub pubkey: [u8; 32],
+    pub owner: [u8; 32],
+    pub slot: u64,
+    pub write_version: u64,
+    pub received_at_ms: i64,
+    pub data: Vec<u8>,
+}
+
+impl RecordedAccount {
+    pub fn from_update(update: &SubscribeUpdateAccount, received_at_ms: i64) -> Option<Self> {
+        let account = update.account.as_ref()?;
+        Some(RecordedAccount {
+            pubkey: account.pubkey.as_slice().try_into().ok()?,
+            owner: account.owner.as_slice().try_into().ok()?,
+            slot: update.slot,
+            write_version: account.write_version,
+            received_at_ms,
+            data: account.data.clone(),
+        })
+    }
+
+    pub fn to_update(&self) -> UpdateOneof {
+        UpdateOneof::Account(SubscribeUpdateAccount {
+            account: Some(SubscribeUpdateAccountInfo {
+                pubkey: self.pubkey.to_vec(),
+                owner: self.owner.to_vec(),
+                data: self.data.clone(),
+                write_version: self.write_version,
+                ..Default::default()
+            }),
+            slot: self.slot,
+            is_startup: false,
+        })
+    }
+}
+
+impl From<RecordedAccount> for AccountUpdate {
+    fn from(record: RecordedAccount) -> Self {
+        AccountUpdate {
+            pubkey: Pubkey::new_from_array(record.pubkey),
+            owner: Pubkey::new_from_array(record.owner),
+            slot: record.slot,
+            write_version: record.write_version,
+            timestamp_ms: record.received_at_ms,
+            data: record.data,
+        }
+    }
+}
+
+// Append-only recording files: an 8 byte magic, then records framed as a little endian u32
+// length followed by the bincode encoded RecordedAccount. A new file is started once the
+// current one exceeds max_file_bytes.
+struct RecordingWriter {
+    dir: PathBuf,
+    name: String,
+    max_file_bytes: u64,
+    file: Option<BufWriter<File>>,
+    bytes_written: u64,
+    sequence: u64,
+}
+
+impl RecordingWriter {
+    fn write(&mut self, record: &RecordedAccount) -> Result<()> {
+        let payload = bincode::serialize(record)?;
+        if self.file.is_none() || self.bytes_written >= self.max_file_bytes {
+            self.rotate()?;
+        }
+
+        let file = self.file.as_mut().ok_or_else(|| anyhow!("recording file not open"))?;
+        file.write_all(&(payload.len() as u32).to_le_bytes())?;
+        file.write_all(&payload)?;
+        self.bytes_written += 4 + payload.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+
+        self.sequence += 1;
+        let path = self.dir.join(format!(
+            "{}-{}-{:04}.{}",
+            self.name,
+            chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+            self.sequence,
+            RECORDING_EXTENSION
+        ));
+        let mut file = BufWriter::new(OpenOptions::new().create_new(true).append(true).open(&path)?);
+        file.write_all(RECORDING_MAGIC)?;
+        self.bytes_written = RECORDING_MAGIC.len() as u64;
+        self.file = Some(file);
+        info!("Recording account updates to {}", path.display());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Some(file) = self.file.as_mut() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// Records every account update a listener receives; files are written from a dedicated
+// thread so the stream loop never blocks on disk.
+#[derive(Clone)]
+pub struct Recorder {
+    sender: mpsc::Sender<RecordedAccount>,
+}
+
+impl Recorder {
+    pub fn open(dir: impl AsRef<Path>, name: &str, max_file_bytes: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut writer = RecordingWriter {
+            dir,
+            name: name.to_string(),
+            max_file_bytes,
+            file: None,
+            bytes_written: 0,
+            sequence: 0,
+        };
+        let (sender, receiver) = mpsc::channel::<RecordedAccount>();
+        thread::Builder::new()
+            .name(format!("{}-recorder", name))
+            .spawn(move || {
+                while let Ok(record) = receiver.recv() {
+                    let result = std::iter::once(record)
+                        .chain(receiver.try_iter())
+                        .try_for_each(|record| writer.write(&record))
+                        .and_then(|_| writer.flush());
+                    if let Err(e) = result {
+                        error!("Failed to write recording: {:?}", e);
+                    }
+                }
+            })?;
+
+        Ok(Recorder { sender })
+    }
+
+    // Enabled by RECORD_DIR; RECORD_MAX_FILE_MB controls rotation.
+    pub fn from_env(name: &str) -> Option<Self> {
+        let dir = std::env::var("RECORD_DIR").ok().filter(|dir| !dir.is_empty())?;
+        let max_file_bytes = std::env::var("RECORD_MAX_FILE_MB")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(|mb| mb * 1024 * 1024)
+            .unwrap_or(DEFAULT_MAX_FILE_BYTES);
+
+        match Recorder::open(&dir, name, max_file_bytes) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                error!("Failed to start recorder in {}: {:?}", dir, e);
+                None
+            }
+        }
+    }
+
+    pub fn record(&self, update: &UpdateOneof) {
+        let UpdateOneof::Account(account) = update else {
+            return;
+        };
+        let received_at_ms = chrono::Utc::now().timestamp_millis();
+        if let Some(record) = RecordedAccount::from_update(account, received_at_ms) {
+            let _ = self.sender.send(record);
+        }
+    }
+}
+
+pub struct RecordingReader {
+    reader: BufReader<File>,
+    path: PathBuf,
+}
+
+impl RecordingReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut reader = BufReader::new(File::open(&path)?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != RECORDING_MAGIC {
+            bail!("{} is not a recording file", path.display());
+        }
+        Ok(RecordingReader { reader, path })
+    }
+}
+
+impl Iterator for RecordingReader {
+    type Item = Result<RecordedAccount>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut length = [0u8; 4];
+        match self.reader.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e.into())),
+        }
+
+        let length = u32::from_le_bytes(length) as usize;
+        if length > MAX_RECORD_BYTES {
+            return Some(Err(anyhow!("corrupt record length {} in {}", length, self.path.display())));
+        }
+        let mut payload = vec![0u8; length];
+        if let Err(e) = self.reader.read_exact(&mut payload) {
+            // A record cut short by a crash mid-write ends the file.
+            warn!("Truncated record at end of {}: {}", self.path.display(), e);
+            return None;
+        }
+        Some(bincode::deserialize(&payload).map_err(Into::into))
+    }
+}
+
+// Recording files in a directory (or a single file), in the order they were written.
+pub fn recording_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == RECORDING_EXTENSION))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+pub fn read_recordings(path: impl AsRef<Path>) -> Result<Vec<RecordedAccount>> {
+    let mut records = Vec::new();
+    for file in recording_files(path)? {
+        for record in RecordingReader::open(&file)? {
+            records.push(record?);
+        }
+    }
+    Ok(records)
+}
+
+// Feeds a recording into a listener's handle_update. With a speed the original spacing
+// between updates is kept (2.0 replays twice as fast); without one updates are sent back to back.
+pub async fn replay_into<L: Listener>(listener: Arc<L>, path: impl AsRef<Path>, speed: Option<f64>) -> Result<usize> {
+    let mut replayed = 0;
+    let mut previous_ms: Option<i64> = None;
+
+    for file in recording_files(path)? {
+        for record in RecordingReader::open(&file)? {
+            let record = record?;
+            if let (Some(speed), Some(previous_ms)) = (speed.filter(|speed| *speed > 0.0), previous_ms) {
+                let gap_ms = (record.received_at_ms - previous_ms).max(0) as f64 / speed;
+                if gap_ms >= 1.0 {
+                    tokio::time::sleep(Duration::from_millis(gap_ms as u64)).await;
+                }
+            }
+            previous_ms = Some(record.received_at_ms);
+            listener.handle_update(record.to_update());
+            replayed += 1;
+        }
+    }
+
+    info!("{} replayed {} recorded account updates", listener.name(), replayed);
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use yellowstone_grpc_proto::geyser::SubscribeRequest;
+
+    #[derive(Default)]
+    struct Collector(Mutex<Vec<UpdateOneof>>);
+
+    impl Listener for Collector {
+        fn get_subscription_request(&self) -> SubscribeRequest {
+            SubscribeRequest::default()
+        }
+
+        fn handle_update(&self, update: UpdateOneof) {
+            self.0.lock().unwrap().push(update);
+        }
+
+        fn name(&self) -> String {
+            "Collector".to_string()
+        }
+    }
+
+    fn record(slot: u64) -> RecordedAccount {
+        RecordedAccount {
+            pubkey: Pubkey::new_unique().to_bytes(),
+            owner: Pubkey::new_unique().to_bytes(),
+            slot,
+            write_version: slot * 10,
+            received_at_ms: 1_700_000_000_000 + slot as i64,
+            data: vec![slot as u8; 64],
+        }
+    }
+
+    fn writer(dir: &Path, max_file_bytes: u64) -> RecordingWriter {
+        RecordingWriter {
+            dir: dir.to_path_buf(),
+            name: "test".to_string(),
+            max_file_bytes,
+            file: None,
+            bytes_written: 0,
+            sequence: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_records_across_rotated_files_in_order() {
+        let dir = std::env::temp_dir().join(format!("recording-{}", Pubkey::new_unique()));
+        fs::create_dir_all(&dir).unwrap();
+        let records: Vec<RecordedAccount> = (1..=3).map(record).collect();
+
+        // Every file is full after one record, so each record lands in its own file.
+        let mut writer = writer(&dir, 1);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(recording_files(&dir).unwrap().len(), 3);
+
+        let listener = Arc::new(Collector::default());
+        assert_eq!(replay_into(Arc::clone(&listener), &dir, None).await.unwrap(), 3);
+        let replayed = listener.0.lock().unwrap();
+        for (update, record) in replayed.iter().zip(&records) {
+            let UpdateOneof::Account(update) = update else {
+                panic!("unexpected update {:?}", update);
+            };
+            let account = update.account.as_ref().unwrap();
+            assert_eq!(update.slot, record.slot);
+            assert_eq!(account.pubkey, record.pubkey.to_vec());
+            assert_eq!(account.write_version, record.write_version);
+            assert_eq!(account.data, record.data);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_truncated_final_frame_ends_the_recording() {
+        let dir = std::env::temp_dir().join(format!("recording-{}", Pubkey::new_unique()));
+        fs::create_dir_all(&dir).unwrap();
+        let records: Vec<RecordedAccount> = (1..=2).map(record).collect();
+        let mut writer = writer(&dir, DEFAULT_MAX_FILE_BYTES);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        writer.flush().unwrap();
+        drop(writer);
+
+        // A crash mid-write leaves a length prefix with only part of its payload.
+        let file = recording_files(&dir).unwrap().remove(0);
+        let mut partial = OpenOptions::new().append(true).open(&file).unwrap();
+        partial.write_all(&200u32.to_le_bytes()).unwrap();
+        partial.write_all(&[7u8; 50]).unwrap();
+        drop(partial);
+
+        assert_eq!(read_recordings(&dir).unwrap(), records);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
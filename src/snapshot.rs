@@ -0,0 +1,252 @@
+use crate::blockhash_listener::current_slot;
+use crate::deleverage::DeleverageStatus;
+use crate::events::EvaluationEvent;
+use crate::health::ObligationHealth;
+use crate::liquidation_price::{LiquidationPrice, ALERT_PRICE_DISTANCE};
+use crate::orders::OrderEvaluation;
+use crate::planner::OpportunityInfo;
+use crate::persistence::{Database, HealthRow};
+use crate::price_listener::{get_price_state, get_token_symbol};
+use crate::volatility::{ObligationRisk, VolatilityEstimate};
+use crate::watchlist::{WatchTier, WatchlistEntryInfo};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, info, warn};
+
+pub const DEFAULT_SNAPSHOT_PATH: &str = "obligations_with_pyth_prices.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PriceInfo {
+    pub symbol: String,
+    pub price: f64,
+    pub confidence: f64,
+    pub status: String,
+    pub last_updated: String,
+    pub publish_time: Option<String>,
+    pub publish_slot: u64,
+    pub ema_price: f64,
+    pub oracle_ema_price: Option<f64>,
+    pub twap: Option<f64>,
+    pub volatility: Vec<VolatilityEstimate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DepositInfo {
+    pub reserve_address: String,
+    pub token_mint: String,
+    pub token_symbol: String,
+    pub deposited_amount: u64,
+    pub market_value: String,
+    pub live_price: Option<f64>,
+    pub price_status: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BorrowInfo {
+    pub reserve_address: String,
+    pub token_mint: String,
+    pub token_symbol: String,
+    pub borrowed_amount: String,
+    pub market_value: String,
+    pub live_price: Option<f64>,
+    pub price_status: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ObligationInfo {
+    pub obligation_address: String,
+    pub slot: u64,
+    pub owner: String,
+    pub deposited_value: String,
+    pub borrowed_value: String,
+    pub allowed_borrow_value: String,
+    pub unhealthy_borrow_value: String,
+    pub elevation_group: u8,
+    pub has_debt: bool,
+    pub active_deposits_count: usize,
+    pub active_borrows_count: usize,
+    pub deposits: Vec<DepositInfo>,
+    pub borrows: Vec<BorrowInfo>,
+    pub all_token_mints: Vec<String>,
+    pub live_prices: HashMap<String, PriceInfo>,
+    pub health: ObligationHealth,
+    pub risk: ObligationRisk,
+    pub liquidation_prices: Vec<LiquidationPrice>,
+    pub orders: Vec<OrderEvaluation>,
+    pub deleverage: Option<DeleverageStatus>,
+    pub opportunities: Vec<OpportunityInfo>,
+    pub watch: Option<WatchlistEntryInfo>,
+    pub last_updated: String,
+}
+
+// Keeps the latest evaluation of every obligation and flushes them to the snapshot file,
+// ordered by distance to liquidation, whenever something changed since the last flush.
+pub struct SnapshotSink {
+    path: PathBuf,
+    flush_interval: Duration,
+    horizon: Duration,
+    latest: HashMap<String, Arc<ObligationInfo>>,
+    dirty: bool,
+    update_counter: usize,
+    database: Option<Database>,
+}
+
+impl SnapshotSink {
+    pub fn new(path: PathBuf, flush_interval: Duration, horizon: Duration) -> Self {
+        SnapshotSink {
+            path,
+            flush_interval,
+            horizon,
+            latest: HashMap::new(),
+            dirty: false,
+            update_counter: 0,
+            database: None,
+        }
+    }
+
+    pub fn with_database(mut self, database: Option<Database>) -> Self {
+        self.database = database;
+        self
+    }
+
+    pub fn start(mut self, mut receiver: broadcast::Receiver<EvaluationEvent>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut flush = tokio::time::interval(self.flush_interval);
+            loop {
+                tokio::select! {
+                    event = receiver.recv() => match event {
+                        Ok(event) => self.apply(event),
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Snapshot sink lagged, skipped {} evaluations", skipped);
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = flush.tick() => {
+                        if self.dirty {
+                            if let Err(e) = self.write() {
+                                error!("Failed to write snapshot: {:?}", e);
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn apply(&mut self, event: EvaluationEvent) {
+        match event {
+            EvaluationEvent::Evaluated(info) => {
+                self.latest.insert(info.obligation_address.clone(), info);
+            }
+            EvaluationEvent::Removed(address) => {
+                self.latest.remove(&address.to_string());
+            }
+        }
+        self.dirty = true;
+    }
+
+    fn write(&mut self) -> Result<()> {
+        self.update_counter += 1;
+        self.dirty = false;
+
+        let mut obligations_info: Vec<ObligationInfo> = self
+            .latest
+            .values()
+            .map(|info| info.as_ref().clone())
+            .collect();
+        obligations_info.sort_by(|a, b| {
+            distance(a).total_cmp(&distance(b))
+                .then_with(|| a.obligation_address.cmp(&b.obligation_address))
+        });
+        for (rank, info) in obligations_info.iter_mut().enumerate() {
+            if let Some(watch) = info.watch.as_mut() {
+                watch.rank = rank + 1;
+            }
+        }
+
+        if let Some(database) = &self.database {
+            let prices = get_price_state().iter().map(|entry| entry.value().clone()).collect();
+            database.record_snapshot(
+                current_slot().unwrap_or_default(),
+                obligations_info.iter().map(HealthRow::from).collect(),
+                prices,
+            );
+        }
+
+        let json_string = serde_json::to_string_pretty(&obligations_info)?;
+        let mut file = File::create(&self.path)?;
+        file.write_all(json_string.as_bytes())?;
+
+        let total_live_prices: usize = obligations_info.iter()
+            .map(|o| o.live_prices.len())
+            .sum();
+        let total_opportunities: usize = obligations_info.iter()
+            .map(|o| o.opportunities.len())
+            .sum();
+        let marked_for_deleveraging = obligations_info.iter()
+            .filter(|o| o.deleverage.is_some())
+            .count();
+        let (hot, warm, cold) = tier_counts(&obligations_info);
+
+        info!("Updated obligations file (#{}) - {} obligations, {} Pyth prices, {} opportunities, {} marked for deleveraging", 
+              self.update_counter, obligations_info.len(), total_live_prices, total_opportunities, marked_for_deleveraging);
+        info!("   Watchlist: {} hot, {} warm, {} cold", hot, warm, cold);
+
+        for o in &obligations_info {
+            if o.health.underwater && !o.health.prices_trusted {
+                warn!("Obligation {} looks underwater but prices are not trusted: {:?}", o.obligation_address, o.health.price_issues);
+            }
+            if o.health.is_liquidatable {
+                continue;
+            }
+            for liquidation_price in o.liquidation_prices.iter().filter(|l| l.is_near(ALERT_PRICE_DISTANCE)) {
+                warn!("Liquidation alert: {} becomes liquidatable if {} moves {:+.2}% to ${:.6} (now ${:.6})",
+                      o.obligation_address, get_token_symbol(&liquidation_price.token_mint),
+                      liquidation_price.price_change.unwrap_or_default() * 100.0,
+                      liquidation_price.liquidation_price.unwrap_or_default(),
+                      liquidation_price.current_price);
+            }
+        }
+
+        let mut at_risk: Vec<&ObligationInfo> = obligations_info.iter()
+            .filter(|o| !o.health.is_liquidatable && o.risk.crossing_probability > 0.0)
+            .collect();
+        at_risk.sort_by(|a, b| b.risk.crossing_probability.total_cmp(&a.risk.crossing_probability));
+        for o in at_risk.iter().take(5) {
+            let dominant = o.liquidation_prices.iter().find(|l| l.dominant && l.liquidation_price.is_some());
+            info!("   At risk: {} health factor {:.4}, {:.1}% chance to cross within {}s, liquidation price {}",
+                  o.obligation_address, o.health.health_factor, o.risk.crossing_probability * 100.0,
+                  self.horizon.as_secs(),
+                  dominant
+                      .map(|l| format!("${:.6} for {}", l.liquidation_price.unwrap_or_default(), get_token_symbol(&l.token_mint)))
+                      .unwrap_or_else(|| "n/a".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+fn distance(info: &ObligationInfo) -> f64 {
+    info.watch
+        .as_ref()
+        .map(|watch| watch.distance_to_liquidation)
+        .unwrap_or(f64::MAX)
+}
+
+fn tier_counts(obligations_info: &[ObligationInfo]) -> (usize, usize, usize) {
+    obligations_info
+        .iter()
+        .filter_map(|info| info.watch.as_ref())
+        .fold((0, 0, 0), |(hot, warm, cold), watch| match watch.tier {
+            WatchTier::Hot => (hot + 1, warm, cold),
+            WatchTier::Warm => (hot, warm + 1, cold),
+            WatchTier::Cold => (hot, warm, cold + 1),
+        })
+}
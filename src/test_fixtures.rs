@@ -0,0 +1,37 @@
+use crate::health::PositionValue;
+use crate::price_guard::PriceVerdict;
+use crate::rpc_pool::{RpcEndpointConfig, RpcPool, RpcPoolConfig};
+use borsh::BorshDeserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::sync::Arc;
+
+// An all-zero klend account (Reserve, Obligation) for tests to fill in.
+pub fn zeroed<T: BorshDeserialize>() -> T {
+    T::deserialize(&mut &vec![0u8; 16_384][..]).unwrap()
+}
+
+// A live, fresh position of `amount` tokens at `price`, with an 80% liquidation threshold
+// and a neutral borrow factor.
+pub fn position(reserve: Pubkey, amount: f64, decimals: u32, price: f64) -> PositionValue {
+    PositionValue {
+        reserve_address: reserve.to_string(),
+        token_mint: Pubkey::new_unique().to_string(),
+        amount,
+        decimals,
+        price,
+        value: amount * price,
+        live_price: true,
+        price_verdict: PriceVerdict::Ok,
+        liquidation_threshold: 0.8,
+        borrow_factor: 1.0,
+    }
+}
+
+// A pool over a single unthrottled endpoint, usually a MockRpc.
+pub fn rpc_pool(url: &str) -> Arc<RpcPool> {
+    let endpoint = RpcEndpointConfig {
+        url: url.to_string(),
+        requests_per_second: 0.0,
+    };
+    Arc::new(RpcPool::new(vec![endpoint], RpcPoolConfig::default()).unwrap())
+}
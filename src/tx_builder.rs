@@ -0,0 +1,137 @@
+use crate::blockhash_listener::{self, DEFAULT_MAX_BLOCKHASH_SLOT_AGE};
+use crate::fees::{self, FeeEstimate, PriorityFeeEstimator, MAX_COMPUTE_UNIT_LIMIT};
+use crate::lookup_table::LookupTableManager;
+use crate::rpc_pool::RpcPool;
+use anyhow::{anyhow, Result};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
+    signature::{Keypair, Signature, Signer},
+    transaction::VersionedTransaction,
+};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+pub struct BuiltTransaction {
+    pub transaction: VersionedTransaction,
+    pub fee: FeeEstimate,
+    pub size: usize,
+    pub lookup_tables_used: usize,
+}
+
+pub struct TransactionBuilder {
+    payer: Arc<Keypair>,
+    rpc_pool: Arc<RpcPool>,
+    fee_estimator: PriorityFeeEstimator,
+    lookup_tables: Arc<LookupTableManager>,
+    pub max_blockhash_slot_age: u64,
+}
+
+impl TransactionBuilder {
+    pub fn new(
+        payer: Arc<Keypair>,
+        rpc_pool: Arc<RpcPool>,
+        fee_estimator: PriorityFeeEstimator,
+        lookup_tables: Arc<LookupTableManager>,
+    ) -> Self {
+        TransactionBuilder {
+            payer,
+            rpc_pool,
+            fee_estimator,
+            lookup_tables,
+            max_blockhash_slot_age: DEFAULT_MAX_BLOCKHASH_SLOT_AGE,
+        }
+    }
+
+    pub async fn latest_blockhash(&self) -> Result<Hash> {
+        if let Some((blockhash, _)) = blockhash_listener::get_cached_blockhash(self.max_blockhash_slot_age) {
+            return Ok(blockhash);
+        }
+
+        warn!("No fresh cached blockhash, fetching from RPC");
+        self.rpc_pool
+            .call_async(|client| async move { client.get_latest_blockhash().await })
+            .await
+    }
+
+    pub async fn build(
+        &self,
+        instructions: &[Instruction],
+        expected_profit_lamports: Option<u64>,
+        blockhash: Hash,
+    ) -> Result<BuiltTransaction> {
+        let queued = self.lookup_tables.queue_missing(instructions);
+        if queued > 0 {
+            warn!(
+                "{} liquidation accounts are not in a lookup table yet ({} pending)",
+                queued,
+                self.lookup_tables.pending_count()
+            );
+        }
+
+        let mut simulation_instructions =
+            vec![ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT)];
+        simulation_instructions.extend_from_slice(instructions);
+        let simulation_tx = self.compile_unsigned(&simulation_instructions, blockhash)?;
+
+        let fee = self
+            .fee_estimator
+            .estimate(&simulation_tx, &fees::writable_accounts(instructions), expected_profit_lamports)
+            .await;
+
+        let mut all_instructions = fee.instructions();
+        all_instructions.extend_from_slice(instructions);
+
+        let lookup_tables = self.lookup_tables.tables_for(&all_instructions);
+        let message = v0::Message::try_compile(
+            &self.payer.pubkey(),
+            &all_instructions,
+            &lookup_tables,
+            blockhash,
+        )?;
+        let lookup_tables_used = message.address_table_lookups.len();
+
+        let transaction =
+            VersionedTransaction::try_new(VersionedMessage::V0(message), &[self.payer.as_ref()])?;
+        let size = bincode::serialized_size(&transaction)? as usize;
+
+        if size > PACKET_DATA_SIZE {
+            return Err(anyhow!(
+                "transaction too large: {} bytes (limit {}), {} lookup tables used",
+                size,
+                PACKET_DATA_SIZE,
+                lookup_tables_used
+            ));
+        }
+
+        info!(
+            "Built v0 transaction: {} instructions, {} bytes, {} lookup tables",
+            all_instructions.len(),
+            size,
+            lookup_tables_used
+        );
+
+        Ok(BuiltTransaction {
+            transaction,
+            fee,
+            size,
+            lookup_tables_used,
+        })
+    }
+
+    fn compile_unsigned(&self, instructions: &[Instruction], blockhash: Hash) -> Result<VersionedTransaction> {
+        let lookup_tables = self.lookup_tables.tables_for(instructions);
+        let message =
+            v0::Message::try_compile(&self.payer.pubkey(), instructions, &lookup_tables, blockhash)?;
+        let signatures =
+            vec![Signature::default(); message.header.num_required_signatures as usize];
+
+        Ok(VersionedTransaction {
+            signatures,
+            message: VersionedMessage::V0(message),
+        })
+    }
+}
@@ -1,40 +1,30 @@
 use anyhow::Result;
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
-use solana_account_decoder::UiAccountEncoding;
+use solana_sdk::pubkey::Pubkey;
 use borsh::BorshDeserialize;
-use solana_client::rpc_config::RpcAccountInfoConfig;
-use crate::kamino::Obligation;
-use std::collections::HashMap;
+use crate::account_source::{write_account_dump, AccountFilter, AccountSource};
+use crate::kamino::{Obligation, Reserve};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use solana_sdk::account::Account;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
 
-pub async fn get_all_obligations_for_market(
-    rpc_client: &RpcClient,
+pub const MULTIPLE_ACCOUNTS_BATCH_SIZE: usize = 100;
+pub const MAX_CONCURRENT_BATCHES: usize = 8;
+
+fn market_filters(lending_market: &Pubkey) -> Vec<AccountFilter> {
+    let lending_market_offset = 32;
+    vec![AccountFilter::Memcmp {
+        offset: lending_market_offset,
+        bytes: lending_market.to_bytes().to_vec(),
+    }]
+}
+
+pub async fn get_all_obligations_for_market<S: AccountSource + ?Sized>(
+    source: &S,
     program_id: &Pubkey,
     lending_market: &Pubkey,
 ) -> Result<Vec<(Obligation, Pubkey)>> {
-    let lending_market_offset = 32;
-    
-    let filters = vec![
-        solana_client::rpc_filter::RpcFilterType::Memcmp(
-            solana_client::rpc_filter::Memcmp::new_base58_encoded(
-                lending_market_offset,
-                lending_market.to_bytes().as_slice(),
-            )
-        ),
-    ];
-
-    let config = solana_client::rpc_config::RpcProgramAccountsConfig {
-        filters: Some(filters),
-        account_config: RpcAccountInfoConfig {
-            encoding: Some(UiAccountEncoding::Base64),
-            commitment: Some(CommitmentConfig::confirmed()),
-            data_slice: None,
-            min_context_slot: None,
-        },
-        with_context: Some(true),
-    };
-
-    let accounts = rpc_client.get_program_accounts_with_config(program_id, config)?;
+    let accounts = source.get_program_accounts(program_id, &market_filters(lending_market)).await?;
     let mut obligations = Vec::new();
     
     for (pubkey, account) in &accounts {
@@ -55,12 +45,12 @@ pub async fn get_all_obligations_for_market(
     Ok(obligations)
 }
 
-pub async fn get_all_program_accounts(
-    rpc_client: &RpcClient,
+pub async fn get_all_program_accounts<S: AccountSource + ?Sized>(
+    source: &S,
     program_id: &Pubkey,
     lending_market: &Pubkey,
 ) -> Result<Vec<(Obligation, Pubkey)>> {
-    let accounts = rpc_client.get_program_accounts(program_id)?;
+    let accounts = source.get_program_accounts(program_id, &[]).await?;
     let mut obligations = Vec::new();
     
     for (pubkey, account) in &accounts {
@@ -80,25 +70,62 @@ pub async fn get_all_program_accounts(
 pub fn filter_obligations_with_borrows(obligations: Vec<(Obligation, Pubkey)>) -> Vec<(Obligation, Pubkey)> {
     obligations
         .into_iter()
-        .filter(|(obligation, _)| {
-            obligation.borrows.iter().any(|borrow| {
-                borrow.borrow_reserve != Pubkey::default() && borrow.borrowed_amount_sf > 0
-            })
-        })
+        .filter(|(obligation, _)| has_borrows(obligation))
         .collect()
 }
 
-pub async fn create_reserve_to_mint_mapping(
-    rpc_client: &RpcClient,
+fn has_borrows(obligation: &Obligation) -> bool {
+    obligation
+        .borrows
+        .iter()
+        .any(|borrow| borrow.borrow_reserve != Pubkey::default() && borrow.borrowed_amount_sf > 0)
+}
+
+// Captures the market's obligations with borrows and the reserves they use as account dumps
+// (obligations.json and reserves.json) for ACCOUNT_DUMP_PATH and test fixtures.
+pub async fn dump_market<S: AccountSource + ?Sized>(
+    source: &S,
+    program_id: &Pubkey,
+    lending_market: &Pubkey,
+    dir: &Path,
+) -> Result<(usize, usize)> {
+    let mut reserve_addresses = BTreeSet::new();
+    let obligations: Vec<(Pubkey, Account)> = source
+        .get_program_accounts(program_id, &market_filters(lending_market))
+        .await?
+        .into_iter()
+        .filter(|(_, account)| {
+            let Some(obligation) = account.data.get(8..).and_then(|data| Obligation::try_from_slice(data).ok()) else {
+                return false;
+            };
+            if !has_borrows(&obligation) {
+                return false;
+            }
+            reserve_addresses.extend(obligation.get_reserve_addresses());
+            true
+        })
+        .collect();
+
+    let reserve_addresses: Vec<Pubkey> = reserve_addresses.into_iter().collect();
+    let mut reserves = Vec::new();
+    for (chunk, accounts) in fetch_in_batches(source, &reserve_addresses).await? {
+        reserves.extend(chunk.iter().zip(accounts).filter_map(|(address, account)| Some((*address, account?))));
+    }
+
+    std::fs::create_dir_all(dir)?;
+    write_account_dump(dir.join("obligations.json"), &obligations)?;
+    write_account_dump(dir.join("reserves.json"), &reserves)?;
+    Ok((obligations.len(), reserves.len()))
+}
+
+pub async fn create_reserve_to_mint_mapping<S: AccountSource + ?Sized>(
+    source: &S,
     program_id: &Pubkey,
     reserve_addresses: Vec<Pubkey>,
 ) -> Result<HashMap<Pubkey, String>> {
-    const BATCH_SIZE: usize = 100;
     let mut reserve_to_mint = HashMap::new();
     
-    for chunk in reserve_addresses.chunks(BATCH_SIZE) {
-        let accounts = rpc_client.get_multiple_accounts(chunk)?;
-        
+    for (chunk, accounts) in fetch_in_batches(source, &reserve_addresses).await? {
         for (i, account_opt) in accounts.iter().enumerate() {
             let reserve_addr = chunk[i];
             
@@ -124,6 +151,42 @@ pub async fn create_reserve_to_mint_mapping(
     Ok(reserve_to_mint)
 }
 
+pub async fn get_reserves<S: AccountSource + ?Sized>(
+    source: &S,
+    program_id: &Pubkey,
+    reserve_addresses: &[Pubkey],
+) -> Result<HashMap<Pubkey, Reserve>> {
+    let mut reserves = HashMap::new();
+
+    for (chunk, accounts) in fetch_in_batches(source, reserve_addresses).await? {
+        for (reserve_addr, account_opt) in chunk.iter().zip(accounts) {
+            if let Some(account) = account_opt {
+                if account.owner != *program_id {
+                    continue;
+                }
+                if let Some(reserve) = Reserve::try_from_account_data(&account.data) {
+                    reserves.insert(*reserve_addr, reserve);
+                }
+            }
+        }
+    }
+
+    Ok(reserves)
+}
+
+// getMultipleAccounts takes at most 100 keys; batches are requested concurrently, at most
+// MAX_CONCURRENT_BATCHES at a time, and returned in input order.
+async fn fetch_in_batches<'a, S: AccountSource + ?Sized>(
+    source: &S,
+    addresses: &'a [Pubkey],
+) -> Result<Vec<(&'a [Pubkey], Vec<Option<Account>>)>> {
+    stream::iter(addresses.chunks(MULTIPLE_ACCOUNTS_BATCH_SIZE))
+        .map(|chunk| async move { Ok((chunk, source.get_multiple_accounts(chunk).await?)) })
+        .buffered(MAX_CONCURRENT_BATCHES)
+        .try_collect()
+        .await
+}
+
 pub fn try_extract_mint_from_reserve(data: &[u8]) -> Option<Pubkey> {
     let offset = 128;
     
@@ -154,4 +217,210 @@ pub fn try_extract_mint_from_reserve(data: &[u8]) -> Option<Pubkey> {
     }
 
     None
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account_source::{write_account_dump, FixtureAccountSource, InMemoryAccountSource};
+    use crate::kamino::f64_to_sf;
+    use crate::klend_listener::account_discriminator;
+    use crate::test_fixtures::zeroed;
+    use borsh::BorshSerialize;
+    use std::str::FromStr;
+
+    // Synthetic klend accounts laid out exactly like mainnet ones (discriminator + borsh body).
+    fn klend_account(program_id: Pubkey, name: &str, body: Vec<u8>) -> Account {
+        let mut data = account_discriminator(name).to_vec();
+        data.extend(body);
+        Account {
+            lamports: 1_000_000,
+            data,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn obligation_account(program_id: Pubkey, lending_market: Pubkey, borrow_reserve: Option<Pubkey>) -> Account {
+        let mut obligation: Obligation = zeroed();
+        obligation.lending_market = lending_market;
+        if let Some(reserve) = borrow_reserve {
+            obligation.borrows[0].borrow_reserve = reserve;
+            obligation.borrows[0].borrowed_amount_sf = f64_to_sf(1_000_000.0);
+        }
+        klend_account(program_id, "Obligation", obligation.try_to_vec().unwrap())
+    }
+
+    fn reserve_account(program_id: Pubkey, lending_market: Pubkey, mint: Pubkey) -> Account {
+        let mut reserve: Reserve = zeroed();
+        reserve.lending_market = lending_market;
+        reserve.liquidity.mint_pubkey = mint;
+        reserve.liquidity.mint_decimals = 6;
+        klend_account(program_id, "Reserve", reserve.try_to_vec().unwrap())
+    }
+
+    struct Market {
+        program_id: Pubkey,
+        lending_market: Pubkey,
+        reserve: Pubkey,
+        mint: Pubkey,
+        borrower: Pubkey,
+        depositor: Pubkey,
+        accounts: Vec<(Pubkey, Account)>,
+    }
+
+    fn market() -> Market {
+        let program_id = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let reserve = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let borrower = Pubkey::new_unique();
+        let depositor = Pubkey::new_unique();
+        let accounts = vec![
+            (reserve, reserve_account(program_id, lending_market, mint)),
+            (borrower, obligation_account(program_id, lending_market, Some(reserve))),
+            (depositor, obligation_account(program_id, lending_market, None)),
+            (Pubkey::new_unique(), obligation_account(program_id, Pubkey::new_unique(), Some(reserve))),
+        ];
+        Market { program_id, lending_market, reserve, mint, borrower, depositor, accounts }
+    }
+
+    #[tokio::test]
+    async fn discovers_market_obligations_from_account_dump() {
+        let market = market();
+        let dir = std::env::temp_dir().join(format!("market-dump-{}", market.lending_market));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_account_dump(dir.join("market.json"), &market.accounts).unwrap();
+        let source = FixtureAccountSource::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut obligations = get_all_obligations_for_market(&source, &market.program_id, &market.lending_market)
+            .await
+            .unwrap();
+        obligations.sort_by_key(|(_, address)| *address);
+        let mut expected = vec![market.borrower, market.depositor];
+        expected.sort();
+        assert_eq!(obligations.iter().map(|(_, address)| *address).collect::<Vec<_>>(), expected);
+
+        let with_borrows = filter_obligations_with_borrows(obligations);
+        assert_eq!(with_borrows.len(), 1);
+        assert_eq!(with_borrows[0].1, market.borrower);
+        assert_eq!(with_borrows[0].0.get_reserve_addresses(), vec![market.reserve]);
+    }
+
+    #[tokio::test]
+    async fn program_scan_fallback_finds_the_same_obligations() {
+        let market = market();
+        let source = InMemoryAccountSource::from_accounts(market.accounts.clone());
+
+        let filtered = get_all_obligations_for_market(&source, &market.program_id, &market.lending_market)
+            .await
+            .unwrap();
+        let scanned = get_all_program_accounts(&source, &market.program_id, &market.lending_market)
+            .await
+            .unwrap();
+
+        let addresses = |obligations: &[(Obligation, Pubkey)]| {
+            let mut addresses: Vec<Pubkey> = obligations.iter().map(|(_, address)| *address).collect();
+            addresses.sort();
+            addresses
+        };
+        assert_eq!(addresses(&filtered), addresses(&scanned));
+    }
+
+    #[tokio::test]
+    async fn maps_reserves_to_mints() {
+        let market = market();
+        let source = InMemoryAccountSource::from_accounts(market.accounts.clone());
+        let foreign = Pubkey::new_unique();
+        source.insert(foreign, reserve_account(Pubkey::new_unique(), market.lending_market, Pubkey::new_unique()));
+        let missing = Pubkey::new_unique();
+
+        let mapping = create_reserve_to_mint_mapping(&source, &market.program_id, vec![market.reserve, foreign, missing])
+            .await
+            .unwrap();
+        assert_eq!(mapping[&market.reserve], market.mint.to_string());
+        assert_eq!(mapping[&foreign], "INVALID");
+        assert_eq!(mapping[&missing], "NOT_FOUND");
+
+        let reserves = get_reserves(&source, &market.program_id, &[market.reserve, foreign, missing])
+            .await
+            .unwrap();
+        assert_eq!(reserves.len(), 1);
+        assert_eq!(reserves[&market.reserve].mint(), market.mint);
+    }
+
+    #[tokio::test]
+    async fn fetches_reserves_across_concurrent_batches() {
+        let program_id = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let reserves: Vec<(Pubkey, Pubkey)> = (0..MULTIPLE_ACCOUNTS_BATCH_SIZE * 2 + 50)
+            .map(|_| (Pubkey::new_unique(), Pubkey::new_unique()))
+            .collect();
+        let source = InMemoryAccountSource::from_accounts(
+            reserves
+                .iter()
+                .map(|(reserve, mint)| (*reserve, reserve_account(program_id, lending_market, *mint))),
+        );
+
+        let addresses: Vec<Pubkey> = reserves.iter().map(|(reserve, _)| *reserve).collect();
+        let mapping = create_reserve_to_mint_mapping(&source, &program_id, addresses.clone())
+            .await
+            .unwrap();
+        assert_eq!(mapping.len(), reserves.len());
+        assert!(reserves.iter().all(|(reserve, mint)| mapping[reserve] == mint.to_string()));
+        assert_eq!(get_reserves(&source, &program_id, &addresses).await.unwrap().len(), reserves.len());
+    }
+
+    #[tokio::test]
+    async fn dumps_borrowing_obligations_and_their_reserves() {
+        let market = market();
+        let source = InMemoryAccountSource::from_accounts(market.accounts.clone());
+        let dir = std::env::temp_dir().join(format!("market-capture-{}", market.lending_market));
+
+        let counts = dump_market(&source, &market.program_id, &market.lending_market, &dir).await.unwrap();
+        let dumped = FixtureAccountSource::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(counts, (1, 1));
+        assert_eq!(dumped.len(), 2);
+        let obligations = get_all_obligations_for_market(&dumped, &market.program_id, &market.lending_market)
+            .await
+            .unwrap();
+        assert_eq!(obligations.iter().map(|(_, address)| *address).collect::<Vec<_>>(), vec![market.borrower]);
+        assert_eq!(get_reserves(&dumped, &market.program_id, &[market.reserve]).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn discovers_the_main_market_from_its_committed_dump() {
+        let program_id = Pubkey::from_str("KLend2g3cP87fffoy8q1mQqGKjrxjC8boSyAYavgmjD").unwrap();
+        let lending_market = Pubkey::from_str("7u3HeHxYDLhnCoErrtycNokbQYbWGzLs6JSDqGAv5PfF").unwrap();
+        let source = FixtureAccountSource::load(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/main_market")).unwrap();
+        assert_eq!(source.len(), 4);
+
+        let obligations = filter_obligations_with_borrows(
+            get_all_obligations_for_market(&source, &program_id, &lending_market).await.unwrap(),
+        );
+        assert_eq!(obligations.len(), 2);
+        let reserve_addresses: Vec<Pubkey> = obligations
+            .iter()
+            .flat_map(|(obligation, _)| obligation.get_reserve_addresses())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        assert_eq!(reserve_addresses.len(), 2);
+
+        let mapping = create_reserve_to_mint_mapping(&source, &program_id, reserve_addresses.clone())
+            .await
+            .unwrap();
+        let mut mints: Vec<&str> = mapping.values().map(String::as_str).collect();
+        mints.sort();
+        assert_eq!(mints, ["EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "So11111111111111111111111111111111111111112"]);
+
+        let reserves = get_reserves(&source, &program_id, &reserve_addresses).await.unwrap();
+        let decimals: Vec<u32> = reserve_addresses.iter().map(|address| reserves[address].decimals()).collect();
+        assert!(decimals.contains(&6) && decimals.contains(&9));
+        assert!(reserves.values().all(|reserve| reserve.lending_market == lending_market));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::health::{ObligationHealth, PositionValue};
+use crate::price_listener::get_realized_volatility;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct VolatilityConfig {
+    pub windows: Vec<Duration>,
+    pub ranking_window: Duration,
+    pub horizon: Duration,
+}
+
+impl Default for VolatilityConfig {
+    fn default() -> Self {
+        VolatilityConfig {
+            windows: vec![
+                Duration::from_secs(300),
+                Duration::from_secs(900),
+                Duration::from_secs(3600),
+            ],
+            ranking_window: Duration::from_secs(900),
+            horizon: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VolatilityEstimate {
+    pub window_secs: u64,
+    pub hourly_volatility: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ObligationRisk {
+    pub hourly_volatility: f64,
+    pub log_distance_to_liquidation: f64,
+    pub sigmas_to_liquidation: Option<f64>,
+    pub crossing_probability: f64,
+}
+
+pub fn mint_volatility(mint: &str, config: &VolatilityConfig) -> Vec<VolatilityEstimate> {
+    config
+        .windows
+        .iter()
+        .map(|window| VolatilityEstimate {
+            window_secs: window.as_secs(),
+            hourly_volatility: get_realized_volatility(mint, *window),
+        })
+        .collect()
+}
+
+pub fn obligation_risk(health: &ObligationHealth, config: &VolatilityConfig) -> ObligationRisk {
+    obligation_risk_with(health, config.horizon, |mint| {
+        get_realized_volatility(mint, config.ranking_window)
+    })
+}
+
+// The health factor moves with the collateral/debt price ratio, so its volatility is taken as
+// the value-weighted deposit and borrow volatilities combined as if independent. The chance of
+// touching the threshold within the horizon uses the reflection principle: 2 * (1 - N(d / σ√T)).
+pub fn obligation_risk_with<F>(health: &ObligationHealth, horizon: Duration, volatility_of: F) -> ObligationRisk
+where
+    F: Fn(&str) -> Option<f64>,
+{
+    if health.bf_adjusted_debt_value <= 0.0 || health.deposited_value <= 0.0 {
+        return ObligationRisk::default();
+    }
+
+    let deposit_volatility = weighted_volatility(&health.deposits, &volatility_of);
+    let borrow_volatility = weighted_volatility(&health.borrows, &volatility_of);
+    let hourly_volatility = (deposit_volatility.powi(2) + borrow_volatility.powi(2)).sqrt();
+    let log_distance = health.health_factor.max(f64::MIN_POSITIVE).ln().max(0.0);
+
+    let horizon_volatility = hourly_volatility * (horizon.as_secs_f64() / 3600.0).sqrt();
+    let sigmas_to_liquidation = (horizon_volatility > 0.0).then(|| log_distance / horizon_volatility);
+    let crossing_probability = match sigmas_to_liquidation {
+        _ if log_distance <= 0.0 => 1.0,
+        Some(sigmas) => (2.0 * (1.0 - normal_cdf(sigmas))).clamp(0.0, 1.0),
+        None => 0.0,
+    };
+
+    ObligationRisk {
+        hourly_volatility,
+        log_distance_to_liquidation: log_distance,
+        sigmas_to_liquidation,
+        crossing_probability,
+    }
+}
+
+fn weighted_volatility<F>(positions: &[PositionValue], volatility_of: &F) -> f64
+where
+    F: Fn(&str) -> Option<f64>,
+{
+    let total: f64 = positions.iter().map(|position| position.value).sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+
+    positions
+        .iter()
+        .map(|position| position.value * volatility_of(&position.token_mint).unwrap_or(0.0))
+        .sum::<f64>()
+        / total
+}
+
+// Abramowitz & Stegun 7.1.26, accurate to ~1e-7.
+fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * z);
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn position(mint: &str, value: f64) -> PositionValue {
+        PositionValue {
+            token_mint: mint.to_string(),
+            ..test_fixtures::position(Pubkey::new_unique(), value, 6, 1.0)
+        }
+    }
+
+    fn health(health_factor: f64) -> ObligationHealth {
+        ObligationHealth {
+            deposited_value: 1000.0,
+            borrowed_value: 500.0,
+            bf_adjusted_debt_value: 500.0,
+            health_factor,
+            deposits: vec![position("sol", 600.0), position("msol", 400.0)],
+            borrows: vec![position("usdc", 500.0)],
+            ..Default::default()
+        }
+    }
+
+    fn volatility_of(mint: &str) -> Option<f64> {
+        match mint {
+            "sol" => Some(0.5),
+            "msol" => Some(0.0),
+            "usdc" => Some(0.4),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn combines_weighted_volatilities_into_a_crossing_probability() {
+        let risk = obligation_risk_with(&health(1.6), Duration::from_secs(900), volatility_of);
+
+        // Deposits: 0.6 * 0.5 + 0.4 * 0 = 0.3; borrows 0.4; combined sqrt(0.09 + 0.16) = 0.5.
+        assert!((risk.hourly_volatility - 0.5).abs() < 1e-12);
+        assert!((risk.log_distance_to_liquidation - 1.6f64.ln()).abs() < 1e-12);
+
+        // A 15 minute horizon scales to 0.5 * sqrt(0.25) = 0.25: ln(1.6) / 0.25 ≈ 1.880 sigmas,
+        // and 2 * (1 - N(1.880)) ≈ 0.0601.
+        let sigmas = risk.sigmas_to_liquidation.unwrap();
+        assert!((sigmas - 1.6f64.ln() / 0.25).abs() < 1e-12);
+        assert!((risk.crossing_probability - 0.0601).abs() < 1e-4);
+    }
+
+    #[test]
+    fn edge_cases_pin_the_probability() {
+        let horizon = Duration::from_secs(600);
+
+        // Already at or below the threshold.
+        let risk = obligation_risk_with(&health(0.9), horizon, volatility_of);
+        assert_eq!(risk.log_distance_to_liquidation, 0.0);
+        assert_eq!(risk.crossing_probability, 1.0);
+
+        // No volatility estimate for any position.
+        let risk = obligation_risk_with(&health(1.6), horizon, |_| None);
+        assert_eq!(risk.hourly_volatility, 0.0);
+        assert_eq!(risk.sigmas_to_liquidation, None);
+        assert_eq!(risk.crossing_probability, 0.0);
+
+        // No debt.
+        let mut debt_free = health(f64::MAX);
+        debt_free.bf_adjusted_debt_value = 0.0;
+        let risk = obligation_risk_with(&debt_free, horizon, volatility_of);
+        assert_eq!(risk.crossing_probability, 0.0);
+        assert_eq!(risk.sigmas_to_liquidation, None);
+    }
+
+    #[test]
+    fn normal_cdf_matches_reference_values() {
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-7);
+        assert!((normal_cdf(1.0) - 0.841_344_7).abs() < 1e-6);
+        assert!((normal_cdf(-1.96) - 0.024_997_9).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::health::ObligationHealth;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct WatchlistConfig {
+    pub hot_health_factor: f64,
+    pub warm_health_factor: f64,
+    pub hot_interval: Duration,
+    pub warm_interval: Duration,
+    pub cold_interval: Duration,
+}
+
+impl Default for WatchlistConfig {
+    fn default() -> Self {
+        WatchlistConfig {
+            hot_health_factor: 1.05,
+            warm_health_factor: 1.25,
+            hot_interval: Duration::from_secs(5),
+            warm_interval: Duration::from_secs(20),
+            cold_interval: Duration::from_secs(120),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchTier {
+    Hot,
+    Warm,
+    Cold,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchlistEntryInfo {
+    pub obligation_address: String,
+    pub rank: usize,
+    pub tier: WatchTier,
+    pub health_factor: f64,
+    pub distance_to_liquidation: f64,
+    pub seconds_since_evaluation: u64,
+}
+
+// Orders obligations by distance to liquidation, closest first, with the address as tiebreak.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DistanceKey(f64, Pubkey);
+
+impl Eq for DistanceKey {}
+
+impl PartialOrd for DistanceKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DistanceKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then_with(|| self.1.cmp(&other.1))
+    }
+}
+
+struct WatchEntry {
+    distance: f64,
+    health_factor: f64,
+    tier: WatchTier,
+    last_evaluated: Instant,
+    next_due: Instant,
+}
+
+pub struct Watchlist {
+    config: WatchlistConfig,
+    entries: HashMap<Pubkey, WatchEntry>,
+    by_distance: BTreeSet<DistanceKey>,
+    schedule: BTreeSet<(Instant, Pubkey)>,
+}
+
+impl Watchlist {
+    pub fn new(config: WatchlistConfig) -> Self {
+        Watchlist {
+            config,
+            entries: HashMap::new(),
+            by_distance: BTreeSet::new(),
+            schedule: BTreeSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn tier_of(&self, health_factor: f64) -> WatchTier {
+        if health_factor <= self.config.hot_health_factor {
+            WatchTier::Hot
+        } else if health_factor <= self.config.warm_health_factor {
+            WatchTier::Warm
+        } else {
+            WatchTier::Cold
+        }
+    }
+
+    pub fn update(&mut self, address: Pubkey, health: &ObligationHealth, now: Instant) {
+        self.remove(&address);
+
+        let health_factor = health.health_factor;
+        let tier = self.tier_of(health_factor);
+        let interval = match tier {
+            WatchTier::Hot => self.config.hot_interval,
+            WatchTier::Warm => self.config.warm_interval,
+            WatchTier::Cold => self.config.cold_interval,
+        };
+        let distance = health_factor - 1.0;
+        let next_due = now + interval;
+
+        self.by_distance.insert(DistanceKey(distance, address));
+        self.schedule.insert((next_due, address));
+        self.entries.insert(
+            address,
+            WatchEntry {
+                distance,
+                health_factor,
+                tier,
+                last_evaluated: now,
+                next_due,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, address: &Pubkey) {
+        let entry = match self.entries.remove(address) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        self.by_distance.remove(&DistanceKey(entry.distance, *address));
+        self.schedule.remove(&(entry.next_due, *address));
+    }
+
+    // Near-threshold obligations are re-evaluated on every price tick that affects them.
+    pub fn is_hot(&self, address: &Pubkey) -> bool {
+        self.entries
+            .get(address)
+            .is_some_and(|entry| entry.tier == WatchTier::Hot)
+    }
+
+    pub fn due(&self, now: Instant) -> Vec<Pubkey> {
+        self.schedule
+            .iter()
+            .take_while(|(due, _)| *due <= now)
+            .map(|(_, address)| *address)
+            .collect()
+    }
+
+    pub fn entry_info(&self, address: &Pubkey, now: Instant) -> Option<WatchlistEntryInfo> {
+        let entry = self.entries.get(address)?;
+        Some(WatchlistEntryInfo {
+            obligation_address: address.to_string(),
+            rank: 0,
+            tier: entry.tier,
+            health_factor: entry.health_factor,
+            distance_to_liquidation: entry.distance,
+            seconds_since_evaluation: now.duration_since(entry.last_evaluated).as_secs(),
+        })
+    }
+
+    pub fn ranked(&self, now: Instant) -> Vec<WatchlistEntryInfo> {
+        self.by_distance
+            .iter()
+            .enumerate()
+            .filter_map(|(rank, key)| {
+                let entry = self.entries.get(&key.1)?;
+                Some(WatchlistEntryInfo {
+                    obligation_address: key.1.to_string(),
+                    rank: rank + 1,
+                    tier: entry.tier,
+                    health_factor: entry.health_factor,
+                    distance_to_liquidation: entry.distance,
+                    seconds_since_evaluation: now.duration_since(entry.last_evaluated).as_secs(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn tier_counts(&self) -> (usize, usize, usize) {
+        self.entries
+            .values()
+            .fold((0, 0, 0), |(hot, warm, cold), entry| match entry.tier {
+                WatchTier::Hot => (hot + 1, warm, cold),
+                WatchTier::Warm => (hot, warm + 1, cold),
+                WatchTier::Cold => (hot, warm, cold + 1),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(health_factor: f64) -> ObligationHealth {
+        ObligationHealth { health_factor, ..Default::default() }
+    }
+
+    fn addresses(ranked: &[WatchlistEntryInfo]) -> Vec<String> {
+        ranked.iter().map(|entry| entry.obligation_address.clone()).collect()
+    }
+
+    #[test]
+    fn tiers_include_their_upper_threshold() {
+        let watchlist = Watchlist::new(WatchlistConfig::default());
+
+        assert_eq!(watchlist.tier_of(0.9), WatchTier::Hot);
+        assert_eq!(watchlist.tier_of(1.05), WatchTier::Hot);
+        assert_eq!(watchlist.tier_of(1.06), WatchTier::Warm);
+        assert_eq!(watchlist.tier_of(1.25), WatchTier::Warm);
+        assert_eq!(watchlist.tier_of(1.2501), WatchTier::Cold);
+        assert_eq!(watchlist.tier_of(f64::INFINITY), WatchTier::Cold);
+    }
+
+    #[test]
+    fn ranks_closest_to_liquidation_first() {
+        let mut watchlist = Watchlist::new(WatchlistConfig::default());
+        let now = Instant::now();
+        let (safe, near, underwater) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+
+        watchlist.update(safe, &health(2.0), now);
+        watchlist.update(near, &health(1.1), now);
+        watchlist.update(underwater, &health(0.95), now);
+
+        let ranked = watchlist.ranked(now);
+        assert_eq!(addresses(&ranked), vec![underwater.to_string(), near.to_string(), safe.to_string()]);
+        assert_eq!(ranked.iter().map(|entry| entry.rank).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!((ranked[0].distance_to_liquidation + 0.05).abs() < 1e-12);
+        assert_eq!(
+            ranked.iter().map(|entry| entry.tier).collect::<Vec<_>>(),
+            vec![WatchTier::Hot, WatchTier::Warm, WatchTier::Cold]
+        );
+        assert_eq!(watchlist.tier_counts(), (1, 1, 1));
+
+        // Equal distances fall back to the address.
+        let (first, second) = {
+            let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+            if a < b { (a, b) } else { (b, a) }
+        };
+        let mut watchlist = Watchlist::new(WatchlistConfig::default());
+        watchlist.update(second, &health(1.5), now);
+        watchlist.update(first, &health(1.5), now);
+        assert_eq!(addresses(&watchlist.ranked(now)), vec![first.to_string(), second.to_string()]);
+    }
+
+    #[test]
+    fn update_replaces_the_previous_entry() {
+        let mut watchlist = Watchlist::new(WatchlistConfig::default());
+        let now = Instant::now();
+        let (moving, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        watchlist.update(moving, &health(2.0), now);
+        watchlist.update(other, &health(1.5), now);
+        assert!(!watchlist.is_hot(&moving));
+
+        let later = now + Duration::from_secs(30);
+        watchlist.update(moving, &health(1.01), later);
+
+        assert_eq!(watchlist.len(), 2);
+        assert!(watchlist.is_hot(&moving));
+        assert_eq!(addresses(&watchlist.ranked(later)), vec![moving.to_string(), other.to_string()]);
+        assert_eq!(watchlist.tier_counts(), (1, 0, 1));
+
+        let info = watchlist.entry_info(&moving, later + Duration::from_secs(3)).unwrap();
+        assert_eq!(info.tier, WatchTier::Hot);
+        assert_eq!(info.seconds_since_evaluation, 3);
+
+        // The stale cold schedule is gone: only the hot one is due after 5s.
+        assert_eq!(watchlist.due(later + Duration::from_secs(5)), vec![moving]);
+    }
+
+    #[test]
+    fn remove_evicts_from_ranking_and_schedule() {
+        let mut watchlist = Watchlist::new(WatchlistConfig::default());
+        let now = Instant::now();
+        let (kept, removed) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        watchlist.update(kept, &health(1.2), now);
+        watchlist.update(removed, &health(1.0), now);
+        watchlist.remove(&removed);
+        watchlist.remove(&Pubkey::new_unique());
+
+        assert_eq!(watchlist.len(), 1);
+        assert!(!watchlist.is_hot(&removed));
+        assert!(watchlist.entry_info(&removed, now).is_none());
+        assert_eq!(addresses(&watchlist.ranked(now)), vec![kept.to_string()]);
+        assert_eq!(watchlist.due(now + Duration::from_secs(3600)), vec![kept]);
+    }
+
+    #[test]
+    fn schedules_each_tier_at_its_interval() {
+        let mut watchlist = Watchlist::new(WatchlistConfig::default());
+        let now = Instant::now();
+        let (hot, warm, cold) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+
+        watchlist.update(cold, &health(3.0), now);
+        watchlist.update(warm, &health(1.2), now);
+        watchlist.update(hot, &health(1.0), now);
+
+        assert!(watchlist.due(now + Duration::from_secs(4)).is_empty());
+        assert_eq!(watchlist.due(now + Duration::from_secs(5)), vec![hot]);
+        assert_eq!(watchlist.due(now + Duration::from_secs(20)), vec![hot, warm]);
+        assert_eq!(watchlist.due(now + Duration::from_secs(120)), vec![hot, warm, cold]);
+    }
+}